@@ -2,10 +2,11 @@ use alloc::alloc::{GlobalAlloc, Layout};
 use core::ptr::null_mut;
 use x86_64::{
     structures::paging::{
-        Mapper, Page, PageTableFlags, Size4KiB, mapper::MapToError, FrameAllocator,
+        FrameAllocator, FrameDeallocator, Mapper, PageTableFlags, PhysFrame, Size4KiB, mapper::MapToError,
     },
     VirtAddr,
 };
+use crate::memory::{self, ContiguousFrameAllocator, KernelHeapInfo};
 
 pub mod bump;
 pub mod linked_list;
@@ -25,6 +26,28 @@ impl<A> Locked<A> {
     pub fn lock(&self) -> spin::MutexGuard<A> {
         self.inner.lock()
     }
+
+    /// Consumes the lock and returns the allocator, e.g. to inspect it at the end of a test.
+    pub fn into_inner(self) -> A {
+        self.inner.into_inner()
+    }
+
+    /// Returns whether the lock is held, for debugging.
+    ///
+    /// The `spin::Mutex` of this version has no such method, so this briefly takes
+    /// the lock if it is free.
+    pub fn is_locked(&self) -> bool {
+        self.inner.try_lock().is_none()
+    }
+}
+
+/// Gives access to the other methods of the mutex, e.g. `try_lock` or `ALLOCATOR.force_unlock()`.
+impl<A> core::ops::Deref for Locked<A> {
+    type Target = spin::Mutex<A>;
+
+    fn deref(&self) -> &spin::Mutex<A> {
+        &self.inner
+    }
 }
 
 /// Align the address `addr` upwards to alignment `align`.
@@ -54,7 +77,7 @@ unsafe impl GlobalAlloc for Dummy {
 use fixed_size_block::FixedSizeBlockAllocator;
 
 #[global_allocator]
-static ALLOCATOR: Locked<FixedSizeBlockAllocator> = Locked::new(FixedSizeBlockAllocator::new());
+static ALLOCATOR: FixedSizeBlockAllocator = FixedSizeBlockAllocator::new();
 
 pub const HEAP_SIZE: usize = 1024 * 1024; // 1 MB
 pub const HEAP_START: usize = 0x4444_4444_0000;
@@ -62,41 +85,120 @@ pub const HEAP_START: usize = 0x4444_4444_0000;
 pub fn init_heap(
     mapper: &mut impl Mapper<Size4KiB>,             
     // `mapper` is responsible for mapping virtual pages to physical frames.
-    frame_allocator: &mut impl FrameAllocator<Size4KiB>, 
+    frame_allocator: &mut (impl ContiguousFrameAllocator + FrameDeallocator<Size4KiB>),
     // `frame_allocator` is responsible for allocating physical frames for pages.
-) -> Result<(), MapToError<Size4KiB>> { // Returns `Ok(())` on success, or a `MapToError` if there is an error.
-    
-    // Define the range of pages that will represent the heap.
-    let page_range = {
-        // Start of the heap in virtual memory.
-        let heap_start = VirtAddr::new(HEAP_START as u64);        
-        // End of the heap in virtual memory, calculated by adding the heap size.
-        let heap_end = heap_start + HEAP_SIZE - 1u64;        
-        // Find the first page that contains the start of the heap.
-        let heap_start_page = Page::containing_address(heap_start);        
-        // Find the last page that contains the end of the heap.
-        let heap_end_page = Page::containing_address(heap_end);        
-        // Create a range of pages from the start page to the end page, inclusive.
-        Page::range_inclusive(heap_start_page, heap_end_page)
-    };
-
-    // For each page in the calculated range, allocate a frame and map it to that page.
-    for page in page_range {
-        // Allocate a physical frame for this page. If no frame is available, return an error.
-        let frame = frame_allocator.allocate_frame().ok_or(MapToError::FrameAllocationFailed)?;
-        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;        
-        // Map the virtual `page` to the allocated physical `frame` with the specified flags.
-        // The `unsafe` keyword is required because `map_to` may involve modifying memory directly.
-        unsafe {
-            let _ = mapper.map_to(page, frame, flags, frame_allocator)?;
-        };
-    }
+) -> Result<KernelHeapInfo, MapToError<Size4KiB>> { // Returns where the heap was mapped, or a `MapToError` if there is an error.
+    init_heap_at(VirtAddr::new(HEAP_START as u64), HEAP_SIZE, mapper, frame_allocator)
+}
+
+/// Like `init_heap`, but maps the heap at `start` with `size` bytes instead of
+/// `HEAP_START` and `HEAP_SIZE`, e.g. at an address reported by the bootloader.
+///
+/// All pages touched by the range are mapped, so neither `start` nor `size` has to be page aligned.
+pub fn init_heap_at(
+    start: VirtAddr,
+    size: usize,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut (impl ContiguousFrameAllocator + FrameDeallocator<Size4KiB>),
+) -> Result<KernelHeapInfo, MapToError<Size4KiB>> {
+    // Map all pages of the heap to newly allocated frames.
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    let end = start + size as u64;
+    let page_count = ((end.align_up(4096u64) - start.align_down(4096u64)) / 4096) as usize;
+    let mut frame_allocator = CountingFrameAllocator { inner: frame_allocator, count: 0 };
+    memory::map_page_range(start, page_count, flags, mapper, &mut frame_allocator)?;
 
     // Initialize the linked list allocator with the start and size of the heap.
     unsafe {
-        ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE);
+        ALLOCATOR.init(start.as_u64() as usize, size);
+    }
+
+    // Return the mapped range if all pages were successfully mapped.
+    Ok(KernelHeapInfo {
+        start,
+        size,
+        page_count,
+        phys_frames_used: frame_allocator.count,
+    })
+}
+
+// counts the frames taken from the wrapped allocator
+struct CountingFrameAllocator<'a, A> {
+    inner: &'a mut A,
+    count: usize,
+}
+
+unsafe impl<A: FrameAllocator<Size4KiB>> FrameAllocator<Size4KiB> for CountingFrameAllocator<'_, A> {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        let frame = self.inner.allocate_frame()?;
+        self.count += 1;
+        Some(frame)
+    }
+}
+
+impl<A: FrameDeallocator<Size4KiB>> FrameDeallocator<Size4KiB> for CountingFrameAllocator<'_, A> {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        self.inner.deallocate_frame(frame);
+        self.count -= 1;
+    }
+}
+
+impl<A: ContiguousFrameAllocator> ContiguousFrameAllocator for CountingFrameAllocator<'_, A> {
+    fn allocate_contiguous(&mut self, count: usize) -> Option<PhysFrame> {
+        let frame = self.inner.allocate_contiguous(count)?;
+        self.count += count;
+        Some(frame)
+    }
+}
+
+/// Returns excess free blocks of the kernel heap to its fallback allocator, see
+/// `FixedSizeBlockAllocator::gc`. Returns the number of bytes that were returned.
+pub fn gc() -> usize {
+    ALLOCATOR.gc()
+}
+
+/// Called when an allocation fails. Prints the failing layout and the heap usage
+/// to the serial port and the screen, then halts.
+pub fn oom_handler(layout: Layout) -> ! {
+    use crate::{println, serial_println};
+
+    serial_println!("OUT OF MEMORY: allocation of {} bytes with alignment {} failed",
+        layout.size(), layout.align());
+    println!("OUT OF MEMORY: allocation of {} bytes with alignment {} failed",
+        layout.size(), layout.align());
+    match ALLOCATOR.stats() {
+        Some(stats) => {
+            serial_println!("heap: {:?}", stats);
+            println!("heap: {:?}", stats);
+        }
+        None => {
+            serial_println!("heap: stats unavailable, allocator is locked");
+        }
     }
+    crate::hlt_loop();
+}
+
+// called by `alloc::alloc::handle_alloc_error`, e.g. when `Box::new` gets a null pointer
+#[alloc_error_handler]
+fn alloc_error(layout: Layout) -> ! {
+    oom_handler(layout)
+}
+
+/// Entry point for code outside of Rust that runs out of memory.
+///
+/// This function is unsafe because `layout` must point to the `Layout` of the failed allocation.
+#[no_mangle]
+pub unsafe extern "C" fn __rust_oom(layout: *const u8) -> ! {
+    oom_handler(*(layout as *const Layout))
+}
 
-    // Return success if all pages were successfully mapped.
-    Ok(())
+#[test_case]
+fn test_locked_is_locked() {
+    let locked = Locked::new(0u8);
+    assert!(!locked.is_locked());
+    // `spin::Mutex::lock` through `Deref`
+    let guard = (*locked).lock();
+    assert!(locked.is_locked());
+    drop(guard);
+    assert!(!locked.is_locked());
 }
@@ -3,6 +3,9 @@ pub struct BumpAllocator {
     heap_end: usize,
     next: usize,
     allocations: usize,
+    // the highest values `allocations` and `next - heap_start` have reached
+    peak_allocations: usize,
+    peak_bytes: usize,
 }
 /**
  * bump allocator is a simple allocator that
@@ -21,6 +24,8 @@ impl BumpAllocator {
             heap_end: 0,
             next: 0,
             allocations: 0,
+            peak_allocations: 0,
+            peak_bytes: 0,
         }
     }
 
@@ -33,6 +38,22 @@ impl BumpAllocator {
         self.heap_end = heap_start + heap_size;
         self.next = heap_start;
     }
+
+    /// Returns the number of allocations that have not been freed yet.
+    pub fn allocation_count(&self) -> usize {
+        self.allocations
+    }
+
+    /// Returns the highest number of live allocations so far.
+    pub fn peak_allocation_count(&self) -> usize {
+        self.peak_allocations
+    }
+
+    /// Returns the highest number of heap bytes in use so far,
+    /// including the padding added for alignment.
+    pub fn peak_bytes(&self) -> usize {
+        self.peak_bytes
+    }
 }
 
 use alloc::alloc::{GlobalAlloc, Layout};
@@ -55,6 +76,8 @@ unsafe impl GlobalAlloc for Locked<BumpAllocator> {
         } else {
             allocator.next = alloc_end;
             allocator.allocations += 1;
+            allocator.peak_allocations = allocator.peak_allocations.max(allocator.allocations);
+            allocator.peak_bytes = allocator.peak_bytes.max(alloc_end - allocator.heap_start);
             alloc_start as *mut u8
         }
     }
@@ -67,4 +90,22 @@ unsafe impl GlobalAlloc for Locked<BumpAllocator> {
             allocator.next = allocator.heap_start;
         }
     }
-}
\ No newline at end of file
+}
+
+#[test_case]
+fn test_locked_into_inner() {
+    let mut heap = [0u8; 64];
+    let allocator = Locked::new(BumpAllocator::new());
+    unsafe {
+        allocator.lock().init(heap.as_mut_ptr() as usize, heap.len());
+        let layout = Layout::from_size_align(16, 8).unwrap();
+        let ptr = allocator.alloc(layout);
+        assert!(!ptr.is_null());
+        allocator.alloc(layout);
+        allocator.dealloc(ptr, layout);
+    }
+
+    let allocator = allocator.into_inner();
+    assert_eq!(allocator.allocation_count(), 1);
+    assert_eq!(allocator.peak_allocation_count(), 2);
+}
@@ -1,7 +1,18 @@
 // Define the ListNode struct, which represents a node in a linked list of free memory blocks.
 // Each node points to the next free block of memory (if available).
 struct ListNode {
-    next: Option<&'static mut ListNode>, // 'next' stores the address of the next free block.
+    next: *mut ListNode, // 'next' stores the address of the next free block, or null.
+}
+
+// Free blocks are linked into lock-free stacks, one per block size.
+unsafe impl StackNode for ListNode {
+    fn next(&self) -> *mut ListNode {
+        self.next
+    }
+
+    fn set_next(&mut self, next: *mut ListNode) {
+        self.next = next;
+    }
 }
 
 // Define the block sizes to use, which will determine the allocator's granularity.
@@ -9,29 +20,52 @@ struct ListNode {
 // also be powers of two for proper memory management.
 const BLOCK_SIZES: &[usize] = &[8, 16, 32, 64, 128, 256, 512, 1024, 2048];
 
+// `gc` keeps this many free blocks of every size and returns the rest to the fallback allocator.
+const GC_KEEP_FREE_BLOCKS: usize = 32;
+
 // The FixedSizeBlockAllocator structure manages memory in fixed-size blocks.
 // It uses multiple linked lists to store free blocks of various sizes, as defined in BLOCK_SIZES.
 // For memory that doesn't fit these sizes, it uses a fallback allocator.
 pub struct FixedSizeBlockAllocator {
-    // Array of linked list heads for each block size, storing the available free blocks.
-    list_heads: [Option<&'static mut ListNode>; BLOCK_SIZES.len()],
+    // Array of lock-free stacks for each block size, storing the available free blocks.
+    // They are shared without a lock, so freeing and reusing blocks never spins.
+    list_heads: [AtomicStack<ListNode>; BLOCK_SIZES.len()],
+    // Number of blocks on each stack. Updated separately from the stack, so it can be
+    // briefly off while blocks are pushed or popped concurrently.
+    free_counts: [AtomicUsize; BLOCK_SIZES.len()],
     // Fallback allocator for cases when a specific block size is unavailable.
-    fallback_allocator: linked_list_allocator::Heap, 
+    // Only this part needs the lock.
+    fallback_allocator: Locked<linked_list_allocator::Heap>,
     // This allocator doesn't merge adjacent free blocks, but it can still manage memory 
     // outside the fixed-size blocks.
 }
 
+/// Heap usage as reported by `FixedSizeBlockAllocator::stats`, in bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HeapStats {
+    pub size: usize,
+    pub used: usize,
+    pub free: usize,
+}
+
 use alloc::alloc::{GlobalAlloc, Layout};
 use core::{ptr::{self, NonNull}, mem};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use crate::sync::{AtomicStack, StackNode};
+use super::Locked;
 
 impl FixedSizeBlockAllocator {
     /// Creates an empty FixedSizeBlockAllocator with no initialized blocks.
-    /// Sets all the linked list heads to `None`, meaning no blocks are currently free.
+    /// Sets all the free lists to empty, meaning no blocks are currently free.
     pub const fn new() -> Self {
-        const EMPTY: Option<&'static mut ListNode> = None;
+        #[allow(clippy::declare_interior_mutable_const)]
+        const EMPTY: AtomicStack<ListNode> = AtomicStack::new();
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicUsize = AtomicUsize::new(0);
         FixedSizeBlockAllocator {
             list_heads: [EMPTY; BLOCK_SIZES.len()], // Initialize the free lists as empty
-            fallback_allocator: linked_list_allocator::Heap::empty(),
+            free_counts: [ZERO; BLOCK_SIZES.len()],
+            fallback_allocator: Locked::new(linked_list_allocator::Heap::empty()),
         }
     }
 
@@ -39,14 +73,63 @@ impl FixedSizeBlockAllocator {
     /// 
     /// This function is `unsafe` because the caller must guarantee that the specified
     /// memory region is valid, unused, and exclusive to the allocator.
-    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
-        self.fallback_allocator.init(heap_start, heap_size); // Initialize fallback allocator
+    pub unsafe fn init(&self, heap_start: usize, heap_size: usize) {
+        self.fallback_allocator.lock().init(heap_start, heap_size); // Initialize fallback allocator
     }
     
+    /// Returns the usage of the fallback allocator, which owns the whole heap.
+    /// Blocks on the free lists count as used.
+    /// - Returns `None` if the fallback allocator is locked, e.g. when called while allocating.
+    pub fn stats(&self) -> Option<HeapStats> {
+        let heap = self.fallback_allocator.try_lock()?;
+        Some(HeapStats {
+            size: heap.size(),
+            used: heap.used(),
+            free: heap.free(),
+        })
+    }
+
+    /// Returns the number of free blocks of the size class `size_class`, an index into the block sizes.
+    pub fn free_blocks(&self, size_class: usize) -> usize {
+        self.free_counts[size_class].load(Ordering::Relaxed)
+    }
+
+    /// Returns at most `min_free` free blocks of the size class `size_class` to the
+    /// fallback allocator, so that they can be merged into larger free regions.
+    /// Returns the number of blocks that were returned.
+    pub fn reclaim_fallback_blocks(&self, size_class: usize, min_free: usize) -> usize {
+        let block_size = BLOCK_SIZES[size_class];
+        let layout = Layout::from_size_align(block_size, block_size).unwrap();
+        let mut reclaimed = 0;
+        while reclaimed < min_free {
+            let node = self.list_heads[size_class].pop();
+            let node = match NonNull::new(node) {
+                Some(node) => node,
+                None => break,
+            };
+            self.free_counts[size_class].fetch_sub(1, Ordering::Relaxed);
+            // the block was allocated from the fallback allocator with this layout
+            unsafe { self.fallback_allocator.lock().deallocate(node.cast(), layout) };
+            reclaimed += 1;
+        }
+        reclaimed
+    }
+
+    /// Returns the free blocks above a fixed number per size class to the fallback
+    /// allocator. Returns the number of bytes that were returned.
+    pub fn gc(&self) -> usize {
+        (0..BLOCK_SIZES.len())
+            .map(|size_class| {
+                let excess = self.free_blocks(size_class).saturating_sub(GC_KEEP_FREE_BLOCKS);
+                self.reclaim_fallback_blocks(size_class, excess) * BLOCK_SIZES[size_class]
+            })
+            .sum()
+    }
+
     /// Uses the fallback allocator to allocate memory when no suitable fixed-size block is available.
-    fn fallback_alloc(&mut self, layout: Layout) -> *mut u8 {
+    fn fallback_alloc(&self, layout: Layout) -> *mut u8 {
         // Try to allocate memory using the fallback allocator and return a pointer to the allocated memory.
-        match self.fallback_allocator.allocate_first_fit(layout) {
+        match self.fallback_allocator.lock().allocate_first_fit(layout) {
             Ok(ptr) => ptr.as_ptr(), // Successful allocation returns the memory pointer
             Err(_) => ptr::null_mut(), // Allocation failure returns a null pointer
         }
@@ -61,63 +144,75 @@ fn list_index(layout: &Layout) -> Option<usize> {
     BLOCK_SIZES.iter().position(|&s| s >= required_block_size) // Find smallest suitable block size
 }
 
-use super::Locked;
-
 // Implement the GlobalAlloc trait, which allows the allocator to be used as a global allocator.
-unsafe impl GlobalAlloc for Locked<FixedSizeBlockAllocator> {
+// No outer lock is needed: the free lists are lock-free and the fallback allocator locks itself.
+unsafe impl GlobalAlloc for FixedSizeBlockAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let mut allocator = self.lock(); // Lock the allocator for thread-safe access
-
         // Determine the appropriate list index for the requested allocation size and alignment.
         match list_index(&layout) {
             Some(index) => {
-                // Try to take a free block from the list head of the appropriate size.
-                match allocator.list_heads[index].take() {
-                    Some(node) => {
-                        // If a free block is available, set the head of the list to the next node.
-                        allocator.list_heads[index] = node.next.take();
-                        node as *mut ListNode as *mut u8 // Return the address of the allocated block
-                    }
-                    None => {
-                        // No block of the required size is available; allocate a new block.
-                        let block_size = BLOCK_SIZES[index];
-                        let block_align = block_size;
-                        let layout = Layout::from_size_align(block_size, block_align).unwrap();
-                        allocator.fallback_alloc(layout) // Use fallback allocator
-                    }
+                // Try to take a free block from the stack of the appropriate size.
+                let node = self.list_heads[index].pop();
+                if !node.is_null() {
+                    self.free_counts[index].fetch_sub(1, Ordering::Relaxed);
+                    node as *mut u8 // Return the address of the allocated block
+                } else {
+                    // No block of the required size is available; allocate a new block.
+                    let block_size = BLOCK_SIZES[index];
+                    let block_align = block_size;
+                    let layout = Layout::from_size_align(block_size, block_align).unwrap();
+                    self.fallback_alloc(layout) // Use fallback allocator
                 }
             }
-            None => allocator.fallback_alloc(layout), // Fallback for unsupported block sizes
+            None => self.fallback_alloc(layout), // Fallback for unsupported block sizes
         }
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        let mut allocator = self.lock(); // Lock the allocator for thread-safe access
-
         // Determine the appropriate list index for the block being deallocated.
         match list_index(&layout) {
             Some(index) => {
-                // Create a new ListNode to represent the freed block.
-                let new_node = ListNode {
-                    next: allocator.list_heads[index].take(),
-                };
-
                 // Validate the block's size and alignment before adding it back to the list.
                 assert!(mem::size_of::<ListNode>() <= BLOCK_SIZES[index]);
                 assert!(mem::align_of::<ListNode>() <= BLOCK_SIZES[index]);
 
-                // Write the new node to the memory location being freed.
+                // Write a new node to the memory location being freed
+                // and push it on the stack for this block size.
                 let new_node_ptr = ptr as *mut ListNode;
-                new_node_ptr.write(new_node);
-
-                // Set the list head for this block size to the newly freed node.
-                allocator.list_heads[index] = Some(&mut *new_node_ptr);
+                new_node_ptr.write(ListNode { next: ptr::null_mut() });
+                self.list_heads[index].push(new_node_ptr);
+                self.free_counts[index].fetch_add(1, Ordering::Relaxed);
             }
             None => {
                 // For blocks not matching our fixed sizes, use the fallback allocator's deallocation.
                 let ptr = NonNull::new(ptr).unwrap();
-                allocator.fallback_allocator.deallocate(ptr, layout)
+                self.fallback_allocator.lock().deallocate(ptr, layout)
             }
         }
     }
 }
+
+#[test_case]
+fn test_reclaim_fallback_blocks() {
+    #[repr(align(4096))]
+    struct Heap([u8; 4096]);
+    static mut HEAP: Heap = Heap([0; 4096]);
+
+    let allocator = FixedSizeBlockAllocator::new();
+    unsafe { allocator.init(ptr::addr_of_mut!(HEAP.0) as usize, 4096) };
+    let layout = Layout::from_size_align(64, 8).unwrap();
+    let blocks: [*mut u8; 8] = core::array::from_fn(|_| unsafe { allocator.alloc(layout) });
+    for &block in &blocks {
+        assert!(!block.is_null());
+        unsafe { allocator.dealloc(block, layout) };
+    }
+    let size_class = list_index(&layout).unwrap();
+    assert_eq!(allocator.free_blocks(size_class), 8);
+    let used = allocator.stats().unwrap().used;
+
+    assert_eq!(allocator.reclaim_fallback_blocks(size_class, 3), 3);
+    assert_eq!(allocator.free_blocks(size_class), 5);
+    assert_eq!(allocator.stats().unwrap().used, used - 3 * 64);
+    // below the number of blocks `gc` keeps
+    assert_eq!(allocator.gc(), 0);
+}
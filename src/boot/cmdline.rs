@@ -0,0 +1,71 @@
+use alloc::collections::BTreeMap;
+use conquer_once::spin::OnceCell;
+use spin::Mutex;
+
+/// The raw kernel command line, set by the boot code before `kernel_main` runs.
+pub static CMDLINE_STR: Mutex<&'static str> = Mutex::new("");
+
+static CMDLINE: OnceCell<CommandLine<'static>> = OnceCell::uninit();
+
+/// A parsed kernel command line made of `key=value` options and bare flags.
+#[derive(Debug, Clone, Default)]
+pub struct CommandLine<'a> {
+    args: BTreeMap<&'a str, Option<&'a str>>,
+}
+
+impl<'a> CommandLine<'a> {
+    /// Returns the value of a `key=value` option.
+    ///
+    /// Returns `None` for bare flags and for options that are not present.
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.args.get(key).copied().flatten()
+    }
+
+    /// Returns whether the flag or option `flag` is present.
+    pub fn has(&self, flag: &str) -> bool {
+        self.args.contains_key(flag)
+    }
+}
+
+/// Parses a space separated command line.
+///
+/// Later occurrences of the same key override earlier ones.
+pub fn parse(s: &str) -> CommandLine<'_> {
+    let mut args = BTreeMap::new();
+    for token in s.split(' ').filter(|token| !token.is_empty()) {
+        match token.split_once('=') {
+            Some((key, value)) => args.insert(key, Some(value)),
+            None => args.insert(token, None),
+        };
+    }
+    CommandLine { args }
+}
+
+/// Sets the raw command line that `init` will parse.
+pub fn set(cmdline: &'static str) {
+    *CMDLINE_STR.lock() = cmdline;
+}
+
+/// Parses `CMDLINE_STR` into the global command line.
+///
+/// Must be called once after the heap has been initialized.
+pub fn init() {
+    let cmdline = *CMDLINE_STR.lock();
+    CMDLINE.try_init_once(|| parse(cmdline))
+        .expect("cmdline::init should only be called once");
+}
+
+/// Returns the global command line, or `None` before `init` was called.
+pub fn get() -> Option<&'static CommandLine<'static>> {
+    CMDLINE.try_get().ok()
+}
+
+#[test_case]
+fn test_parse_options_and_flags() {
+    let cmdline = parse("heap=2M  quiet log=debug");
+    assert_eq!(cmdline.get("heap"), Some("2M"));
+    assert_eq!(cmdline.get("log"), Some("debug"));
+    assert!(cmdline.has("quiet"));
+    assert_eq!(cmdline.get("quiet"), None);
+    assert!(!cmdline.has("verbose"));
+}
@@ -0,0 +1,114 @@
+//! Inspection of the memory map passed by the bootloader.
+
+use bootloader::{bootinfo::{MemoryMap, MemoryRegionType}, BootInfo};
+use crate::allocator::{HEAP_SIZE, HEAP_START};
+use crate::memory::{self, BootInfoError};
+use crate::serial_println;
+
+// usable memory required besides the heap, for page tables, stacks and the frame allocator
+const MIN_FREE_MEMORY: u64 = 1024 * 1024;
+
+/// Checks everything `memory::validate_boot_info` checks, and that the kernel heap
+/// fits: the physical memory mapping must not overlap it, and there must be at least
+/// `HEAP_SIZE` plus 1 MB of usable memory.
+///
+/// Call this first, before the boot information is used.
+pub fn verify_memory_map(boot_info: &BootInfo) -> Result<(), BootInfoError> {
+    let offset = boot_info.physical_memory_offset;
+    if offset == 0 {
+        return Err(BootInfoError::ZeroPhysicalMemoryOffset);
+    }
+    memory::validate_boot_info(boot_info)?;
+
+    let heap_start = HEAP_START as u64;
+    let heap_end = heap_start + HEAP_SIZE as u64;
+    let mut usable = 0;
+    for region in boot_info.memory_map.iter() {
+        let start = region.range.start_addr();
+        let end = region.range.end_addr();
+        // where the region is accessible through the physical memory mapping
+        let overlaps = match (offset.checked_add(start), offset.checked_add(end)) {
+            (Some(virt_start), Some(virt_end)) => virt_start < heap_end && heap_start < virt_end,
+            // a higher-half mapping that reaches the end of the address space, far above the heap
+            _ => false,
+        };
+        if overlaps {
+            return Err(BootInfoError::HeapOverlapsRegion(start));
+        }
+        if region.region_type == MemoryRegionType::Usable {
+            usable += end - start;
+        }
+    }
+
+    let required = HEAP_SIZE as u64 + MIN_FREE_MEMORY;
+    if usable < required {
+        return Err(BootInfoError::NotEnoughMemory { usable, required });
+    }
+    Ok(())
+}
+
+/// Prints every region of the memory map to the serial port as
+/// `[0x...-0x...] TYPE (size)`, followed by the total usable memory.
+pub fn print_memory_map(memory_map: &MemoryMap) {
+    let mut usable = 0;
+    serial_println!("physical memory map:");
+    for region in memory_map.iter() {
+        let start = region.range.start_addr();
+        let end = region.range.end_addr();
+        serial_println!("[{:#012x}-{:#012x}] {:?} ({})", start, end, region.region_type, Size(end - start));
+        if region.region_type == MemoryRegionType::Usable {
+            usable += end - start;
+        }
+    }
+    serial_println!("usable memory: {}", Size(usable));
+}
+
+// formats a byte count in MB, or in KB for regions below 1 MB
+struct Size(u64);
+
+impl core::fmt::Display for Size {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        if self.0 >= 1024 * 1024 {
+            write!(f, "{} MB", self.0 / (1024 * 1024))
+        } else {
+            write!(f, "{} KB", self.0 / 1024)
+        }
+    }
+}
+
+#[test_case]
+fn test_size_display() {
+    use crate::collections::string::KernelString;
+    use core::fmt::Write;
+
+    let mut s = KernelString::<16>::new();
+    write!(s, "{}", Size(3 * 1024 * 1024 + 5)).unwrap();
+    assert_eq!(s.as_str(), "3 MB");
+
+    s.clear();
+    write!(s, "{}", Size(640 * 1024)).unwrap();
+    assert_eq!(s.as_str(), "640 KB");
+}
+
+#[test_case]
+fn test_verify_memory_map() {
+    use bootloader::bootinfo::{FrameRange, MemoryRegion};
+
+    fn boot_info(offset: u64, usable_end: u64) -> BootInfo {
+        let mut map = MemoryMap::new();
+        map.add_region(MemoryRegion { range: FrameRange::new(0, 0x1000), region_type: MemoryRegionType::Reserved });
+        map.add_region(MemoryRegion { range: FrameRange::new(0x1000, usable_end), region_type: MemoryRegionType::Usable });
+        BootInfo::new(map, None, 0, offset)
+    }
+
+    let offset = 1 << 40;
+    assert_eq!(verify_memory_map(&boot_info(offset, 0x40_0000)), Ok(()));
+    assert_eq!(verify_memory_map(&boot_info(0, 0x40_0000)), Err(BootInfoError::ZeroPhysicalMemoryOffset));
+    assert_eq!(verify_memory_map(&boot_info(offset, 0x10_1000)),
+        Err(BootInfoError::NotEnoughMemory { usable: 0x10_0000, required: 0x20_0000 }));
+    // the mapping of the usable region would cover the heap
+    let below_heap = (HEAP_START as u64 - 0x1000) & !((1 << 30) - 1);
+    assert_eq!(verify_memory_map(&boot_info(below_heap, 0x40_0000_0000)), Err(BootInfoError::HeapOverlapsRegion(0x1000)));
+    // a higher-half offset whose mapping of the region runs past the end of the address space
+    assert_eq!(verify_memory_map(&boot_info(0xffff_ffff_c000_0000, 0x40_0000_0000)), Ok(()));
+}
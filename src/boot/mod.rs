@@ -0,0 +1,7 @@
+//! Boot protocol support beyond the `bootloader` crate.
+
+pub mod multiboot2;
+pub mod cmdline;
+pub mod memory_map;
+
+pub use memory_map::{print_memory_map, verify_memory_map};
@@ -0,0 +1,187 @@
+use core::{ptr, slice, str};
+
+/// The value a Multiboot2 compliant bootloader passes in `eax`.
+pub const BOOTLOADER_MAGIC: u32 = 0x36D7_6289;
+
+// tag types we understand, see the Multiboot2 specification section 3.6
+const TAG_END: u32 = 0;
+const TAG_COMMAND_LINE: u32 = 1;
+const TAG_MEMORY_MAP: u32 = 6;
+const TAG_FRAMEBUFFER: u32 = 8;
+
+// the framebuffer tag up to and including its `bpp` field
+const FRAMEBUFFER_TAG_MIN_SIZE: usize = 29;
+
+/// Errors returned by `parse`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mb2Error {
+    /// The bootloader did not pass the Multiboot2 magic value.
+    InvalidMagic(u32),
+    /// The boot information pointer is null or not 8-byte aligned.
+    InvalidAddress,
+}
+
+/// The boot information structure the bootloader left in memory.
+#[derive(Debug, Clone, Copy)]
+pub struct Multiboot2Info {
+    start: *const u8,
+    total_size: usize,
+}
+
+/// Validates the magic value and wraps the boot information at `addr`.
+///
+/// This function is unsafe because the caller must guarantee that `addr` points
+/// to the boot information structure passed by the bootloader and that this memory
+/// stays mapped and unmodified for the rest of the kernel's lifetime.
+pub unsafe fn parse(magic: u32, addr: *const u8) -> Result<Multiboot2Info, Mb2Error> {
+    if magic != BOOTLOADER_MAGIC {
+        return Err(Mb2Error::InvalidMagic(magic));
+    }
+    if addr.is_null() || addr as usize % 8 != 0 {
+        return Err(Mb2Error::InvalidAddress);
+    }
+    // the structure starts with its total size followed by a reserved field
+    let total_size = read_u32(addr, 0) as usize;
+    Ok(Multiboot2Info { start: addr, total_size })
+}
+
+/// A region of physical memory reported by the bootloader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mb2MemoryArea {
+    pub base_addr: u64,
+    pub length: u64,
+    pub area_type: Mb2MemoryAreaType,
+}
+
+/// The kind of a memory area, unknown types are treated as reserved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mb2MemoryAreaType {
+    Available,
+    Reserved,
+    AcpiReclaimable,
+    Nvs,
+    BadMemory,
+}
+
+impl From<u32> for Mb2MemoryAreaType {
+    fn from(value: u32) -> Self {
+        match value {
+            1 => Mb2MemoryAreaType::Available,
+            3 => Mb2MemoryAreaType::AcpiReclaimable,
+            4 => Mb2MemoryAreaType::Nvs,
+            5 => Mb2MemoryAreaType::BadMemory,
+            _ => Mb2MemoryAreaType::Reserved,
+        }
+    }
+}
+
+/// The framebuffer the bootloader set up for us.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mb2Framebuffer {
+    pub addr: u64,
+    pub pitch: u32,
+    pub width: u32,
+    pub height: u32,
+    pub bpp: u8,
+}
+
+/// A single tag in the boot information, pointing at its payload.
+#[derive(Debug, Clone, Copy)]
+struct Tag {
+    typ: u32,
+    size: usize,
+    start: *const u8,
+}
+
+impl Multiboot2Info {
+    /// Returns an iterator over all tags up to the end tag.
+    fn tags(&self) -> impl Iterator<Item = Tag> {
+        let start = self.start;
+        let end = self.total_size;
+        // tags start after the 8 byte fixed header
+        let mut offset = 8;
+
+        core::iter::from_fn(move || {
+            if offset + 8 > end {
+                return None;
+            }
+            let tag_start = unsafe { start.add(offset) };
+            let typ = unsafe { read_u32(tag_start, 0) };
+            let size = unsafe { read_u32(tag_start, 4) } as usize;
+            if typ == TAG_END || size < 8 {
+                return None;
+            }
+            // every tag is padded to an 8 byte boundary
+            offset += (size + 7) & !7;
+            Some(Tag { typ, size, start: tag_start })
+        })
+    }
+
+    fn find_tag(&self, typ: u32) -> Option<Tag> {
+        self.tags().find(|tag| tag.typ == typ)
+    }
+
+    /// Returns an iterator over the memory areas in the memory map tag.
+    pub fn memory_map(&self) -> impl Iterator<Item = Mb2MemoryArea> {
+        let (start, entry_size, count) = match self.find_tag(TAG_MEMORY_MAP) {
+            Some(tag) => {
+                let entry_size = unsafe { read_u32(tag.start, 8) } as usize;
+                // the entries follow the 16 byte tag header
+                // a malformed tag smaller than its header has no entries
+                let count = tag
+                    .size
+                    .checked_sub(16)
+                    .and_then(|len| len.checked_div(entry_size))
+                    .unwrap_or(0);
+                (unsafe { tag.start.add(16) }, entry_size, count)
+            }
+            None => (ptr::null(), 0, 0),
+        };
+
+        (0..count).map(move |i| {
+            let entry = unsafe { start.add(i * entry_size) };
+            unsafe {
+                Mb2MemoryArea {
+                    base_addr: read_u64(entry, 0),
+                    length: read_u64(entry, 8),
+                    area_type: read_u32(entry, 16).into(),
+                }
+            }
+        })
+    }
+
+    /// Returns the kernel command line passed by the bootloader, if any.
+    pub fn command_line(&self) -> Option<&'static str> {
+        let tag = self.find_tag(TAG_COMMAND_LINE)?;
+        // the string is zero terminated and follows the 8 byte tag header
+        let bytes = unsafe { slice::from_raw_parts(tag.start.add(8), tag.size - 8) };
+        let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        str::from_utf8(&bytes[..len]).ok()
+    }
+
+    /// Returns the framebuffer information, if the bootloader set up a framebuffer.
+    pub fn framebuffer_info(&self) -> Option<Mb2Framebuffer> {
+        let tag = self.find_tag(TAG_FRAMEBUFFER)?;
+        // a truncated tag does not contain all fields up to `bpp` at offset 28
+        if tag.size < FRAMEBUFFER_TAG_MIN_SIZE {
+            return None;
+        }
+        unsafe {
+            Some(Mb2Framebuffer {
+                addr: read_u64(tag.start, 8),
+                pitch: read_u32(tag.start, 16),
+                width: read_u32(tag.start, 20),
+                height: read_u32(tag.start, 24),
+                bpp: *tag.start.add(28),
+            })
+        }
+    }
+}
+
+unsafe fn read_u32(base: *const u8, offset: usize) -> u32 {
+    ptr::read_unaligned(base.add(offset) as *const u32)
+}
+
+unsafe fn read_u64(base: *const u8, offset: usize) -> u64 {
+    ptr::read_unaligned(base.add(offset) as *const u64)
+}
@@ -0,0 +1,160 @@
+use core::{mem::MaybeUninit, ops::Index, ptr};
+
+/// A vector with an inline capacity of `N` elements that never allocates.
+pub struct FixedVec<T, const N: usize> {
+    data: [MaybeUninit<T>; N],
+    len: usize,
+}
+
+impl<T, const N: usize> FixedVec<T, N> {
+    pub fn new() -> Self {
+        FixedVec {
+            // an array of `MaybeUninit` does not need to be initialized
+            data: unsafe { MaybeUninit::uninit().assume_init() },
+            len: 0,
+        }
+    }
+
+    /// Appends `value`, or hands it back if the vector is full.
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if self.len == N {
+            return Err(value);
+        }
+        self.data[self.len].write(value);
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        // the element at `len` was initialized and is no longer part of the vector
+        Some(unsafe { self.data[self.len].assume_init_read() })
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&T> {
+        self.as_slice().get(idx)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.as_slice().iter()
+    }
+
+    /// Drops all elements.
+    pub fn clear(&mut self) {
+        let len = self.len;
+        // reset the length first so a panicking destructor cannot cause a double drop
+        self.len = 0;
+        unsafe {
+            ptr::drop_in_place(ptr::slice_from_raw_parts_mut(self.data.as_mut_ptr() as *mut T, len));
+        }
+    }
+
+    pub fn as_slice(&self) -> &[T] {
+        // the first `len` elements are initialized
+        unsafe { core::slice::from_raw_parts(self.data.as_ptr() as *const T, self.len) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+}
+
+impl<T, const N: usize> Default for FixedVec<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Drop for FixedVec<T, N> {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+impl<T, const N: usize> Index<usize> for FixedVec<T, N> {
+    type Output = T;
+
+    fn index(&self, idx: usize) -> &T {
+        &self.as_slice()[idx]
+    }
+}
+
+impl<'a, T, const N: usize> IntoIterator for &'a FixedVec<T, N> {
+    type Item = &'a T;
+    type IntoIter = core::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.as_slice().iter()
+    }
+}
+
+impl<T, const N: usize> IntoIterator for FixedVec<T, N> {
+    type Item = T;
+    type IntoIter = IntoIter<T, N>;
+
+    fn into_iter(self) -> IntoIter<T, N> {
+        IntoIter { vec: self, next: 0 }
+    }
+}
+
+/// An iterator that moves the elements out of a `FixedVec`.
+pub struct IntoIter<T, const N: usize> {
+    vec: FixedVec<T, N>,
+    next: usize,
+}
+
+impl<T, const N: usize> Iterator for IntoIter<T, N> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        if self.next == self.vec.len {
+            return None;
+        }
+        let value = unsafe { self.vec.data[self.next].assume_init_read() };
+        self.next += 1;
+        Some(value)
+    }
+}
+
+impl<T, const N: usize> Drop for IntoIter<T, N> {
+    fn drop(&mut self) {
+        // drop the elements that were not yielded, the vector's own drop must not see any
+        let remaining = ptr::slice_from_raw_parts_mut(
+            unsafe { (self.vec.data.as_mut_ptr() as *mut T).add(self.next) },
+            self.vec.len - self.next,
+        );
+        self.vec.len = 0;
+        unsafe { ptr::drop_in_place(remaining) };
+    }
+}
+
+#[test_case]
+fn test_fixed_vec() {
+    use alloc::rc::Rc;
+
+    let counter = Rc::new(());
+    let mut vec = FixedVec::<Rc<()>, 2>::new();
+    assert!(vec.push(counter.clone()).is_ok());
+    assert!(vec.push(counter.clone()).is_ok());
+    assert!(vec.push(counter.clone()).is_err());
+    assert_eq!(vec.len(), 2);
+    assert_eq!(Rc::strong_count(&vec[1]), 3);
+
+    let first = vec.pop();
+    assert!(first.is_some());
+    drop(first);
+    drop(vec);
+    // dropping the vector dropped the remaining element
+    assert_eq!(Rc::strong_count(&counter), 1);
+}
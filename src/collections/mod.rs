@@ -0,0 +1,5 @@
+//! Data structures that complement the ones in `alloc`.
+
+pub mod string;
+pub mod ring;
+pub mod fixed_vec;
@@ -0,0 +1,81 @@
+use alloc::vec::Vec;
+
+/// A single-threaded FIFO queue with a fixed capacity.
+///
+/// Unlike `crossbeam_queue::ArrayQueue` it allows looking at the queued elements.
+pub struct RingBuffer<T> {
+    // slots outside of `head..head + len` (wrapping) are `None`
+    buf: Vec<Option<T>>,
+    head: usize,
+    len: usize,
+}
+
+impl<T> RingBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        let mut buf = Vec::with_capacity(capacity);
+        buf.resize_with(capacity, || None);
+        RingBuffer { buf, head: 0, len: 0 }
+    }
+
+    /// Appends `value` to the back, returns false and drops `value` if the buffer is full.
+    pub fn push_back(&mut self, value: T) -> bool {
+        if self.is_full() {
+            return false;
+        }
+        let tail = (self.head + self.len) % self.capacity();
+        self.buf[tail] = Some(value);
+        self.len += 1;
+        true
+    }
+
+    pub fn pop_front(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let value = self.buf[self.head].take();
+        self.head = (self.head + 1) % self.capacity();
+        self.len -= 1;
+        value
+    }
+
+    /// Returns the element at the front without removing it.
+    pub fn peek(&self) -> Option<&T> {
+        self.iter().next()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == self.capacity()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Iterates over the elements from front to back.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        (0..self.len).filter_map(move |i| self.buf[(self.head + i) % self.capacity()].as_ref())
+    }
+}
+
+#[test_case]
+fn test_ring_buffer_wraps() {
+    let mut ring = RingBuffer::new(3);
+    assert!(ring.push_back(1));
+    assert!(ring.push_back(2));
+    assert!(ring.push_back(3));
+    assert!(!ring.push_back(4));
+
+    assert_eq!(ring.pop_front(), Some(1));
+    assert!(ring.push_back(4));
+    assert_eq!(ring.peek(), Some(&2));
+    assert!(ring.iter().copied().eq([2, 3, 4]));
+    assert_eq!(ring.len(), 3);
+}
@@ -0,0 +1,134 @@
+use core::{fmt, ops::Deref};
+
+/// A string stored inline in a fixed-size buffer of `N` bytes.
+///
+/// Useful for formatting short strings where allocating is not an option,
+/// e.g. in interrupt handlers.
+#[derive(Clone, Copy)]
+pub struct KernelString<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> KernelString<N> {
+    pub const fn new() -> Self {
+        KernelString { buf: [0; N], len: 0 }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only whole `&str`s are ever copied into the buffer
+        unsafe { core::str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Returns a writer that appends as much as fits instead of rejecting the whole chunk,
+    /// e.g. for messages that are better cut off than lost.
+    pub fn truncating(&mut self) -> TruncatingWriter<'_, N> {
+        TruncatingWriter(self)
+    }
+}
+
+/// Appends to a `KernelString`, see `KernelString::truncating`.
+pub struct TruncatingWriter<'a, const N: usize>(&'a mut KernelString<N>);
+
+impl<const N: usize> fmt::Write for TruncatingWriter<'_, N> {
+    /// Appends the longest prefix of `s` that fits, and returns an error if that is not all of it.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let string = &mut *self.0;
+        let mut fits = s.len().min(N - string.len);
+        // only whole characters are copied, so the string stays valid UTF-8
+        while !s.is_char_boundary(fits) {
+            fits -= 1;
+        }
+        string.buf[string.len..string.len + fits].copy_from_slice(&s.as_bytes()[..fits]);
+        string.len += fits;
+        if fits == s.len() {
+            Ok(())
+        } else {
+            Err(fmt::Error)
+        }
+    }
+}
+
+impl<const N: usize> Default for KernelString<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> fmt::Write for KernelString<N> {
+    /// Appends `s`, or returns an error and leaves the string unchanged if it does not fit.
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > N {
+            return Err(fmt::Error);
+        }
+        self.buf[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+impl<const N: usize> Deref for KernelString<N> {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl<const N: usize> AsRef<[u8]> for KernelString<N> {
+    fn as_ref(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl<const N: usize> fmt::Display for KernelString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl<const N: usize> fmt::Debug for KernelString<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+#[test_case]
+fn test_kernel_string_write() {
+    use core::fmt::Write;
+
+    let mut s = KernelString::<8>::new();
+    write!(&mut s, "IRQ{}", 14).unwrap();
+    assert_eq!(&*s, "IRQ14");
+    // does not fit, so the content stays the same
+    assert!(write!(&mut s, "long").is_err());
+    assert_eq!(s.as_str(), "IRQ14");
+}
+
+#[test_case]
+fn test_kernel_string_truncating() {
+    use core::fmt::Write;
+
+    let mut s = KernelString::<8>::new();
+    assert!(write!(s.truncating(), "IRQ{}", 14).is_ok());
+    // the prefix that fits is kept, the two byte `ä` does not fit after "lo"
+    assert!(write!(s.truncating(), "loäng").is_err());
+    assert_eq!(s.as_str(), "IRQ14lo");
+}
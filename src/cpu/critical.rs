@@ -0,0 +1,75 @@
+use core::arch::asm;
+use core::marker::PhantomData;
+
+// the interrupt flag in RFLAGS
+const RFLAGS_IF: u64 = 1 << 9;
+
+/// Returns the current value of the RFLAGS register.
+pub fn save_flags() -> u64 {
+    let flags: u64;
+    unsafe {
+        asm!("pushfq", "pop {}", out(reg) flags, options(nomem, preserves_flags));
+    }
+    flags
+}
+
+/// Writes `flags` back to RFLAGS, which re-enables interrupts if they were enabled
+/// when the flags were saved.
+///
+/// `flags` should come from `save_flags`, other bits in RFLAGS change the behaviour
+/// of the CPU, e.g. the trap flag enables single stepping.
+pub fn restore_flags(flags: u64) {
+    unsafe {
+        asm!("push {}", "popfq", in(reg) flags, options(nomem));
+    }
+}
+
+/// A section of code that runs with interrupts disabled.
+pub struct CriticalSection;
+
+/// Restores the interrupt state from before `CriticalSection::enter` when dropped.
+///
+/// Not `Send`, the flags must be restored on the CPU they were saved on.
+pub struct CriticalSectionGuard {
+    flags: u64,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl CriticalSection {
+    /// Saves RFLAGS and disables interrupts until the returned guard is dropped.
+    ///
+    /// Critical sections can be nested, interrupts are only enabled again when the
+    /// outermost guard is dropped.
+    pub fn enter() -> CriticalSectionGuard {
+        let flags = save_flags();
+        x86_64::instructions::interrupts::disable();
+        CriticalSectionGuard { flags, _not_send: PhantomData }
+    }
+}
+
+impl CriticalSectionGuard {
+    /// Returns whether interrupts were enabled when the section was entered.
+    pub fn interrupts_were_enabled(&self) -> bool {
+        self.flags & RFLAGS_IF != 0
+    }
+}
+
+impl Drop for CriticalSectionGuard {
+    fn drop(&mut self) {
+        restore_flags(self.flags);
+    }
+}
+
+#[test_case]
+fn test_nested_critical_sections() {
+    use x86_64::instructions::interrupts;
+
+    let outer = CriticalSection::enter();
+    let inner = CriticalSection::enter();
+    assert!(!interrupts::are_enabled());
+    drop(inner);
+    // the outer section is still active
+    assert!(!interrupts::are_enabled());
+    drop(outer);
+    assert!(interrupts::are_enabled());
+}
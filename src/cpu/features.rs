@@ -0,0 +1,127 @@
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use super::cpuid;
+
+// the features detected by `init`, plus the ones added with `KernelFeatures::enable`
+static KERNEL_FEATURES: AtomicU64 = AtomicU64::new(0);
+
+/// Hardware features that decide which kernel subsystems are initialized.
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub struct KernelFeatures(u64);
+
+impl KernelFeatures {
+    /// An x87 floating point unit is present.
+    pub const HAS_FPU: KernelFeatures = KernelFeatures(1 << 0);
+    /// The CPU has a local APIC.
+    pub const HAS_APIC: KernelFeatures = KernelFeatures(1 << 1);
+    /// The local APIC supports x2APIC mode.
+    pub const HAS_X2APIC: KernelFeatures = KernelFeatures(1 << 2);
+    /// 2 MiB pages can be used.
+    pub const HAS_HUGE_PAGES_2MB: KernelFeatures = KernelFeatures(1 << 3);
+    /// 1 GiB pages can be used.
+    pub const HAS_HUGE_PAGES_1GB: KernelFeatures = KernelFeatures(1 << 4);
+    /// The ACPI tables were found, set once `drivers::acpi_poweroff::init` succeeded.
+    pub const HAS_ACPI: KernelFeatures = KernelFeatures(1 << 5);
+    /// The package has more than one logical processor.
+    pub const HAS_SMP: KernelFeatures = KernelFeatures(1 << 6);
+    /// The machine check exception can be enabled.
+    pub const HAS_MCE: KernelFeatures = KernelFeatures(1 << 7);
+    /// The machine check MSRs are present.
+    pub const HAS_MCA: KernelFeatures = KernelFeatures(1 << 8);
+
+    pub const fn empty() -> KernelFeatures {
+        KernelFeatures(0)
+    }
+
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+
+    /// Returns whether all features of `other` are present.
+    pub const fn has(self, other: KernelFeatures) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn union(self, other: KernelFeatures) -> KernelFeatures {
+        KernelFeatures(self.0 | other.0)
+    }
+
+    /// Returns the features of the running kernel.
+    pub fn get() -> KernelFeatures {
+        KernelFeatures(KERNEL_FEATURES.load(Ordering::Acquire))
+    }
+
+    /// Adds features that are not detected with CPUID, e.g. `HAS_ACPI`.
+    pub fn enable(features: KernelFeatures) {
+        KERNEL_FEATURES.fetch_or(features.0, Ordering::AcqRel);
+    }
+
+    /// Reads the features of the current CPU with CPUID.
+    pub fn detect() -> KernelFeatures {
+        let mut features = KernelFeatures::empty();
+        let mut set = |feature: KernelFeatures, present: bool| {
+            if present {
+                features = features.union(feature);
+            }
+        };
+
+        // leaf 1: EDX bit 0 FPU, 3 PSE, 7 MCE, 9 APIC, 14 MCA, 28 HTT, ECX bit 21 x2APIC
+        let leaf_1 = cpuid(1, 0);
+        set(Self::HAS_FPU, leaf_1.edx & (1 << 0) != 0);
+        set(Self::HAS_HUGE_PAGES_2MB, leaf_1.edx & (1 << 3) != 0);
+        set(Self::HAS_MCE, leaf_1.edx & (1 << 7) != 0);
+        set(Self::HAS_APIC, leaf_1.edx & (1 << 9) != 0);
+        set(Self::HAS_MCA, leaf_1.edx & (1 << 14) != 0);
+        set(Self::HAS_X2APIC, leaf_1.ecx & (1 << 21) != 0);
+        // EBX[23:16] is the number of logical processors if HTT is set
+        set(Self::HAS_SMP, leaf_1.edx & (1 << 28) != 0 && (leaf_1.ebx >> 16) & 0xff > 1);
+
+        // extended leaf 0x8000_0001: EDX bit 26 1 GiB pages
+        if cpuid(0x8000_0000, 0).eax >= 0x8000_0001 {
+            set(Self::HAS_HUGE_PAGES_1GB, cpuid(0x8000_0001, 0).edx & (1 << 26) != 0);
+        }
+        features
+    }
+}
+
+/// Detects the CPU features, must be called before the features are checked.
+pub fn init() {
+    KernelFeatures::enable(KernelFeatures::detect());
+}
+
+impl core::ops::BitOr for KernelFeatures {
+    type Output = KernelFeatures;
+
+    fn bitor(self, other: KernelFeatures) -> KernelFeatures {
+        self.union(other)
+    }
+}
+
+impl fmt::Debug for KernelFeatures {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        const NAMES: [(KernelFeatures, &str); 9] = [
+            (KernelFeatures::HAS_FPU, "HAS_FPU"),
+            (KernelFeatures::HAS_APIC, "HAS_APIC"),
+            (KernelFeatures::HAS_X2APIC, "HAS_X2APIC"),
+            (KernelFeatures::HAS_HUGE_PAGES_2MB, "HAS_HUGE_PAGES_2MB"),
+            (KernelFeatures::HAS_HUGE_PAGES_1GB, "HAS_HUGE_PAGES_1GB"),
+            (KernelFeatures::HAS_ACPI, "HAS_ACPI"),
+            (KernelFeatures::HAS_SMP, "HAS_SMP"),
+            (KernelFeatures::HAS_MCE, "HAS_MCE"),
+            (KernelFeatures::HAS_MCA, "HAS_MCA"),
+        ];
+        f.debug_set()
+            .entries(NAMES.iter().filter(|(feature, _)| self.has(*feature)).map(|(_, name)| name))
+            .finish()
+    }
+}
+
+#[test_case]
+fn test_kernel_features() {
+    let features = KernelFeatures::HAS_FPU | KernelFeatures::HAS_APIC;
+    assert!(features.has(KernelFeatures::HAS_APIC));
+    assert!(!features.has(KernelFeatures::HAS_APIC | KernelFeatures::HAS_SMP));
+    // every x86_64 CPU has an FPU, and `init` ran before the tests
+    assert!(KernelFeatures::detect().has(KernelFeatures::HAS_FPU));
+    assert!(KernelFeatures::get().has(KernelFeatures::detect()));
+}
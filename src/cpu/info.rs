@@ -0,0 +1,106 @@
+use core::fmt::Write;
+use crate::collections::string::KernelString;
+use super::cpuid;
+
+/// Identification of the CPU as reported by CPUID.
+#[derive(Debug, Clone, Copy)]
+pub struct CpuInfo {
+    /// e.g. `GenuineIntel` or `AuthenticAMD`
+    pub vendor: KernelString<12>,
+    /// e.g. `Intel(R) Core(TM) i7-8700 CPU @ 3.20GHz`, empty if the CPU has no brand string
+    pub brand: KernelString<48>,
+    /// The display family, including the extended family.
+    pub family: u8,
+    /// The display model, including the extended model.
+    pub model: u8,
+    pub stepping: u8,
+    /// The number of logical processors in the package.
+    pub logical_cores: u8,
+    /// The number of cores in the package.
+    pub physical_cores: u8,
+}
+
+// writes the bytes of the registers as text, CPUID strings are plain ASCII
+fn push_registers<const N: usize>(s: &mut KernelString<N>, registers: &[u32]) {
+    for byte in registers.iter().flat_map(|reg| reg.to_le_bytes()) {
+        if byte.is_ascii() && byte != 0 {
+            let _ = s.write_char(byte as char);
+        }
+    }
+}
+
+/// Reads the vendor, brand string, family, model and core counts of the current CPU.
+pub fn cpu_info() -> CpuInfo {
+    // leaf 0: the highest basic leaf and the vendor in EBX, EDX, ECX
+    let leaf_0 = cpuid(0, 0);
+    let max_leaf = leaf_0.eax;
+    let mut vendor = KernelString::new();
+    push_registers(&mut vendor, &[leaf_0.ebx, leaf_0.edx, leaf_0.ecx]);
+
+    // leaf 1: version information and the logical processor count
+    let leaf_1 = cpuid(1, 0);
+    let stepping = (leaf_1.eax & 0xf) as u8;
+    let base_model = (leaf_1.eax >> 4) & 0xf;
+    let base_family = (leaf_1.eax >> 8) & 0xf;
+    let extended_model = (leaf_1.eax >> 16) & 0xf;
+    let extended_family = (leaf_1.eax >> 20) & 0xff;
+    let family = if base_family == 0xf { base_family + extended_family } else { base_family };
+    let model = if base_family == 0x6 || base_family == 0xf {
+        (extended_model << 4) + base_model
+    } else {
+        base_model
+    };
+    // EBX[23:16] is only valid if EDX reports hyper-threading support (HTT, bit 28)
+    let logical_cores = if leaf_1.edx & (1 << 28) != 0 {
+        ((leaf_1.ebx >> 16) & 0xff).max(1) as u8
+    } else {
+        1
+    };
+
+    let max_extended_leaf = cpuid(0x8000_0000, 0).eax;
+    let physical_cores = if vendor.as_str() == "GenuineIntel" && max_leaf >= 4 {
+        // leaf 4: EAX[31:26] is the number of cores per package minus one
+        ((cpuid(4, 0).eax >> 26) + 1) as u8
+    } else if max_extended_leaf >= 0x8000_0008 {
+        // AMD: leaf 0x8000_0008 ECX[7:0] is the number of threads per package minus one
+        let threads = (cpuid(0x8000_0008, 0).ecx & 0xff) + 1;
+        let threads_per_core = if max_extended_leaf >= 0x8000_001e {
+            ((cpuid(0x8000_001e, 0).ebx >> 8) & 0xff) + 1
+        } else {
+            1
+        };
+        (threads / threads_per_core).max(1) as u8
+    } else {
+        1
+    };
+
+    // leaves 0x8000_0002 to 0x8000_0004: 48 bytes of brand string
+    let mut brand = KernelString::new();
+    if max_extended_leaf >= 0x8000_0004 {
+        let mut raw = KernelString::<48>::new();
+        for leaf in 0x8000_0002..=0x8000_0004 {
+            let r = cpuid(leaf, 0);
+            push_registers(&mut raw, &[r.eax, r.ebx, r.ecx, r.edx]);
+        }
+        // the string is often padded with spaces on Intel CPUs
+        let _ = brand.write_str(raw.trim());
+    }
+
+    CpuInfo {
+        vendor,
+        brand,
+        family: family.min(u32::from(u8::MAX)) as u8,
+        model: model as u8,
+        stepping,
+        logical_cores,
+        physical_cores,
+    }
+}
+
+#[test_case]
+fn test_cpu_info() {
+    let info = cpu_info();
+    assert_eq!(info.vendor.len(), 12);
+    assert!(info.family > 0);
+    assert!(info.logical_cores >= 1 && info.physical_cores >= 1);
+}
@@ -0,0 +1,77 @@
+//! CPU identification, per-CPU state and interrupt-safe critical sections.
+
+pub mod percpu;
+pub mod critical;
+pub mod info;
+pub mod features;
+
+pub use percpu::PerCpu;
+pub use critical::{restore_flags, save_flags, CriticalSection, CriticalSectionGuard};
+pub use info::{cpu_info, CpuInfo};
+pub use features::KernelFeatures;
+
+use core::arch::x86_64::{CpuidResult, __cpuid_count};
+use core::hint::spin_loop;
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::registers::model_specific::Msr;
+
+// IA32_APIC_BASE, bit 8 is set on the bootstrap processor
+// and bit 10 when the local APIC runs in x2APIC mode
+const IA32_APIC_BASE: u32 = 0x1B;
+const APIC_BASE_BSP: u64 = 1 << 8;
+// the x2APIC ID register
+const X2APIC_ID: u32 = 0x802;
+
+/// Executes CPUID for the given leaf and subleaf.
+// `__cpuid_count` is only unsafe on older nightlies
+#[allow(unused_unsafe)]
+pub fn cpuid(leaf: u32, subleaf: u32) -> CpuidResult {
+    unsafe { __cpuid_count(leaf, subleaf) }
+}
+
+/// Returns the local APIC ID of the CPU this code is running on.
+pub fn apic_id() -> u32 {
+    // CPUID leaf 1 EDX bit 9 reports whether there is a local APIC at all
+    let leaf_1 = cpuid(1, 0);
+    let has_apic = leaf_1.edx & (1 << 9) != 0;
+
+    if has_apic && unsafe { Msr::new(IA32_APIC_BASE).read() } & (1 << 10) != 0 {
+        // in x2APIC mode the full 32 bit ID is only available through the MSR
+        unsafe { Msr::new(X2APIC_ID).read() as u32 }
+    } else {
+        // otherwise CPUID reports the 8 bit initial APIC ID in EBX[31:24]
+        leaf_1.ebx >> 24
+    }
+}
+
+/// Returns whether this code runs on the bootstrap processor, the CPU that started first.
+pub fn is_bsp() -> bool {
+    let has_apic = cpuid(1, 0).edx & (1 << 9) != 0;
+    // without a local APIC there is only one CPU
+    !has_apic || unsafe { Msr::new(IA32_APIC_BASE).read() } & APIC_BASE_BSP != 0
+}
+
+// set by the SMP initialization once the application processors may continue
+static APS_RELEASED: AtomicBool = AtomicBool::new(false);
+
+/// Allows the application processors waiting in `ap_halt_loop` to continue.
+pub fn release_aps() {
+    APS_RELEASED.store(true, Ordering::Release);
+}
+
+/// Parks an application processor until `release_aps` is called.
+///
+/// There is no entry point for application processors yet, so they halt afterwards.
+pub fn ap_halt_loop() -> ! {
+    while !APS_RELEASED.load(Ordering::Acquire) {
+        spin_loop();
+    }
+    loop {
+        x86_64::instructions::hlt();
+    }
+}
+
+#[test_case]
+fn test_tests_run_on_bsp() {
+    assert!(is_bsp());
+}
@@ -0,0 +1,94 @@
+use core::cell::UnsafeCell;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, Ordering};
+use x86_64::registers::model_specific::GsBase;
+use x86_64::VirtAddr;
+
+/// The number of CPUs the kernel keeps per-CPU data for.
+///
+/// The kernel only runs on the bootstrap processor for now, so every
+/// `PerCpu<T>` degenerates to a single `T` wrapped in an `UnsafeCell`.
+pub const MAX_CPUS: usize = 1;
+
+/// The per-CPU block that `GS` points to.
+///
+/// The first field is a pointer to the block itself, so the block can be found
+/// with a single `mov reg, gs:[0]` without reading the `GS` base MSR.
+#[repr(C)]
+pub struct CpuBlock {
+    this: *const CpuBlock,
+    /// Index of the CPU into the `PerCpu` slots.
+    pub index: usize,
+    /// Local APIC ID of the CPU.
+    pub apic_id: u32,
+}
+
+static mut BSP_BLOCK: CpuBlock = CpuBlock {
+    this: ptr::null(),
+    index: 0,
+    apic_id: 0,
+};
+
+// set once GS points to a valid CpuBlock
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+/// Sets up the per-CPU block of the bootstrap processor and points `GS` to it.
+pub fn init_bsp() {
+    unsafe {
+        let block = ptr::addr_of_mut!(BSP_BLOCK);
+        (*block).this = block;
+        (*block).apic_id = super::apic_id();
+        // writing the MSR directly works even if the CPU lacks the wrgsbase instruction
+        GsBase::write(VirtAddr::from_ptr(block));
+    }
+    INITIALIZED.store(true, Ordering::Release);
+}
+
+/// Returns the per-CPU block of the current CPU, or `None` before `init_bsp`.
+pub fn current() -> Option<&'static CpuBlock> {
+    if !INITIALIZED.load(Ordering::Acquire) {
+        return None;
+    }
+    let block: *const CpuBlock;
+    unsafe {
+        core::arch::asm!("mov {}, gs:[0]", out(reg) block, options(nostack, readonly, preserves_flags));
+        Some(&*block)
+    }
+}
+
+/// Returns the index of the current CPU, which is 0 before `init_bsp` was called.
+pub fn current_cpu_index() -> usize {
+    current().map_or(0, |block| block.index)
+}
+
+/// A value with a separate instance for every CPU.
+pub struct PerCpu<T> {
+    slots: [UnsafeCell<T>; MAX_CPUS],
+}
+
+// every CPU only accesses its own slot
+unsafe impl<T: Send> Sync for PerCpu<T> {}
+
+impl<T> PerCpu<T> {
+    /// Creates the per-CPU value, with `value` as the instance of the bootstrap processor.
+    pub const fn new(value: T) -> Self {
+        PerCpu {
+            slots: [UnsafeCell::new(value)],
+        }
+    }
+
+    /// Returns the instance of the current CPU.
+    pub fn get(&self) -> &T {
+        unsafe { &*self.slots[current_cpu_index()].get() }
+    }
+
+    /// Returns a mutable reference to the instance of the current CPU.
+    ///
+    /// This function is unsafe because the caller must guarantee that no other
+    /// reference to the current CPU's instance exists, including ones held by
+    /// interrupted code on the same CPU.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get_mut(&self) -> &mut T {
+        &mut *self.slots[current_cpu_index()].get()
+    }
+}
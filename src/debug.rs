@@ -0,0 +1,163 @@
+//! Register state of exceptions, kept for post-mortem inspection.
+//!
+//! The `x86-interrupt` calling convention only gives handlers the interrupt stack frame.
+//! Handlers that need the general purpose registers are entered through an assembly stub
+//! created with `exception_entry_with_error_code!`, which pushes all of them before
+//! calling the handler with a pointer to an `ExceptionContext`.
+
+use core::fmt;
+use spin::Mutex;
+use x86_64::{structures::idt::InterruptStackFrame, VirtAddr};
+
+/// The general purpose registers, RFLAGS, RSP and RIP at the time of an exception.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RegisterDump {
+    pub rax: u64,
+    pub rbx: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    pub rbp: u64,
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub rip: u64,
+}
+
+impl fmt::Display for RegisterDump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "RAX={:016x} RBX={:016x} RCX={:016x}", self.rax, self.rbx, self.rcx)?;
+        writeln!(f, "RDX={:016x} RSI={:016x} RDI={:016x}", self.rdx, self.rsi, self.rdi)?;
+        writeln!(f, "RBP={:016x} R8 ={:016x} R9 ={:016x}", self.rbp, self.r8, self.r9)?;
+        writeln!(f, "R10={:016x} R11={:016x} R12={:016x}", self.r10, self.r11, self.r12)?;
+        writeln!(f, "R13={:016x} R14={:016x} R15={:016x}", self.r13, self.r14, self.r15)?;
+        write!(f, "RSP={:016x} RIP={:016x} RFL={:016x}", self.rsp, self.rip, self.rflags)
+    }
+}
+
+/// The registers of the last exception that went through an entry stub,
+/// for a debugger to query.
+pub static LAST_EXCEPTION_REGS: Mutex<Option<RegisterDump>> = Mutex::new(None);
+
+/// Stores `dump` in `LAST_EXCEPTION_REGS`.
+///
+/// Does nothing if it is locked, i.e. if the exception happened while it was being read.
+pub fn record(dump: RegisterDump) {
+    if let Some(mut regs) = LAST_EXCEPTION_REGS.try_lock() {
+        *regs = Some(dump);
+    }
+}
+
+/// Everything an entry stub leaves on the stack, starting with the last pushed register.
+#[repr(C)]
+pub struct ExceptionContext {
+    r15: u64,
+    r14: u64,
+    r13: u64,
+    r12: u64,
+    r11: u64,
+    r10: u64,
+    r9: u64,
+    r8: u64,
+    rbp: u64,
+    rdi: u64,
+    rsi: u64,
+    rdx: u64,
+    rcx: u64,
+    rbx: u64,
+    rax: u64,
+    pub error_code: u64,
+    stack_frame: InterruptStackFrame,
+}
+
+impl ExceptionContext {
+    /// Returns the interrupt stack frame pushed by the CPU.
+    pub fn stack_frame(&self) -> &InterruptStackFrame {
+        &self.stack_frame
+    }
+
+    pub fn register_dump(&self) -> RegisterDump {
+        RegisterDump {
+            rax: self.rax,
+            rbx: self.rbx,
+            rcx: self.rcx,
+            rdx: self.rdx,
+            rsi: self.rsi,
+            rdi: self.rdi,
+            rbp: self.rbp,
+            r8: self.r8,
+            r9: self.r9,
+            r10: self.r10,
+            r11: self.r11,
+            r12: self.r12,
+            r13: self.r13,
+            r14: self.r14,
+            r15: self.r15,
+            rflags: self.stack_frame.cpu_flags,
+            rsp: self.stack_frame.stack_pointer.as_u64(),
+            rip: self.stack_frame.instruction_pointer.as_u64(),
+        }
+    }
+}
+
+/// Defines the assembly entry stub `$entry` for an exception that pushes an error code.
+/// The stub saves all general purpose registers and calls
+/// `extern "C" fn $handler(context: &mut ExceptionContext)`.
+///
+/// Install it with `set_handler_addr`. SSE is disabled for the kernel, so there is no
+/// other register state to save.
+#[macro_export]
+macro_rules! exception_entry_with_error_code {
+    ($entry:ident, $handler:path) => {
+        extern "C" {
+            fn $entry();
+        }
+
+        // the CPU aligned the stack to 16 bytes before pushing the 5 words of the
+        // stack frame and the error code, the 15 registers leave it off by 8
+        core::arch::global_asm!(
+            concat!(".global ", stringify!($entry)),
+            concat!(stringify!($entry), ":"),
+            "push rax", "push rbx", "push rcx", "push rdx", "push rsi",
+            "push rdi", "push rbp", "push r8", "push r9", "push r10",
+            "push r11", "push r12", "push r13", "push r14", "push r15",
+            "mov rdi, rsp",
+            "cld",
+            "sub rsp, 8",
+            "call {handler}",
+            "add rsp, 8",
+            "pop r15", "pop r14", "pop r13", "pop r12", "pop r11",
+            "pop r10", "pop r9", "pop r8", "pop rbp", "pop rdi",
+            "pop rsi", "pop rdx", "pop rcx", "pop rbx", "pop rax",
+            // drop the error code
+            "add rsp, 8",
+            "iretq",
+            handler = sym $handler,
+        );
+    };
+}
+
+/// Returns the address of an entry stub for `set_handler_addr`.
+pub fn entry_address(entry: unsafe extern "C" fn()) -> VirtAddr {
+    VirtAddr::new(entry as usize as u64)
+}
+
+#[test_case]
+fn test_register_dump_display() {
+    use crate::collections::string::KernelString;
+    use core::fmt::Write;
+
+    let dump = RegisterDump { rax: 0x1234, rip: 0xdead_beef, ..RegisterDump::default() };
+    let mut s = KernelString::<512>::new();
+    write!(s, "{}", dump).unwrap();
+    assert!(s.starts_with("RAX=0000000000001234 "));
+    assert!(s.contains("RIP=00000000deadbeef"));
+}
@@ -0,0 +1,327 @@
+//! Powering off and resetting the machine through ACPI.
+//!
+//! `init` finds the FADT through the RSDP and the RSDT or XSDT, and takes the
+//! sleep type of the S5 (soft off) state from the `\_S5_` package in the DSDT.
+//! Only the parts needed for `power_off` and `reset` are parsed.
+
+use core::{mem, ptr};
+use x86_64::{instructions::port::Port, PhysAddr, VirtAddr};
+use crate::memory;
+use crate::sync::Once;
+
+// the RSDP is in the first KiB of the EBDA or in the BIOS area, on a 16 byte boundary
+const EBDA_SEGMENT_PTR: u64 = 0x40E;
+const BIOS_AREA: (u64, u64) = (0xE_0000, 0x10_0000);
+const RSDP_SIGNATURE: &[u8; 8] = b"RSD PTR ";
+
+// every system description table starts with this header
+const SDT_HEADER_LEN: u64 = 36;
+
+// FADT field offsets from the ACPI specification
+const FADT_DSDT: u64 = 40;
+const FADT_SMI_CMD: u64 = 48;
+const FADT_ACPI_ENABLE: u64 = 52;
+const FADT_PM1A_CNT_BLK: u64 = 64;
+const FADT_PM1B_CNT_BLK: u64 = 68;
+const FADT_FLAGS: u64 = 112;
+const FADT_RESET_REG: u64 = 116;
+const FADT_RESET_VALUE: u64 = 128;
+const FADT_X_DSDT: u64 = 140;
+
+// FADT flags: the reset register is supported
+const RESET_REG_SUP: u32 = 1 << 10;
+
+// PM1 control register: SCI enabled, sleep type in bits 10-12, sleep enable
+const SCI_EN: u16 = 1 << 0;
+const SLP_TYP_SHIFT: u16 = 10;
+const SLP_EN: u16 = 1 << 13;
+
+// AML opcodes used in the `\_S5_` package
+const AML_NAME_OP: u8 = 0x08;
+const AML_PACKAGE_OP: u8 = 0x12;
+const AML_BYTE_PREFIX: u8 = 0x0A;
+
+// generic address structure address spaces
+const ADDRESS_SPACE_MEMORY: u8 = 0;
+const ADDRESS_SPACE_IO: u8 = 1;
+
+static POWER: Once<AcpiPower> = Once::new();
+
+/// Errors returned by the ACPI functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcpiError {
+    /// `init` has not been called successfully.
+    NotInitialized,
+    /// No RSDP was found in the BIOS memory areas.
+    RsdpNotFound,
+    /// The table with the given signature has a wrong checksum.
+    InvalidChecksum([u8; 4]),
+    /// A table points to physical memory outside of the memory map.
+    AddressNotMapped(u64),
+    /// The RSDT or XSDT has no FADT.
+    FadtNotFound,
+    /// The DSDT has no `\_S5_` package.
+    S5NotFound,
+    /// The FADT has no reset register.
+    ResetNotSupported,
+    /// The reset register is in an address space other than memory or I/O ports.
+    UnsupportedAddressSpace(u8),
+    /// The register was written, but the machine is still running.
+    NoEffect,
+}
+
+// the register from the FADT's generic address structure
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ResetRegister {
+    address_space: u8,
+    address: u64,
+    value: u8,
+}
+
+/// What `power_off` and `reset` need from the ACPI tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AcpiPower {
+    pm1a_cnt: u16,
+    /// Zero if there is no second PM1 control block.
+    pm1b_cnt: u16,
+    slp_typa: u16,
+    slp_typb: u16,
+    smi_cmd: u16,
+    acpi_enable: u8,
+    reset: Option<ResetRegister>,
+    physical_memory_offset: VirtAddr,
+}
+
+/// Parses the ACPI tables and remembers the registers used by `power_off` and `reset`.
+///
+/// This function is unsafe because the caller must guarantee that the complete
+/// physical memory is mapped at `physical_memory_offset`.
+pub unsafe fn init(physical_memory_offset: VirtAddr) -> Result<&'static AcpiPower, AcpiError> {
+    let tables = PhysMemory(physical_memory_offset);
+    let power = tables.parse()?;
+    Ok(POWER.call_once(|| power))
+}
+
+/// Puts the machine into the S5 (soft off) state.
+///
+/// Only returns if the machine could not be powered off.
+pub fn power_off() -> AcpiError {
+    let power = match POWER.get() {
+        Some(power) => power,
+        None => return AcpiError::NotInitialized,
+    };
+
+    unsafe {
+        power.enable();
+        let mut pm1a = Port::<u16>::new(power.pm1a_cnt);
+        let value = pm1a.read() & !(0b111 << SLP_TYP_SHIFT);
+        pm1a.write(value | (power.slp_typa << SLP_TYP_SHIFT) | SLP_EN);
+        if power.pm1b_cnt != 0 {
+            let mut pm1b = Port::<u16>::new(power.pm1b_cnt);
+            let value = pm1b.read() & !(0b111 << SLP_TYP_SHIFT);
+            pm1b.write(value | (power.slp_typb << SLP_TYP_SHIFT) | SLP_EN);
+        }
+    }
+    AcpiError::NoEffect
+}
+
+/// Resets the machine through the reset register of the FADT.
+///
+/// Only returns if the machine could not be reset.
+pub fn reset() -> AcpiError {
+    let power = match POWER.get() {
+        Some(power) => power,
+        None => return AcpiError::NotInitialized,
+    };
+    let register = match power.reset {
+        Some(register) => register,
+        None => return AcpiError::ResetNotSupported,
+    };
+
+    match register.address_space {
+        ADDRESS_SPACE_IO => unsafe { Port::<u8>::new(register.address as u16).write(register.value) },
+        ADDRESS_SPACE_MEMORY => {
+            let addr = memory::phys_to_virt(PhysAddr::new(register.address), power.physical_memory_offset);
+            match addr {
+                Some(addr) => unsafe { ptr::write_volatile(addr.as_mut_ptr::<u8>(), register.value) },
+                None => return AcpiError::AddressNotMapped(register.address),
+            }
+        }
+        space => return AcpiError::UnsupportedAddressSpace(space),
+    }
+    AcpiError::NoEffect
+}
+
+impl AcpiPower {
+    // switches the chipset from legacy to ACPI mode if the firmware has not done it yet
+    unsafe fn enable(&self) {
+        let mut pm1a = Port::<u16>::new(self.pm1a_cnt);
+        if pm1a.read() & SCI_EN != 0 || self.smi_cmd == 0 || self.acpi_enable == 0 {
+            return;
+        }
+        Port::<u8>::new(self.smi_cmd).write(self.acpi_enable);
+        // the firmware sets SCI_EN once it has switched, this usually takes a few microseconds
+        for _ in 0..1_000_000 {
+            if pm1a.read() & SCI_EN != 0 {
+                break;
+            }
+            core::hint::spin_loop();
+        }
+    }
+}
+
+// reads the ACPI tables through the physical memory mapping
+struct PhysMemory(VirtAddr);
+
+impl PhysMemory {
+    unsafe fn read<T: Copy>(&self, phys: u64) -> Result<T, AcpiError> {
+        let bytes = self.bytes(phys, mem::size_of::<T>() as u64)?;
+        Ok(ptr::read_unaligned(bytes.as_ptr() as *const T))
+    }
+
+    unsafe fn bytes(&self, phys: u64, len: u64) -> Result<&'static [u8], AcpiError> {
+        if len == 0 || memory::phys_to_virt(PhysAddr::new(phys + len - 1), self.0).is_none() {
+            return Err(AcpiError::AddressNotMapped(phys));
+        }
+        let addr = memory::phys_to_virt(PhysAddr::new(phys), self.0)
+            .ok_or(AcpiError::AddressNotMapped(phys))?;
+        Ok(core::slice::from_raw_parts(addr.as_ptr(), len as usize))
+    }
+
+    // returns the address of the RSDP
+    unsafe fn find_rsdp(&self) -> Result<u64, AcpiError> {
+        let ebda = (self.read::<u16>(EBDA_SEGMENT_PTR)? as u64) << 4;
+        let areas = [(ebda, ebda + 1024), BIOS_AREA];
+        for (start, end) in areas {
+            if start == 0 {
+                continue;
+            }
+            for addr in (start..end).step_by(16) {
+                let candidate = self.bytes(addr, 20)?;
+                if &candidate[..8] == RSDP_SIGNATURE && checksum(candidate) {
+                    return Ok(addr);
+                }
+            }
+        }
+        Err(AcpiError::RsdpNotFound)
+    }
+
+    // returns the whole table at `addr` after checking its checksum
+    unsafe fn table(&self, addr: u64) -> Result<&'static [u8], AcpiError> {
+        let header = self.bytes(addr, SDT_HEADER_LEN)?;
+        let length = self.read::<u32>(addr + 4)? as u64;
+        let table = self.bytes(addr, length.max(SDT_HEADER_LEN))?;
+        if !checksum(table) {
+            return Err(AcpiError::InvalidChecksum(header[..4].try_into().unwrap()));
+        }
+        Ok(table)
+    }
+
+    unsafe fn find_fadt(&self, rsdp: u64) -> Result<&'static [u8], AcpiError> {
+        // revision 2 and later have the 64 bit XSDT address
+        let revision = self.read::<u8>(rsdp + 15)?;
+        let xsdt = if revision >= 2 { self.read::<u64>(rsdp + 24)? } else { 0 };
+        let (root, entry_size) = match xsdt {
+            0 => (self.table(self.read::<u32>(rsdp + 16)? as u64)?, 4),
+            xsdt => (self.table(xsdt)?, 8),
+        };
+
+        for entry in root[SDT_HEADER_LEN as usize..].chunks_exact(entry_size) {
+            let addr = match entry_size {
+                4 => u32::from_le_bytes(entry.try_into().unwrap()) as u64,
+                _ => u64::from_le_bytes(entry.try_into().unwrap()),
+            };
+            if self.bytes(addr, 4)? == b"FACP" {
+                return self.table(addr);
+            }
+        }
+        Err(AcpiError::FadtNotFound)
+    }
+
+    unsafe fn parse(&self) -> Result<AcpiPower, AcpiError> {
+        let fadt = self.find_fadt(self.find_rsdp()?)?;
+        let field_u32 = |offset: u64| -> u32 {
+            let offset = offset as usize;
+            fadt.get(offset..offset + 4).map_or(0, |b| u32::from_le_bytes(b.try_into().unwrap()))
+        };
+        let field_u64 = |offset: u64| -> u64 {
+            let offset = offset as usize;
+            fadt.get(offset..offset + 8).map_or(0, |b| u64::from_le_bytes(b.try_into().unwrap()))
+        };
+
+        let dsdt = match field_u64(FADT_X_DSDT) {
+            0 => field_u32(FADT_DSDT) as u64,
+            x_dsdt => x_dsdt,
+        };
+        let (slp_typa, slp_typb) = find_s5(self.table(dsdt)?).ok_or(AcpiError::S5NotFound)?;
+
+        let reset = match fadt.get(FADT_RESET_VALUE as usize) {
+            Some(&value) if field_u32(FADT_FLAGS) & RESET_REG_SUP != 0 => Some(ResetRegister {
+                address_space: fadt[FADT_RESET_REG as usize],
+                address: field_u64(FADT_RESET_REG + 4),
+                value,
+            }),
+            _ => None,
+        };
+
+        Ok(AcpiPower {
+            pm1a_cnt: field_u32(FADT_PM1A_CNT_BLK) as u16,
+            pm1b_cnt: field_u32(FADT_PM1B_CNT_BLK) as u16,
+            slp_typa,
+            slp_typb,
+            smi_cmd: field_u32(FADT_SMI_CMD) as u16,
+            acpi_enable: fadt.get(FADT_ACPI_ENABLE as usize).copied().unwrap_or(0),
+            reset,
+            physical_memory_offset: self.0,
+        })
+    }
+}
+
+// the bytes of a valid table add up to zero
+fn checksum(bytes: &[u8]) -> bool {
+    bytes.iter().fold(0u8, |sum, &byte| sum.wrapping_add(byte)) == 0
+}
+
+/// Returns SLP_TYPa and SLP_TYPb from the `\_S5_` package in the AML of the DSDT.
+///
+/// This does not interpret the AML, it looks for the bytes of `Name (_S5_, Package () {a, b, ..})`.
+fn find_s5(aml: &[u8]) -> Option<(u16, u16)> {
+    let pos = aml.windows(4).position(|window| window == b"_S5_")?;
+    // the name must be defined with NameOp, optionally in the root scope
+    let defined = match pos {
+        0 => false,
+        1 => aml[0] == AML_NAME_OP,
+        _ => aml[pos - 1] == AML_NAME_OP || (aml[pos - 2] == AML_NAME_OP && aml[pos - 1] == b'\\'),
+    };
+    let mut rest = aml.get(pos + 4..)?;
+    if !defined || *rest.first()? != AML_PACKAGE_OP {
+        return None;
+    }
+    // the top two bits of the first PkgLength byte give the number of following bytes,
+    // after the PkgLength comes the number of elements
+    let pkg_length_bytes = ((rest.get(1)? & 0xC0) >> 6) as usize + 1;
+    rest = rest.get(1 + pkg_length_bytes + 1..)?;
+
+    let mut next_value = || -> Option<u16> {
+        // values below 0x0A are encoded without a prefix, e.g. ZeroOp and OneOp
+        if *rest.first()? == AML_BYTE_PREFIX {
+            rest = &rest[1..];
+        }
+        let value = *rest.first()?;
+        rest = &rest[1..];
+        Some(value as u16)
+    };
+    let slp_typa = next_value()?;
+    let slp_typb = next_value()?;
+    Some((slp_typa, slp_typb))
+}
+
+#[test_case]
+fn test_find_s5() {
+    // Name (\_S5_, Package (0x04) { 0x05, Zero, Zero, Zero }) as compiled by iasl
+    let aml = [0x10, 0x08, b'\\', b'_', b'S', b'5', b'_', 0x12, 0x08, 0x04, 0x0A, 0x05, 0x00, 0x00, 0x00];
+    assert_eq!(find_s5(&aml[1..]), Some((5, 0)));
+    // a method that references the name does not define it
+    assert_eq!(find_s5(b"\x14\x05_S5_\x12\x06\x02\x01\x01"), None);
+    assert_eq!(find_s5(b"no sleep states"), None);
+}
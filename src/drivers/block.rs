@@ -0,0 +1,45 @@
+use alloc::{sync::Arc, vec::Vec};
+use spin::Mutex;
+
+/// Errors returned by block devices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockError {
+    /// The block address is past the end of the device.
+    OutOfRange(u64),
+    /// The buffer length is not the device's block size.
+    InvalidBufferSize(usize),
+    /// The device does not support writing.
+    ReadOnly,
+    /// The device reported an error.
+    DeviceError,
+}
+
+/// A device that is read and written in fixed-size blocks, such as a disk.
+pub trait BlockDev: Send + Sync {
+    /// The size of one block in bytes.
+    fn block_size(&self) -> usize;
+
+    /// The number of blocks on the device.
+    fn block_count(&self) -> u64;
+
+    /// Reads block `lba` into `buf`, which must be exactly one block long.
+    fn read_block(&self, lba: u64, buf: &mut [u8]) -> Result<(), BlockError>;
+
+    /// Writes `buf`, which must be exactly one block long, to block `lba`.
+    fn write_block(&mut self, lba: u64, buf: &[u8]) -> Result<(), BlockError>;
+}
+
+/// All block devices found during initialization, in discovery order.
+pub static BLOCK_DEVICES: Mutex<Vec<Arc<dyn BlockDev>>> = Mutex::new(Vec::new());
+
+/// Adds `device` to `BLOCK_DEVICES` and returns its index.
+pub fn register(device: Arc<dyn BlockDev>) -> usize {
+    let mut devices = BLOCK_DEVICES.lock();
+    devices.push(device);
+    devices.len() - 1
+}
+
+/// Returns the registered device at `index`.
+pub fn get(index: usize) -> Option<Arc<dyn BlockDev>> {
+    BLOCK_DEVICES.lock().get(index).cloned()
+}
@@ -0,0 +1,64 @@
+use x86_64::instructions::interrupts;
+use crate::io::{io_delay, ports::{CmosData, CmosIndex}};
+
+// the CMOS is accessed by selecting a register on the index port and then reading or writing the data port
+// setting bit 7 of the index disables non-maskable interrupts while we access the CMOS
+const NMI_DISABLE: u8 = 0x80;
+
+/// Well-known CMOS registers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum CmosRegister {
+    Seconds = 0x00,
+    Minutes = 0x02,
+    Hours = 0x04,
+    Weekday = 0x06,
+    DayOfMonth = 0x07,
+    Month = 0x08,
+    Year = 0x09,
+    StatusA = 0x0A,
+    StatusB = 0x0B,
+    StatusC = 0x0C,
+    ShutdownStatus = 0x0F,
+    FloppyDriveType = 0x10,
+    CenturyRegister = 0x32,
+}
+
+impl CmosRegister {
+    pub fn as_u8(self) -> u8 {
+        self as u8
+    }
+}
+
+/// Reads the given CMOS register.
+pub fn read(register: u8) -> u8 {
+    let mut index = CmosIndex::new();
+    let mut data = CmosData::new();
+
+    // an interrupt between selecting the register and reading it could select another register
+    interrupts::without_interrupts(|| {
+        index.write(NMI_DISABLE | register);
+        // the CMOS needs time to select the register
+        io_delay();
+        let value = data.read();
+        // re-enable NMIs
+        index.write(register & !NMI_DISABLE);
+        value
+    })
+}
+
+/// Writes `value` to the given CMOS register.
+///
+/// Some registers hold the checksummed system configuration, so writing
+/// the wrong register can make the firmware reset its settings on the next boot.
+pub fn write(register: u8, value: u8) {
+    let mut index = CmosIndex::new();
+    let mut data = CmosData::new();
+
+    interrupts::without_interrupts(|| {
+        index.write(NMI_DISABLE | register);
+        io_delay();
+        data.write(value);
+        index.write(register & !NMI_DISABLE);
+    });
+}
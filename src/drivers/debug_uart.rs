@@ -0,0 +1,122 @@
+//! Debug output that is independent of how the UART is reached.
+//!
+//! On x86 the serial port is accessed with port I/O, other platforms map the UART
+//! registers into memory (e.g. the ARM PL011 or the SiFive UART).
+
+use core::fmt;
+use core::ptr;
+use uart_16550::SerialPort;
+use x86_64::instructions::port::PortReadOnly;
+
+// line status register: the transmitter is empty, including the shift register
+const LINE_STATUS_OFFSET: u16 = 5;
+const TRANSMITTER_EMPTY: u8 = 1 << 6;
+
+/// A byte-oriented output for debug messages.
+pub trait DebugOutput {
+    fn write_byte(&mut self, byte: u8);
+
+    fn write_str(&mut self, s: &str) {
+        for byte in s.bytes() {
+            self.write_byte(byte);
+        }
+    }
+
+    /// Writes binary data. Unlike `write_str`, no byte gets special treatment.
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_byte(byte);
+        }
+    }
+
+    /// Waits until all written bytes have left the device.
+    fn flush(&mut self) {}
+
+    /// Writes formatted text, used by the `serial_print!` macros.
+    fn write_fmt(&mut self, args: fmt::Arguments) -> fmt::Result {
+        // `fmt::write` needs a `fmt::Write`, so forward through a small adapter
+        struct Adapter<'a, D: DebugOutput + ?Sized>(&'a mut D);
+
+        impl<D: DebugOutput + ?Sized> fmt::Write for Adapter<'_, D> {
+            fn write_str(&mut self, s: &str) -> fmt::Result {
+                self.0.write_str(s);
+                Ok(())
+            }
+        }
+
+        fmt::write(&mut Adapter(self), args)
+    }
+}
+
+/// A 16550 UART accessed through x86 I/O ports.
+pub struct PortDebugUart {
+    port: SerialPort,
+    line_status: PortReadOnly<u8>,
+}
+
+impl PortDebugUart {
+    /// Initializes the UART at the given base port.
+    ///
+    /// This function is unsafe because the caller must guarantee that there is
+    /// a UART at `base` and that nothing else uses its ports.
+    pub unsafe fn new(base: u16) -> Self {
+        let mut port = SerialPort::new(base);
+        port.init();
+        PortDebugUart {
+            port,
+            line_status: PortReadOnly::new(base + LINE_STATUS_OFFSET),
+        }
+    }
+}
+
+impl DebugOutput for PortDebugUart {
+    fn write_byte(&mut self, byte: u8) {
+        // `send` turns backspace and delete into a sequence that erases the last character
+        self.port.send(byte);
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.port.send_raw(byte);
+        }
+    }
+
+    fn flush(&mut self) {
+        while unsafe { self.line_status.read() } & TRANSMITTER_EMPTY == 0 {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// A UART whose transmit register is mapped into memory.
+///
+/// This is a minimal stub: it writes every byte to the register at `base`
+/// without waiting for space in the transmit FIFO.
+pub struct MmioDebugUart {
+    base: *mut u8,
+}
+
+impl MmioDebugUart {
+    /// This function is unsafe because `base` must be the mapped transmit
+    /// data register of a UART that nothing else accesses.
+    pub unsafe fn new(base: *mut u8) -> Self {
+        MmioDebugUart { base }
+    }
+}
+
+impl DebugOutput for MmioDebugUart {
+    fn write_byte(&mut self, byte: u8) {
+        unsafe { ptr::write_volatile(self.base, byte) };
+    }
+}
+
+// the register belongs to the UART alone, so it can be used from any CPU behind a lock
+unsafe impl Send for MmioDebugUart {}
+
+#[test_case]
+fn test_mmio_debug_uart_writes_register() {
+    let mut register = 0u8;
+    let mut uart = unsafe { MmioDebugUart::new(&mut register) };
+    uart.write_str("ok");
+    assert_eq!(register, b'k');
+}
@@ -0,0 +1,318 @@
+use core::ptr;
+use x86_64::{
+    structures::paging::{
+        mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+use crate::net::{MacAddr, NetworkDevice};
+
+/// PCI vendor ID of Intel.
+pub const VENDOR_ID: u16 = 0x8086;
+/// PCI device ID of the 82540EM that QEMU emulates as `e1000`.
+pub const DEVICE_ID: u16 = 0x100E;
+
+// the register block is 128 KiB large and mapped at this virtual address
+const E1000_VIRT_BASE: u64 = 0x_5555_0020_0000;
+const MMIO_SIZE: u64 = 128 * 1024;
+
+// number of descriptors per ring, 256 descriptors of 16 bytes fill exactly one frame
+const RING_SIZE: usize = 256;
+// every descriptor gets a 2 KiB buffer, so two buffers share one frame
+const BUFFER_SIZE: usize = 2048;
+
+// register offsets from the 8254x software developer's manual
+const CTRL: usize = 0x0000;
+const ICR: usize = 0x00C0;
+const IMC: usize = 0x00D8;
+const RCTL: usize = 0x0100;
+const TCTL: usize = 0x0400;
+const TIPG: usize = 0x0410;
+const RDBAL: usize = 0x2800;
+const RDBAH: usize = 0x2804;
+const RDLEN: usize = 0x2808;
+const RDH: usize = 0x2810;
+const RDT: usize = 0x2818;
+const TDBAL: usize = 0x3800;
+const TDBAH: usize = 0x3804;
+const TDLEN: usize = 0x3808;
+const TDH: usize = 0x3810;
+const TDT: usize = 0x3818;
+const MTA: usize = 0x5200;
+const RAL0: usize = 0x5400;
+const RAH0: usize = 0x5404;
+
+// CTRL bits: auto speed detection, set link up, device reset
+const CTRL_ASDE: u32 = 1 << 5;
+const CTRL_SLU: u32 = 1 << 6;
+const CTRL_RST: u32 = 1 << 26;
+// RCTL bits: receiver enable, accept broadcast, strip CRC, buffer size 2048 is the default 0
+const RCTL_EN: u32 = 1 << 1;
+const RCTL_BAM: u32 = 1 << 15;
+const RCTL_SECRC: u32 = 1 << 26;
+// TCTL bits: transmit enable, pad short packets, collision threshold and distance
+const TCTL_EN: u32 = 1 << 1;
+const TCTL_PSP: u32 = 1 << 3;
+const TCTL_CT: u32 = 0x10 << 4;
+const TCTL_COLD: u32 = 0x40 << 12;
+// recommended inter packet gap for IEEE 802.3
+const TIPG_VALUE: u32 = 0x0060_200A;
+
+// descriptor status bit: the device is done with the descriptor
+const STATUS_DD: u8 = 1 << 0;
+// transmit command bits: end of packet, insert checksum, report status
+const CMD_EOP: u8 = 1 << 0;
+const CMD_IFCS: u8 = 1 << 1;
+const CMD_RS: u8 = 1 << 3;
+
+/// Errors of the e1000 driver.
+#[derive(Debug)]
+pub enum E1000Error {
+    /// Mapping the register block or a ring failed.
+    MapFailed(MapToError<Size4KiB>),
+    /// The packet does not fit into a transmit buffer.
+    PacketTooLarge,
+    /// All transmit descriptors are still owned by the device.
+    TxRingFull,
+}
+
+impl From<MapToError<Size4KiB>> for E1000Error {
+    fn from(err: MapToError<Size4KiB>) -> Self {
+        E1000Error::MapFailed(err)
+    }
+}
+
+/// A receive descriptor as laid out in memory.
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct RxDescriptor {
+    addr: u64,
+    length: u16,
+    checksum: u16,
+    status: u8,
+    errors: u8,
+    special: u16,
+}
+
+/// A legacy transmit descriptor as laid out in memory.
+#[derive(Debug, Clone, Copy, Default)]
+#[repr(C)]
+pub struct TxDescriptor {
+    addr: u64,
+    length: u16,
+    cso: u8,
+    cmd: u8,
+    status: u8,
+    css: u8,
+    special: u16,
+}
+
+/// A physically contiguous descriptor ring together with the buffers of its descriptors.
+pub struct DescriptorRing<D> {
+    descriptors: *mut D,
+    phys: PhysAddr,
+    buffers: [VirtAddr; RING_SIZE],
+}
+
+pub type RxRing = DescriptorRing<RxDescriptor>;
+pub type TxRing = DescriptorRing<TxDescriptor>;
+
+impl<D: Default> DescriptorRing<D> {
+    /// Allocates the descriptor array and the buffers from physical frames.
+    ///
+    /// `init` is called with every descriptor and the physical address of its buffer.
+    fn new(
+        physical_memory_offset: VirtAddr,
+        frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+        init: impl Fn(&mut D, u64),
+    ) -> Result<Self, E1000Error> {
+        let ring_frame = frame_allocator.allocate_frame()
+            .ok_or(MapToError::FrameAllocationFailed)?;
+        let phys = ring_frame.start_address();
+        let descriptors: *mut D = (physical_memory_offset + phys.as_u64()).as_mut_ptr();
+
+        let mut buffers = [VirtAddr::zero(); RING_SIZE];
+        for pair in 0..RING_SIZE / 2 {
+            let frame = frame_allocator.allocate_frame()
+                .ok_or(MapToError::FrameAllocationFailed)?;
+            for half in 0..2 {
+                let index = pair * 2 + half;
+                let buffer_phys = frame.start_address().as_u64() + (half * BUFFER_SIZE) as u64;
+                buffers[index] = physical_memory_offset + buffer_phys;
+
+                let mut descriptor = D::default();
+                init(&mut descriptor, buffer_phys);
+                unsafe { ptr::write_volatile(descriptors.add(index), descriptor) };
+            }
+        }
+
+        Ok(DescriptorRing { descriptors, phys, buffers })
+    }
+
+    fn descriptor(&self, index: usize) -> *mut D {
+        unsafe { self.descriptors.add(index) }
+    }
+}
+
+/// An initialized Intel 8254x network card.
+pub struct E1000 {
+    tx_ring: TxRing,
+    rx_ring: RxRing,
+    base: VirtAddr,
+}
+
+impl E1000 {
+    /// Maps the registers at `bar0`, resets the device and enables receiving and transmitting.
+    ///
+    /// `bar0` is the memory BAR of the PCI function with `VENDOR_ID` and `DEVICE_ID`.
+    /// The rings and buffers are allocated from `frame_allocator` and accessed through
+    /// the complete physical memory mapping at `physical_memory_offset`.
+    pub fn init(
+        bar0: PhysAddr,
+        physical_memory_offset: VirtAddr,
+        mapper: &mut impl Mapper<Size4KiB>,
+        frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+    ) -> Result<E1000, E1000Error> {
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+        for offset in (0..MMIO_SIZE).step_by(4096) {
+            let page: Page<Size4KiB> = Page::containing_address(VirtAddr::new(E1000_VIRT_BASE + offset));
+            let frame = PhysFrame::containing_address(bar0 + offset);
+            unsafe { mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+        }
+
+        let base = VirtAddr::new(E1000_VIRT_BASE);
+        let rx_ring = RxRing::new(physical_memory_offset, frame_allocator, |desc, buffer| {
+            desc.addr = buffer;
+        })?;
+        let tx_ring = TxRing::new(physical_memory_offset, frame_allocator, |desc, buffer| {
+            desc.addr = buffer;
+            // mark the descriptor as free
+            desc.status = STATUS_DD;
+        })?;
+
+        let mut nic = E1000 { tx_ring, rx_ring, base };
+        nic.reset();
+        nic.init_rx();
+        nic.init_tx();
+        Ok(nic)
+    }
+
+    fn reset(&mut self) {
+        // mask all interrupts, reset the device and wait until the reset bit clears
+        self.write(IMC, 0xFFFF_FFFF);
+        self.write(CTRL, self.read(CTRL) | CTRL_RST);
+        while self.read(CTRL) & CTRL_RST != 0 {
+            core::hint::spin_loop();
+        }
+        self.write(IMC, 0xFFFF_FFFF);
+        // reading ICR clears pending interrupt causes
+        self.read(ICR);
+
+        self.write(CTRL, self.read(CTRL) | CTRL_SLU | CTRL_ASDE);
+        // clear the multicast table
+        for i in 0..128 {
+            self.write(MTA + i * 4, 0);
+        }
+    }
+
+    fn init_rx(&mut self) {
+        let phys = self.rx_ring.phys.as_u64();
+        self.write(RDBAL, phys as u32);
+        self.write(RDBAH, (phys >> 32) as u32);
+        self.write(RDLEN, (RING_SIZE * core::mem::size_of::<RxDescriptor>()) as u32);
+        // all descriptors except the one at the tail are available to the device
+        self.write(RDH, 0);
+        self.write(RDT, RING_SIZE as u32 - 1);
+        self.write(RCTL, RCTL_EN | RCTL_BAM | RCTL_SECRC);
+    }
+
+    fn init_tx(&mut self) {
+        let phys = self.tx_ring.phys.as_u64();
+        self.write(TDBAL, phys as u32);
+        self.write(TDBAH, (phys >> 32) as u32);
+        self.write(TDLEN, (RING_SIZE * core::mem::size_of::<TxDescriptor>()) as u32);
+        self.write(TDH, 0);
+        self.write(TDT, 0);
+        self.write(TIPG, TIPG_VALUE);
+        self.write(TCTL, TCTL_EN | TCTL_PSP | TCTL_CT | TCTL_COLD);
+    }
+
+    /// Returns the MAC address the device loaded from its EEPROM.
+    pub fn mac_address(&self) -> MacAddr {
+        let low = self.read(RAL0).to_le_bytes();
+        let high = self.read(RAH0).to_le_bytes();
+        MacAddr([low[0], low[1], low[2], low[3], high[0], high[1]])
+    }
+
+    /// Queues an Ethernet frame for transmission.
+    pub fn send(&mut self, packet: &[u8]) -> Result<(), E1000Error> {
+        if packet.len() > BUFFER_SIZE {
+            return Err(E1000Error::PacketTooLarge);
+        }
+
+        let tail = self.read(TDT) as usize;
+        let descriptor = self.tx_ring.descriptor(tail);
+        let mut desc = unsafe { ptr::read_volatile(descriptor) };
+        if desc.status & STATUS_DD == 0 {
+            return Err(E1000Error::TxRingFull);
+        }
+
+        let buffer: *mut u8 = self.tx_ring.buffers[tail].as_mut_ptr();
+        unsafe { ptr::copy_nonoverlapping(packet.as_ptr(), buffer, packet.len()) };
+
+        desc.length = packet.len() as u16;
+        desc.cmd = CMD_EOP | CMD_IFCS | CMD_RS;
+        desc.status = 0;
+        unsafe { ptr::write_volatile(descriptor, desc) };
+
+        // moving the tail hands the descriptor to the device
+        self.write(TDT, ((tail + 1) % RING_SIZE) as u32);
+        Ok(())
+    }
+
+    /// Copies the next received frame into `buf` and returns its length.
+    ///
+    /// Returns 0 if no frame is pending. Frames longer than `buf` are truncated.
+    pub fn recv(&mut self, buf: &mut [u8]) -> usize {
+        let index = (self.read(RDT) as usize + 1) % RING_SIZE;
+        let descriptor = self.rx_ring.descriptor(index);
+        let mut desc = unsafe { ptr::read_volatile(descriptor) };
+        if desc.status & STATUS_DD == 0 {
+            return 0;
+        }
+
+        let len = (desc.length as usize).min(buf.len());
+        let buffer: *const u8 = self.rx_ring.buffers[index].as_ptr();
+        unsafe { ptr::copy_nonoverlapping(buffer, buf.as_mut_ptr(), len) };
+
+        // give the descriptor back to the device
+        desc.status = 0;
+        unsafe { ptr::write_volatile(descriptor, desc) };
+        self.write(RDT, index as u32);
+        len
+    }
+
+    fn read(&self, register: usize) -> u32 {
+        unsafe { ptr::read_volatile((self.base + register).as_ptr::<u32>()) }
+    }
+
+    fn write(&self, register: usize, value: u32) {
+        unsafe { ptr::write_volatile((self.base + register).as_mut_ptr::<u32>(), value) }
+    }
+}
+
+impl NetworkDevice for E1000 {
+    type Error = E1000Error;
+
+    fn mac_address(&self) -> MacAddr {
+        E1000::mac_address(self)
+    }
+
+    fn send(&mut self, packet: &[u8]) -> Result<(), E1000Error> {
+        E1000::send(self, packet)
+    }
+
+    fn recv(&mut self, buf: &mut [u8]) -> usize {
+        E1000::recv(self, buf)
+    }
+}
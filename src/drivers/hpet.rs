@@ -0,0 +1,147 @@
+use core::ptr;
+use x86_64::{
+    structures::paging::{
+        mapper::MapToError, FrameAllocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB,
+    },
+    PhysAddr, VirtAddr,
+};
+use crate::sync::Once;
+use crate::memory::PhysAddrExt;
+
+// virtual address the HPET register block is mapped to
+const HPET_VIRT_ADDR: u64 = 0x_5555_0000_0000;
+
+// register offsets from the IA-PC HPET specification
+const GCAP_ID: usize = 0x000;
+const GEN_CONF: usize = 0x010;
+const MAIN_COUNTER: usize = 0x0F0;
+const TIMER_0_CONF: usize = 0x100;
+const TIMER_0_COMPARATOR: usize = 0x108;
+
+// general configuration: start the main counter
+const ENABLE_CNF: u64 = 1 << 0;
+// timer configuration: periodic mode, periodic capable, allow writing the accumulator
+const TN_TYPE_CNF: u64 = 1 << 3;
+const TN_PER_INT_CAP: u64 = 1 << 4;
+const TN_VAL_SET_CNF: u64 = 1 << 6;
+
+// the specification limits the counter period to 100 ns
+const MAX_PERIOD_FS: u64 = 100_000_000;
+// period of comparator 0: 1 ms in femtoseconds
+const TIMER_0_INTERVAL_FS: u64 = 1_000_000_000_000;
+
+static HPET: Once<Hpet> = Once::new();
+
+/// Errors returned by `init`.
+#[derive(Debug)]
+pub enum HpetError {
+    /// The capabilities register reports a period outside of the valid range.
+    InvalidPeriod(u64),
+    /// Comparator 0 cannot run in periodic mode.
+    NotPeriodicCapable,
+    /// Mapping the register block failed.
+    MapFailed(MapToError<Size4KiB>),
+}
+
+/// A mapped and enabled HPET.
+#[derive(Debug, Clone, Copy)]
+pub struct Hpet {
+    base: VirtAddr,
+    /// Length of one counter tick in femtoseconds.
+    period_fs: u64,
+    /// Number of comparators the HPET provides.
+    timer_count: u8,
+}
+
+/// Maps the HPET registers at `base_phys`, starts the main counter and
+/// puts comparator 0 into periodic mode with a 1 ms period.
+///
+/// Comparator 0 does not raise interrupts yet since the I/O APIC routing is not set up.
+pub fn init(
+    base_phys: PhysAddr,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<Hpet, HpetError> {
+    let page = Page::containing_address(VirtAddr::new(HPET_VIRT_ADDR));
+    let frame = PhysFrame::containing_address(base_phys);
+    // MMIO registers must not be cached
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+    match unsafe { mapper.map_to(page, frame, flags, frame_allocator) } {
+        Ok(flush) => flush.flush(),
+        // the registers are already mapped if init is called again
+        Err(MapToError::PageAlreadyMapped(_)) => {}
+        Err(err) => return Err(HpetError::MapFailed(err)),
+    }
+
+    let base = page.start_address() + base_phys.offset_in_page();
+    let capabilities = unsafe { read_register(base, GCAP_ID) };
+    // the upper 32 bits hold the period, bits 8-12 the number of the last timer
+    let period_fs = capabilities >> 32;
+    if period_fs == 0 || period_fs > MAX_PERIOD_FS {
+        return Err(HpetError::InvalidPeriod(period_fs));
+    }
+    let timer_count = ((capabilities >> 8) & 0x1F) as u8 + 1;
+
+    unsafe {
+        let timer_conf = read_register(base, TIMER_0_CONF);
+        if timer_conf & TN_PER_INT_CAP == 0 {
+            return Err(HpetError::NotPeriodicCapable);
+        }
+
+        // stop the counter while programming the comparator
+        let conf = read_register(base, GEN_CONF);
+        write_register(base, GEN_CONF, conf & !ENABLE_CNF);
+        write_register(base, MAIN_COUNTER, 0);
+
+        // in periodic mode the first write sets the comparator, the second the period
+        let interval = TIMER_0_INTERVAL_FS / period_fs;
+        write_register(base, TIMER_0_CONF, timer_conf | TN_TYPE_CNF | TN_VAL_SET_CNF);
+        write_register(base, TIMER_0_COMPARATOR, interval);
+        write_register(base, TIMER_0_COMPARATOR, interval);
+
+        write_register(base, GEN_CONF, conf | ENABLE_CNF);
+    }
+
+    let hpet = Hpet { base, period_fs, timer_count };
+    Ok(*HPET.call_once(|| hpet))
+}
+
+impl Hpet {
+    /// Returns the current value of the main counter.
+    pub fn read_counter(&self) -> u64 {
+        unsafe { read_register(self.base, MAIN_COUNTER) }
+    }
+
+    /// Returns the length of one counter tick in nanoseconds.
+    pub fn ns_per_tick(&self) -> u64 {
+        self.period_fs / 1_000_000
+    }
+
+    /// Returns the length of one counter tick in femtoseconds.
+    pub fn period_fs(&self) -> u64 {
+        self.period_fs
+    }
+
+    /// Returns the number of comparators.
+    pub fn timer_count(&self) -> u8 {
+        self.timer_count
+    }
+}
+
+/// Returns the current value of the main counter of the HPET set up by `init`.
+pub fn read_counter() -> u64 {
+    HPET.get().expect("HPET not initialized").read_counter()
+}
+
+/// Returns the length of one HPET counter tick in nanoseconds.
+pub fn ns_per_tick() -> u64 {
+    HPET.get().expect("HPET not initialized").ns_per_tick()
+}
+
+unsafe fn read_register(base: VirtAddr, offset: usize) -> u64 {
+    ptr::read_volatile((base + offset).as_ptr::<u64>())
+}
+
+unsafe fn write_register(base: VirtAddr, offset: usize, value: u64) {
+    ptr::write_volatile((base + offset).as_mut_ptr::<u64>(), value)
+}
@@ -0,0 +1,14 @@
+//! Drivers for the legacy PC hardware the kernel talks to directly.
+
+pub mod speaker;
+pub mod cmos;
+pub mod hpet;
+pub mod virtio;
+pub mod e1000;
+pub mod block;
+pub mod ps2_controller;
+pub mod vbe;
+pub mod debug_uart;
+pub mod pit;
+pub mod acpi_poweroff;
+pub mod parallel_port;
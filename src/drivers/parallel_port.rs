@@ -0,0 +1,59 @@
+//! The first parallel port (LPT1), e.g. for a printer in a lab setup.
+//!
+//! Bytes are sent with the classic Centronics handshake: put the byte on the data
+//! lines, pulse STROBE and wait for the printer to acknowledge it.
+
+use crate::io::{io_delay, ports::{Lpt1Control, Lpt1Data, Lpt1Status}};
+
+// status register bits, both lines are active low, so a set bit means inactive
+const STATUS_NOT_BUSY: u8 = 1 << 7;
+const STATUS_NOT_ACK: u8 = 1 << 6;
+
+// control register bits, STROBE is inverted by the port: a set bit pulls the line low
+const CONTROL_STROBE: u8 = 1 << 0;
+
+// number of status polls before giving up on the printer
+const TIMEOUT_POLLS: u32 = 100_000;
+
+/// Errors while sending to the parallel port.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParallelError {
+    /// The printer stayed busy or did not acknowledge the byte, there may be no printer.
+    Timeout,
+}
+
+/// Sends `byte` to the printer on LPT1 and waits until the printer has taken it.
+pub fn lpt_write_byte(byte: u8) -> Result<(), ParallelError> {
+    let mut control = Lpt1Control::new();
+
+    wait_for_status(STATUS_NOT_BUSY, STATUS_NOT_BUSY)?;
+    Lpt1Data::new().write(byte);
+
+    // the printer reads the data lines while STROBE is low
+    let value = control.read();
+    control.write(value | CONTROL_STROBE);
+    io_delay();
+    control.write(value & !CONTROL_STROBE);
+
+    // ACK goes low once the printer has the byte and high again when it is ready for the next
+    wait_for_status(STATUS_NOT_ACK, 0)?;
+    wait_for_status(STATUS_NOT_ACK, STATUS_NOT_ACK)
+}
+
+/// Sends `data` to the printer on LPT1 and returns how many bytes were sent,
+/// which is less than `data.len()` if the printer stopped responding.
+pub fn lpt_send(data: &[u8]) -> usize {
+    data.iter().take_while(|&&byte| lpt_write_byte(byte).is_ok()).count()
+}
+
+// polls the status register until the bits in `mask` equal `expected`
+fn wait_for_status(mask: u8, expected: u8) -> Result<(), ParallelError> {
+    let mut status = Lpt1Status::new();
+    for _ in 0..TIMEOUT_POLLS {
+        if status.read() & mask == expected {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err(ParallelError::Timeout)
+}
@@ -0,0 +1,64 @@
+//! Busy-wait delays measured with the programmable interval timer.
+//!
+//! The output of channel 0 cannot be read back, so the delays count down PIT
+//! channel 2 instead, whose output is visible in bit 5 of system control port B.
+//! Channel 0 and the timer interrupt keep running unchanged. Channel 2 also drives
+//! the speaker, so a tone playing through `speaker` is cut off by a sleep.
+
+use core::hint::spin_loop;
+use crate::io::ports::{PitChannel2, PitCommand, SystemControlB};
+use crate::time::PIT_FREQUENCY_HZ;
+
+// bits of system control port B
+const GATE_2: u8 = 1 << 0;
+const SPEAKER: u8 = 1 << 1;
+const OUT_2: u8 = 1 << 5;
+
+/// Spins for `ticks` periods of the PIT input clock (about 0.84 µs each).
+///
+/// Works with interrupts disabled, so it can be used early during driver initialization.
+pub fn sleep_ticks(ticks: u16) {
+    if ticks == 0 {
+        return;
+    }
+
+    let mut control = SystemControlB::new();
+    let mut command = PitCommand::new();
+    let mut channel_2 = PitChannel2::new();
+    let saved = control.read();
+
+    // stop channel 2 and disconnect the speaker while the count is loaded
+    control.write(saved & !(GATE_2 | SPEAKER));
+    // channel 2, access mode lobyte/hibyte, mode 0 (interrupt on terminal count), binary
+    command.write(0b1011_0000);
+    channel_2.write(ticks as u8);
+    channel_2.write((ticks >> 8) as u8);
+
+    // opening the gate starts the count, the output goes high once it reaches 0
+    control.write((saved & !SPEAKER) | GATE_2);
+    while control.read() & OUT_2 == 0 {
+        spin_loop();
+    }
+    control.write(saved);
+}
+
+/// Spins for at least `ms` milliseconds.
+pub fn sleep_ms(ms: u32) {
+    // rounded up so the delay is never too short
+    let mut ticks = (u64::from(ms) * PIT_FREQUENCY_HZ).div_ceil(1000);
+    while ticks > 0 {
+        let chunk = ticks.min(u64::from(u16::MAX));
+        sleep_ticks(chunk as u16);
+        ticks -= chunk;
+    }
+}
+
+#[test_case]
+fn test_sleep_ms_waits() {
+    use crate::interrupts::ticks;
+
+    // 120 ms cover at least one timer interrupt at the default rate of 18.2 Hz
+    let start = ticks();
+    sleep_ms(120);
+    assert!(ticks() > start);
+}
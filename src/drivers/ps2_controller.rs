@@ -0,0 +1,189 @@
+//! The 8042 PS/2 controller the keyboard (and a mouse) are attached to.
+
+use crate::io::{io_delay, ports::{KbdData, KbdStatus}};
+use crate::sync::Once;
+
+// status register bits
+const STATUS_OUTPUT_FULL: u8 = 1 << 0;
+const STATUS_INPUT_FULL: u8 = 1 << 1;
+
+// controller commands, written to the command port
+const CMD_READ_CONFIG: u8 = 0x20;
+const CMD_WRITE_CONFIG: u8 = 0x60;
+const CMD_DISABLE_PORT_2: u8 = 0xA7;
+const CMD_ENABLE_PORT_2: u8 = 0xA8;
+const CMD_TEST_PORT_2: u8 = 0xA9;
+const CMD_SELF_TEST: u8 = 0xAA;
+const CMD_TEST_PORT_1: u8 = 0xAB;
+const CMD_DISABLE_PORT_1: u8 = 0xAD;
+const CMD_ENABLE_PORT_1: u8 = 0xAE;
+
+// configuration byte bits
+const CONFIG_PORT_1_IRQ: u8 = 1 << 0;
+const CONFIG_PORT_2_IRQ: u8 = 1 << 1;
+const CONFIG_PORT_2_CLOCK_DISABLED: u8 = 1 << 5;
+const CONFIG_TRANSLATION: u8 = 1 << 6;
+
+// keyboard commands and responses, written to and read from the data port
+/// Resets the keyboard, which answers with `KEYBOARD_ACK` and then `KEYBOARD_SELF_TEST_PASSED`.
+pub const KEYBOARD_RESET: u8 = 0xFF;
+/// The keyboard accepted a command.
+pub const KEYBOARD_ACK: u8 = 0xFA;
+/// The keyboard passed its basic assurance test (BAT) after a reset.
+pub const KEYBOARD_SELF_TEST_PASSED: u8 = 0xAA;
+
+const SELF_TEST_PASSED: u8 = 0x55;
+const PORT_TEST_PASSED: u8 = 0x00;
+
+// number of status polls before giving up on the controller
+const TIMEOUT_POLLS: u32 = 100_000;
+
+/// Errors during the initialization of the PS/2 controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ps2Error {
+    /// The controller did not become ready in time, there may be no controller.
+    Timeout,
+    /// The controller self test returned this instead of 0x55.
+    SelfTestFailed(u8),
+    /// The interface test of the first port returned this error code.
+    PortTestFailed(u8),
+}
+
+/// An initialized PS/2 controller.
+#[derive(Debug)]
+pub struct Controller {
+    dual_channel: bool,
+}
+
+static CONTROLLER: Once<Controller> = Once::new();
+
+impl Controller {
+    /// Resets the controller to a known state and enables its ports and interrupts.
+    ///
+    /// Scancode translation is turned off, so the keyboard delivers scancode set 2.
+    /// Must be called with interrupts disabled, otherwise the interrupt handlers
+    /// would steal the responses of the controller.
+    pub fn init() -> Result<&'static Controller, Ps2Error> {
+        // disable both ports so the devices cannot interfere with the setup
+        send_command(CMD_DISABLE_PORT_1)?;
+        send_command(CMD_DISABLE_PORT_2)?;
+
+        // drop any bytes the devices have already sent
+        let mut status = KbdStatus::new();
+        let mut data = KbdData::new();
+        while status.read() & STATUS_OUTPUT_FULL != 0 {
+            data.read();
+        }
+
+        send_command(CMD_READ_CONFIG)?;
+        let mut config = read_data()?;
+        // the clock of the second port was just disabled, if the bit is still clear
+        // there is no second port
+        let maybe_dual_channel = config & CONFIG_PORT_2_CLOCK_DISABLED != 0;
+        config &= !(CONFIG_PORT_1_IRQ | CONFIG_PORT_2_IRQ | CONFIG_TRANSLATION);
+        write_config(config)?;
+
+        send_command(CMD_SELF_TEST)?;
+        match read_data()? {
+            SELF_TEST_PASSED => {}
+            response => return Err(Ps2Error::SelfTestFailed(response)),
+        }
+        // the self test can reset the controller, so restore the configuration
+        write_config(config)?;
+
+        // the second port exists if enabling it clears its clock disable bit
+        let mut dual_channel = false;
+        if maybe_dual_channel {
+            send_command(CMD_ENABLE_PORT_2)?;
+            send_command(CMD_READ_CONFIG)?;
+            dual_channel = read_data()? & CONFIG_PORT_2_CLOCK_DISABLED == 0;
+            send_command(CMD_DISABLE_PORT_2)?;
+        }
+
+        send_command(CMD_TEST_PORT_1)?;
+        match read_data()? {
+            PORT_TEST_PASSED => {}
+            error => return Err(Ps2Error::PortTestFailed(error)),
+        }
+        // a broken second port is not fatal, the keyboard is on the first one
+        if dual_channel {
+            send_command(CMD_TEST_PORT_2)?;
+            dual_channel = read_data()? == PORT_TEST_PASSED;
+        }
+
+        send_command(CMD_ENABLE_PORT_1)?;
+        config |= CONFIG_PORT_1_IRQ;
+        if dual_channel {
+            send_command(CMD_ENABLE_PORT_2)?;
+            config |= CONFIG_PORT_2_IRQ;
+        }
+        write_config(config)?;
+
+        Ok(CONTROLLER.call_once(|| Controller { dual_channel }))
+    }
+
+    /// Returns the controller if `init` has succeeded.
+    pub fn get() -> Option<&'static Controller> {
+        CONTROLLER.get()
+    }
+
+    /// Returns whether a second (mouse) port is available.
+    pub fn has_second_port(&self) -> bool {
+        self.dual_channel
+    }
+
+    /// Reads the byte a device has sent.
+    ///
+    /// Used by the interrupt handlers, which are only called when a byte is available.
+    pub fn read_byte(&self) -> u8 {
+        KbdData::new().read()
+    }
+
+    /// Sends a byte to the keyboard on the first port, e.g. `KEYBOARD_RESET`.
+    ///
+    /// The response arrives through the keyboard interrupt.
+    pub fn send_to_keyboard(&self, byte: u8) -> Result<(), Ps2Error> {
+        wait_for_input_empty()?;
+        KbdData::new().write(byte);
+        Ok(())
+    }
+}
+
+fn wait_for_input_empty() -> Result<(), Ps2Error> {
+    let mut status = KbdStatus::new();
+    for _ in 0..TIMEOUT_POLLS {
+        if status.read() & STATUS_INPUT_FULL == 0 {
+            return Ok(());
+        }
+        core::hint::spin_loop();
+    }
+    Err(Ps2Error::Timeout)
+}
+
+fn send_command(command: u8) -> Result<(), Ps2Error> {
+    wait_for_input_empty()?;
+    // the status port is the command port when written
+    KbdStatus::new().write(command);
+    // old controllers need time to process the command before the status is valid
+    io_delay();
+    Ok(())
+}
+
+fn write_config(config: u8) -> Result<(), Ps2Error> {
+    send_command(CMD_WRITE_CONFIG)?;
+    wait_for_input_empty()?;
+    KbdData::new().write(config);
+    io_delay();
+    Ok(())
+}
+
+fn read_data() -> Result<u8, Ps2Error> {
+    let mut status = KbdStatus::new();
+    for _ in 0..TIMEOUT_POLLS {
+        if status.read() & STATUS_OUTPUT_FULL != 0 {
+            return Ok(KbdData::new().read());
+        }
+        core::hint::spin_loop();
+    }
+    Err(Ps2Error::Timeout)
+}
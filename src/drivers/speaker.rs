@@ -0,0 +1,89 @@
+use crate::io::ports::{PitChannel2, PitCommand, SystemControlB};
+use crate::interrupts::ticks;
+use crate::task::delay::Delay;
+use crate::time::{Duration, PIT_FREQUENCY_HZ};
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// Starts playing a tone of the given frequency until `stop` is called.
+pub fn play(frequency_hz: u32) {
+    // a channel is programmed with a divisor of the PIT frequency,
+    // the divisor is 16 bits wide, so very low frequencies are clamped
+    let divisor = (PIT_FREQUENCY_HZ / u64::from(frequency_hz.max(1))).clamp(1, u64::from(u16::MAX)) as u16;
+
+    let mut command = PitCommand::new();
+    let mut channel_2 = PitChannel2::new();
+    let mut speaker = SystemControlB::new();
+
+    // channel 2, access mode lobyte/hibyte, mode 3 (square wave), binary
+    command.write(0b1011_0110);
+    channel_2.write(divisor as u8);
+    channel_2.write((divisor >> 8) as u8);
+
+    // connect the speaker to channel 2 if it is not already
+    // bit 0 gates PIT channel 2, bit 1 connects its output to the speaker
+    let value = speaker.read();
+    if value & 0b11 != 0b11 {
+        speaker.write(value | 0b11);
+    }
+}
+
+/// Silences the speaker by disconnecting it from PIT channel 2.
+pub fn stop() {
+    let mut speaker = SystemControlB::new();
+    let value = speaker.read();
+    speaker.write(value & !0b11);
+}
+
+// the tick at which the timer interrupt ends a beep of `start_beep`, 0 if none is running
+static BEEP_END_TICK: AtomicU64 = AtomicU64::new(0);
+
+/// Starts a beep at `frequency_hz` that the timer interrupt stops after `duration`.
+///
+/// Returns right away, so unlike `beep` it can be used with interrupts disabled or
+/// locks held. A beep that is still running is replaced.
+pub fn start_beep(frequency_hz: u32, duration: Duration) {
+    // set first, so that a tick in between cannot stop the new tone with the old end
+    BEEP_END_TICK.store(ticks() + duration.as_ticks().max(1), Ordering::Relaxed);
+    play(frequency_hz);
+}
+
+/// Called by the timer interrupt handler on every tick, ends a beep of `start_beep`.
+pub(crate) fn on_tick() {
+    let end = BEEP_END_TICK.load(Ordering::Relaxed);
+    if end != 0 && ticks() >= end {
+        BEEP_END_TICK.store(0, Ordering::Relaxed);
+        stop();
+    }
+}
+
+/// Beeps at `frequency_hz` for `duration_ticks` timer ticks.
+///
+/// Blocks the CPU with `hlt` until the timer has ticked often enough,
+/// so interrupts must be enabled.
+pub fn beep(frequency_hz: u32, duration_ticks: u64) {
+    let end = ticks() + duration_ticks;
+    play(frequency_hz);
+    while ticks() < end {
+        x86_64::instructions::hlt();
+    }
+    stop();
+}
+
+/// Like `beep`, but waits on a `Delay` so other async tasks keep running.
+pub async fn beep_async(frequency_hz: u32, duration: Duration) {
+    play(frequency_hz);
+    Delay::new(duration).await;
+    stop();
+}
+
+#[test_case]
+fn test_start_beep_is_stopped_by_timer() {
+    start_beep(440, Duration::from_ms(1));
+    let end = BEEP_END_TICK.load(Ordering::Relaxed);
+    assert_ne!(end, 0);
+    while ticks() < end {
+        x86_64::instructions::hlt();
+    }
+    assert_eq!(BEEP_END_TICK.load(Ordering::Relaxed), 0);
+    assert_eq!(SystemControlB::new().read() & 0b11, 0);
+}
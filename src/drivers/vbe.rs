@@ -0,0 +1,96 @@
+//! Linear framebuffer set up by the bootloader through the VESA BIOS Extensions.
+
+use crate::boot::multiboot2::Mb2Framebuffer;
+use x86_64::{
+    structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB},
+    PhysAddr, VirtAddr,
+};
+use crate::memory::PhysAddrExt;
+
+// virtual address the framebuffer is mapped to
+const FRAMEBUFFER_VIRT_ADDR: u64 = 0x_5555_0100_0000;
+
+/// A mapped linear framebuffer.
+#[derive(Debug, Clone, Copy)]
+pub struct Framebuffer {
+    pub base: VirtAddr,
+    pub width: u32,
+    pub height: u32,
+    /// Number of bytes between the starts of two rows.
+    pub pitch: u32,
+    /// Bits per pixel, 16, 24 and 32 are supported.
+    pub bpp: u8,
+}
+
+/// Maps the framebuffer described by the bootloader into kernel memory.
+///
+/// The `bootloader` crate only sets up VGA text mode, so the framebuffer information
+/// comes from a Multiboot2 bootloader. Returns `None` if the pixel format is not
+/// supported or mapping fails.
+pub fn init(
+    info: &Mb2Framebuffer,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Option<Framebuffer> {
+    if !matches!(info.bpp, 16 | 24 | 32) {
+        return None;
+    }
+
+    let phys_start = PhysAddr::new(info.addr);
+    let size = u64::from(info.pitch) * u64::from(info.height);
+    let first_frame = PhysFrame::<Size4KiB>::containing_address(phys_start);
+    let last_frame = PhysFrame::containing_address(phys_start + size - 1u64);
+    let first_page = Page::<Size4KiB>::containing_address(VirtAddr::new(FRAMEBUFFER_VIRT_ADDR));
+
+    // video memory must not be cached, or writes may not reach the screen
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+    for (i, frame) in PhysFrame::range_inclusive(first_frame, last_frame).enumerate() {
+        let page = first_page + i as u64;
+        unsafe { mapper.map_to(page, frame, flags, frame_allocator).ok()?.flush() };
+    }
+
+    Some(Framebuffer {
+        base: first_page.start_address() + phys_start.offset_in_page(),
+        width: info.width,
+        height: info.height,
+        pitch: info.pitch,
+        bpp: info.bpp,
+    })
+}
+
+impl Framebuffer {
+    /// Sets the pixel at (`x`, `y`) to the given color. Pixels outside of the screen are ignored.
+    pub fn write_pixel(&mut self, x: u32, y: u32, r: u8, g: u8, b: u8) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let bytes_per_pixel = u64::from(self.bpp / 8);
+        let offset = u64::from(y) * u64::from(self.pitch) + u64::from(x) * bytes_per_pixel;
+        let pixel: *mut u8 = (self.base + offset).as_mut_ptr();
+
+        unsafe {
+            match self.bpp {
+                // 5:6:5 RGB
+                16 => {
+                    let value = (u16::from(r) >> 3) << 11 | (u16::from(g) >> 2) << 5 | u16::from(b) >> 3;
+                    (pixel as *mut u16).write_volatile(value);
+                }
+                // the color components are stored as blue, green, red
+                _ => {
+                    pixel.write_volatile(b);
+                    pixel.add(1).write_volatile(g);
+                    pixel.add(2).write_volatile(r);
+                }
+            }
+        }
+    }
+
+    /// Fills the whole screen with the given color.
+    pub fn clear(&mut self, r: u8, g: u8, b: u8) {
+        for y in 0..self.height {
+            for x in 0..self.width {
+                self.write_pixel(x, y, r, g, b);
+            }
+        }
+    }
+}
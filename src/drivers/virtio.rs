@@ -0,0 +1,86 @@
+use core::ptr;
+use spin::Mutex;
+use x86_64::{
+    structures::paging::{FrameAllocator, Mapper, Page, PageTableFlags, PhysFrame, Size4KiB},
+    PhysAddr, VirtAddr,
+};
+use crate::memory::PhysAddrExt;
+
+// "virt" in little endian
+const VIRTIO_MAGIC: u32 = 0x7472_6976;
+
+// register offsets of the VirtIO MMIO transport
+const MAGIC_VALUE: usize = 0x000;
+const DEVICE_ID: usize = 0x008;
+
+// probed register blocks are mapped one page after another starting here
+const VIRTIO_VIRT_BASE: u64 = 0x_5555_0010_0000;
+static NEXT_VIRT_ADDR: Mutex<u64> = Mutex::new(VIRTIO_VIRT_BASE);
+
+/// The type of a VirtIO device as reported in its device ID register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VirtioDeviceId {
+    NetworkDevice,
+    BlockDevice,
+    ConsoleDevice,
+    /// A device type we have no driver for.
+    Other(u32),
+}
+
+impl From<u32> for VirtioDeviceId {
+    fn from(id: u32) -> Self {
+        match id {
+            1 => VirtioDeviceId::NetworkDevice,
+            2 => VirtioDeviceId::BlockDevice,
+            3 => VirtioDeviceId::ConsoleDevice,
+            other => VirtioDeviceId::Other(other),
+        }
+    }
+}
+
+/// A VirtIO device discovered by `probe`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VirtioDevice {
+    pub id: VirtioDeviceId,
+    /// Virtual address of the device's MMIO register block.
+    pub base: VirtAddr,
+}
+
+/// Maps the MMIO region at `base` and checks whether a VirtIO device lives there.
+///
+/// Returns `None` if the region has no VirtIO magic value or no device attached
+/// (device ID 0), in which case the mapping is removed again.
+pub fn probe(
+    base: PhysAddr,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Option<VirtioDevice> {
+    let mut next_virt_addr = NEXT_VIRT_ADDR.lock();
+
+    let page: Page<Size4KiB> = Page::containing_address(VirtAddr::new(*next_virt_addr));
+    let frame = PhysFrame::containing_address(base);
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+    unsafe { mapper.map_to(page, frame, flags, frame_allocator) }.ok()?.flush();
+
+    let regs = page.start_address() + base.offset_in_page();
+    let magic = unsafe { read_register(regs, MAGIC_VALUE) };
+    let device_id = unsafe { read_register(regs, DEVICE_ID) };
+
+    if magic != VIRTIO_MAGIC || device_id == 0 {
+        if let Ok((_, flush)) = mapper.unmap(page) {
+            flush.flush();
+        }
+        return None;
+    }
+
+    // keep the mapping and hand out the next page to the next device
+    *next_virt_addr += 4096;
+    Some(VirtioDevice {
+        id: device_id.into(),
+        base: regs,
+    })
+}
+
+unsafe fn read_register(base: VirtAddr, offset: usize) -> u32 {
+    ptr::read_volatile((base + offset).as_ptr::<u32>())
+}
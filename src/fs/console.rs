@@ -0,0 +1,59 @@
+use super::{vfs::VfsNode, FsError};
+use x86_64::instructions::interrupts;
+
+/// Standard input. Nothing feeds it yet, so reads always report end of file.
+pub struct Stdin;
+
+/// Writes to the VGA text buffer.
+pub struct VgaOutput;
+
+/// Writes to the first serial port.
+pub struct SerialOutput;
+
+impl VfsNode for Stdin {
+    fn read(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize, FsError> {
+        Ok(0)
+    }
+
+    fn write(&self, _offset: usize, _buf: &[u8]) -> Result<usize, FsError> {
+        Err(FsError::NotSupported)
+    }
+}
+
+impl VfsNode for VgaOutput {
+    fn read(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize, FsError> {
+        Err(FsError::NotSupported)
+    }
+
+    fn write(&self, _offset: usize, buf: &[u8]) -> Result<usize, FsError> {
+        // see explanation in vga_buffer.rs
+        interrupts::without_interrupts(|| {
+            let mut writer = crate::vga_buffer::WRITER.lock();
+            for &byte in buf {
+                match byte {
+                    0x20..=0x7e | b'\n' => writer.write_byte(byte),
+                    _ => writer.write_byte(0xfe),
+                }
+            }
+        });
+        Ok(buf.len())
+    }
+}
+
+impl VfsNode for SerialOutput {
+    fn read(&self, _offset: usize, _buf: &mut [u8]) -> Result<usize, FsError> {
+        Err(FsError::NotSupported)
+    }
+
+    fn write(&self, _offset: usize, buf: &[u8]) -> Result<usize, FsError> {
+        interrupts::without_interrupts(|| {
+            use crate::drivers::debug_uart::DebugOutput;
+
+            let mut serial = crate::serial::SERIAL1.lock();
+            for &byte in buf {
+                serial.write_byte(byte);
+            }
+        });
+        Ok(buf.len())
+    }
+}
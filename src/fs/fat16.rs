@@ -0,0 +1,276 @@
+use super::{vfs::VfsNode, FsError};
+
+// size of a directory entry
+const DIR_ENTRY_LEN: usize = 32;
+// directory entry attributes
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+// first name byte of unused and deleted entries
+const ENTRY_END: u8 = 0x00;
+const ENTRY_DELETED: u8 = 0xE5;
+// FAT entries at or above this value mark the end of a cluster chain
+const END_OF_CHAIN: u16 = 0xFFF8;
+
+/// The BIOS Parameter Block from the first sector of the volume.
+#[derive(Debug, Clone, Copy)]
+pub struct Bpb {
+    pub bytes_per_sector: u16,
+    pub sectors_per_cluster: u8,
+    pub reserved_sectors: u16,
+    pub fat_count: u8,
+    pub root_entry_count: u16,
+    pub total_sectors: u32,
+    pub sectors_per_fat: u16,
+}
+
+impl Bpb {
+    /// Parses the BPB from the boot sector.
+    pub fn parse(sector: &[u8]) -> Result<Bpb, FsError> {
+        if sector.len() < 512 || sector[510] != 0x55 || sector[511] != 0xAA {
+            return Err(FsError::InvalidFilesystem);
+        }
+        let read_u16 = |offset: usize| u16::from_le_bytes([sector[offset], sector[offset + 1]]);
+
+        // the 16 bit total is 0 if the volume has more than 65535 sectors
+        let total_sectors = match read_u16(19) {
+            0 => u32::from_le_bytes([sector[32], sector[33], sector[34], sector[35]]),
+            total => u32::from(total),
+        };
+        let bpb = Bpb {
+            bytes_per_sector: read_u16(11),
+            sectors_per_cluster: sector[13],
+            reserved_sectors: read_u16(14),
+            fat_count: sector[16],
+            root_entry_count: read_u16(17),
+            total_sectors,
+            sectors_per_fat: read_u16(22),
+        };
+        if bpb.bytes_per_sector < 512 || bpb.sectors_per_cluster == 0
+            || bpb.fat_count == 0 || bpb.sectors_per_fat == 0
+        {
+            return Err(FsError::InvalidFilesystem);
+        }
+        Ok(bpb)
+    }
+
+    fn bytes_per_cluster(&self) -> usize {
+        usize::from(self.bytes_per_sector) * usize::from(self.sectors_per_cluster)
+    }
+
+    fn fat_start(&self) -> usize {
+        usize::from(self.reserved_sectors) * usize::from(self.bytes_per_sector)
+    }
+
+    fn root_dir_start(&self) -> usize {
+        let fat_len = usize::from(self.fat_count) * usize::from(self.sectors_per_fat);
+        self.fat_start() + fat_len * usize::from(self.bytes_per_sector)
+    }
+
+    fn data_start(&self) -> usize {
+        self.root_dir_start() + usize::from(self.root_entry_count) * DIR_ENTRY_LEN
+    }
+}
+
+/// A FAT16 volume whose complete image is in memory.
+pub struct Fat16 {
+    bpb: Bpb,
+    data: &'static [u8],
+}
+
+/// An open file on a FAT16 volume.
+#[derive(Debug, Clone, Copy)]
+pub struct Fat16File {
+    start_cluster: u16,
+    size: u32,
+    // the first FAT, a little-endian u16 per cluster
+    fat: &'static [u8],
+    // the data region, starting with cluster 2
+    clusters: &'static [u8],
+    cluster_size: usize,
+}
+
+impl Fat16 {
+    /// Parses the volume in `data`.
+    pub fn new(data: &'static [u8]) -> Result<Fat16, FsError> {
+        let bpb = Bpb::parse(data)?;
+        if data.len() < bpb.data_start() {
+            return Err(FsError::InvalidFilesystem);
+        }
+        Ok(Fat16 { bpb, data })
+    }
+
+    pub fn bpb(&self) -> &Bpb {
+        &self.bpb
+    }
+
+    /// Opens the file at `path` in the root directory, e.g. `/KERNEL.CFG`.
+    ///
+    /// Subdirectories are not supported and names must fit the 8.3 format.
+    pub fn open(&self, path: &str) -> Result<Fat16File, FsError> {
+        let name = short_name(path.trim_start_matches('/'))?;
+
+        let root_dir = &self.data[self.bpb.root_dir_start()..self.bpb.data_start()];
+        for entry in root_dir.chunks_exact(DIR_ENTRY_LEN) {
+            match entry[0] {
+                ENTRY_END => break,
+                ENTRY_DELETED => continue,
+                _ => {}
+            }
+            let attributes = entry[11];
+            // long file name entries have the volume ID bit set as well
+            if attributes & ATTR_VOLUME_ID != 0 || entry[..11] != name {
+                continue;
+            }
+            if attributes & ATTR_DIRECTORY != 0 {
+                return Err(FsError::IsDirectory);
+            }
+
+            return Ok(Fat16File {
+                start_cluster: u16::from_le_bytes([entry[26], entry[27]]),
+                size: u32::from_le_bytes([entry[28], entry[29], entry[30], entry[31]]),
+                fat: self.fat(),
+                clusters: &self.data[self.bpb.data_start()..],
+                cluster_size: self.bpb.bytes_per_cluster(),
+            });
+        }
+        Err(FsError::NotFound)
+    }
+
+    /// Returns the bytes of the first FAT.
+    fn fat(&self) -> &'static [u8] {
+        let start = self.bpb.fat_start();
+        let len = usize::from(self.bpb.sectors_per_fat) * usize::from(self.bpb.bytes_per_sector);
+        &self.data[start..start + len]
+    }
+}
+
+impl Fat16File {
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    /// Copies file content starting at `offset` into `buf` and returns the number of bytes read.
+    ///
+    /// Returns 0 at the end of the file or if the cluster chain is broken.
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> usize {
+        let size = self.size as usize;
+        if offset >= size {
+            return 0;
+        }
+        let end = size.min(offset + buf.len());
+
+        // skip the clusters before `offset`
+        let mut cluster = self.start_cluster;
+        for _ in 0..offset / self.cluster_size {
+            match self.next_cluster(cluster) {
+                Some(next) => cluster = next,
+                None => return 0,
+            }
+        }
+
+        let mut position = offset;
+        while position < end {
+            let cluster_offset = position % self.cluster_size;
+            let len = (self.cluster_size - cluster_offset).min(end - position);
+            let start = match usize::from(cluster).checked_sub(2) {
+                Some(index) => index * self.cluster_size + cluster_offset,
+                None => break,
+            };
+            let Some(source) = self.clusters.get(start..start + len) else { break };
+            buf[position - offset..position - offset + len].copy_from_slice(source);
+            position += len;
+
+            if position < end {
+                match self.next_cluster(cluster) {
+                    Some(next) => cluster = next,
+                    None => break,
+                }
+            }
+        }
+        position - offset
+    }
+
+    fn next_cluster(&self, cluster: u16) -> Option<u16> {
+        // the image is only guaranteed to be byte aligned, so the entries are decoded bytewise
+        let entry = self.fat.chunks_exact(2).nth(usize::from(cluster))?;
+        match u16::from_le_bytes([entry[0], entry[1]]) {
+            next if !(2..END_OF_CHAIN).contains(&next) => None,
+            next => Some(next),
+        }
+    }
+}
+
+impl VfsNode for Fat16File {
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, FsError> {
+        Ok(Fat16File::read(self, offset, buf))
+    }
+
+    fn write(&self, _offset: usize, _buf: &[u8]) -> Result<usize, FsError> {
+        Err(FsError::NotSupported)
+    }
+}
+
+/// Converts a file name like `hello.txt` to its padded 8.3 form `HELLO   TXT`.
+fn short_name(name: &str) -> Result<[u8; 11], FsError> {
+    let (base, extension) = name.split_once('.').unwrap_or((name, ""));
+    if base.is_empty() || base.len() > 8 || extension.len() > 3 || name.contains('/') {
+        return Err(FsError::InvalidPath);
+    }
+
+    let mut short = [b' '; 11];
+    for (i, byte) in base.bytes().enumerate() {
+        short[i] = byte.to_ascii_uppercase();
+    }
+    for (i, byte) in extension.bytes().enumerate() {
+        short[8 + i] = byte.to_ascii_uppercase();
+    }
+    Ok(short)
+}
+
+#[test_case]
+fn test_read_across_clusters() {
+    use alloc::{boxed::Box, vec};
+
+    // boot sector, one FAT sector, one root directory sector and three data clusters
+    let mut image = vec![0u8; 512 * 6];
+    image[11..13].copy_from_slice(&512u16.to_le_bytes());
+    image[13] = 1; // sectors per cluster
+    image[14..16].copy_from_slice(&1u16.to_le_bytes()); // reserved sectors
+    image[16] = 1; // FATs
+    image[17..19].copy_from_slice(&16u16.to_le_bytes()); // root entries
+    image[19..21].copy_from_slice(&6u16.to_le_bytes()); // total sectors
+    image[22..24].copy_from_slice(&1u16.to_le_bytes()); // sectors per FAT
+    image[510] = 0x55;
+    image[511] = 0xAA;
+
+    // FAT: cluster 2 -> cluster 3 -> end of chain
+    image[512 + 4..512 + 6].copy_from_slice(&3u16.to_le_bytes());
+    image[512 + 6..512 + 8].copy_from_slice(&0xFFFFu16.to_le_bytes());
+
+    // root directory entry for a 600 byte file
+    let entry = &mut image[1024..1024 + DIR_ENTRY_LEN];
+    entry[..11].copy_from_slice(b"HELLO   TXT");
+    entry[26..28].copy_from_slice(&2u16.to_le_bytes());
+    entry[28..32].copy_from_slice(&600u32.to_le_bytes());
+
+    // fill the file content with its offsets modulo 251
+    for i in 0..600 {
+        image[1536 + i] = (i % 251) as u8;
+    }
+
+    // start the image at an odd address, the FAT entries must not need alignment
+    let mut padded = vec![0u8];
+    padded.extend_from_slice(&image);
+    let image: &'static [u8] = &Box::leak(padded.into_boxed_slice())[1..];
+    let fs = Fat16::new(image).unwrap();
+    let file = fs.open("/hello.txt").unwrap();
+    assert_eq!(file.size(), 600);
+
+    let mut buf = [0u8; 16];
+    assert_eq!(file.read(505, &mut buf), 16);
+    for (i, byte) in buf.iter().enumerate() {
+        assert_eq!(*byte, ((505 + i) % 251) as u8);
+    }
+    assert_eq!(file.read(590, &mut buf), 10);
+    assert_eq!(fs.open("/missing.txt").unwrap_err(), FsError::NotFound);
+}
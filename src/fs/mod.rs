@@ -0,0 +1,20 @@
+//! Filesystem drivers.
+
+pub mod fat16;
+pub mod vfs;
+pub mod console;
+
+/// Errors returned by filesystem operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    /// No file exists at the given path.
+    NotFound,
+    /// The path cannot be represented on this filesystem.
+    InvalidPath,
+    /// The on-disk structures are corrupted or not of the expected type.
+    InvalidFilesystem,
+    /// The path refers to a directory where a file was expected.
+    IsDirectory,
+    /// The node does not support the operation, e.g. writing to a read-only file.
+    NotSupported,
+}
@@ -0,0 +1,31 @@
+use super::{fat16::Fat16, FsError};
+use crate::sync::Once;
+use alloc::sync::Arc;
+
+/// A file-like object that can be referenced by a file descriptor.
+pub trait VfsNode: Send + Sync {
+    /// Reads into `buf` starting at `offset` and returns the number of bytes read.
+    fn read(&self, offset: usize, buf: &mut [u8]) -> Result<usize, FsError>;
+
+    /// Writes `buf` starting at `offset` and returns the number of bytes written.
+    fn write(&self, offset: usize, buf: &[u8]) -> Result<usize, FsError>;
+}
+
+// the filesystem mounted at `/`
+static ROOT: Once<Fat16> = Once::new();
+
+/// Mounts `fs` as the root filesystem.
+///
+/// Only the first call has an effect, the root cannot be replaced once mounted.
+pub fn mount_root(fs: Fat16) {
+    ROOT.call_once(|| fs);
+}
+
+/// Resolves an absolute path to the node it refers to.
+pub fn resolve(path: &str) -> Result<Arc<dyn VfsNode>, FsError> {
+    if !path.starts_with('/') {
+        return Err(FsError::InvalidPath);
+    }
+    let root = ROOT.get().ok_or(FsError::NotFound)?;
+    Ok(Arc::new(root.open(path)?))
+}
@@ -1,18 +1,25 @@
 use x86_64::VirtAddr;
 use x86_64::structures::tss::TaskStateSegment;
 use lazy_static::lazy_static;
-use x86_64::structures::gdt::{GlobalDescriptorTable, Descriptor, SegmentSelector};
+use spin::Mutex;
+use x86_64::structures::gdt::{GlobalDescriptorTable, Descriptor, DescriptorFlags, SegmentSelector};
 
 // 0th Interrupt Stack Table (IST) entry is used for handling double faults
 pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
 
 // Initialize the Task State Segment (TSS) once, which cannot be done at compile time
+// the TSS is behind a Mutex because its stacks are replaced after boot,
+// the CPU reads it directly from memory, so the lock only guards our writes
 lazy_static! {
-    static ref TSS: TaskStateSegment = {
+    static ref TSS: Mutex<TaskStateSegment> = {
         // Create a new Task State Segment
         let mut tss = TaskStateSegment::new();
         
         // Allocate a dedicated stack for the double fault handler
+        // this static stack has no guard page, it is only used until the memory
+        // management is up and `set_double_fault_stack` replaces it with a `KernelStack`,
+        // a `KernelStack` cannot be used here because `init` runs before paging and the
+        // frame allocator are set up, and a double fault before that still needs a stack
         tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
             // Define the stack size (5 pages, each 4096 bytes)
             const STACK_SIZE: usize = 4096 * 5;
@@ -23,7 +30,7 @@ lazy_static! {
             let stack_end = stack_start + STACK_SIZE;
             stack_end // Set the end of the stack in the IST entry
         };
-        tss
+        Mutex::new(tss)
     };
 }
 
@@ -35,34 +42,180 @@ lazy_static! {
         
         // Add a kernel code segment descriptor to the GDT
         let code_selector = gdt.add_entry(Descriptor::kernel_code_segment());
+        // Add a kernel data segment descriptor for SS and the other data segment registers
+        let data_selector = gdt.add_entry(Descriptor::kernel_data_segment());
+
+        // `sysretq` expects the user segments in this order, see `setup_sysretq_selectors`:
+        // a 32 bit code segment, the data segment at +8 and the 64 bit code segment at +16
+        let user_code32_selector = gdt.add_entry(Descriptor::UserSegment(DescriptorFlags::USER_CODE32.bits()));
+        let user_data_selector = gdt.add_entry(Descriptor::user_data_segment());
+        let user_code_selector = gdt.add_entry(Descriptor::user_code_segment());
         
         // Add the TSS segment descriptor to the GDT
-        let tss_selector = gdt.add_entry(Descriptor::tss_segment(&TSS));
+        // the TSS lives in a static, so its address stays valid after the lock is released
+        let tss: *const TaskStateSegment = &*TSS.lock();
+        let tss_selector = gdt.add_entry(unsafe { Descriptor::tss_segment_unchecked(tss) });
         
         // Return the GDT with the associated selectors for code and TSS segments
-        (gdt, Selectors { code_selector, tss_selector })
+        (gdt, Selectors {
+            code_selector,
+            data_selector,
+            user_code32_selector,
+            user_data_selector,
+            user_code_selector,
+            tss_selector,
+        })
     };
 }
 
-// A struct to hold the segment selectors for code and TSS segments
+// A struct to hold the segment selectors for code, data and TSS segments
 struct Selectors {
     code_selector: SegmentSelector,
+    data_selector: SegmentSelector,
+    user_code32_selector: SegmentSelector,
+    user_data_selector: SegmentSelector,
+    user_code_selector: SegmentSelector,
     tss_selector: SegmentSelector,
 }   
 
+/// The selector fields of the STAR MSR, which `syscall` and `sysretq` load the segments from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StarSelectors {
+    /// `syscall` loads CS with this and SS with this + 8, the kernel data segment.
+    pub kernel_cs: u16,
+    /// `sysretq` loads SS with this + 8 and CS with this + 16, the user data and
+    /// 64 bit code segments. Has RPL 3.
+    pub user_cs32: u16,
+}
+
+/// Returns the selectors for the STAR MSR.
+pub fn star_selectors() -> StarSelectors {
+    StarSelectors {
+        kernel_cs: GDT.1.code_selector.0,
+        user_cs32: GDT.1.user_code32_selector.0,
+    }
+}
+
+/// Returns the selectors of the user code and data segments, e.g. for entering ring 3 with `iretq`.
+pub fn user_selectors() -> (SegmentSelector, SegmentSelector) {
+    (GDT.1.user_code_selector, GDT.1.user_data_selector)
+}
+
+/// Writes the selectors of `star_selectors` to the STAR MSR, so that `syscall` and
+/// `sysretq` switch to the segments of this GDT.
+pub fn setup_sysretq_selectors() {
+    use x86_64::registers::model_specific::Star;
+
+    let selectors = star_selectors();
+    // the GDT places the segments at the offsets the instructions expect
+    unsafe { Star::write_raw(selectors.user_cs32, selectors.kernel_cs) };
+}
+
 /// Initializes the GDT and loads the TSS by setting the appropriate segment registers
 pub fn init() {
-    use x86_64::instructions::segmentation::{CS, Segment};
     use x86_64::instructions::tables::load_tss;
 
     // Load the GDT into the CPU's GDTR register
     GDT.0.load();
 
     unsafe {
-        // Set the code segment register (CS) to the GDT's code segment selector
-        CS::set_reg(GDT.1.code_selector);
+        // Replace the bootloader's selectors in all segment registers with ours
+        reload_segments(GDT.1.code_selector, GDT.1.data_selector);
         
         // Load the Task State Segment (TSS) by setting the TSS segment selector
         load_tss(GDT.1.tss_selector);
     }
+
+    setup_sysretq_selectors();
+}
+
+/// Loads `code_sel` into CS and `data_sel` into DS, ES, FS, GS and SS,
+/// e.g. after the GDT was replaced or changed.
+///
+/// CS cannot be the target of a `mov`, so it is reloaded with a far return to the
+/// next instruction. The FS and GS base addresses are preserved, loading the selectors
+/// would otherwise reset them to the base of the descriptor.
+///
+/// This function is unsafe because the selectors must refer to valid code and data
+/// segments of the loaded GDT, otherwise the CPU raises a general protection fault.
+pub unsafe fn reload_segments(code_sel: SegmentSelector, data_sel: SegmentSelector) {
+    use core::arch::asm;
+    use x86_64::registers::model_specific::{FsBase, GsBase};
+
+    let fs_base = FsBase::read();
+    let gs_base = GsBase::read();
+
+    asm!(
+        "push {code}",
+        "lea {tmp}, [rip + 2f]",
+        "push {tmp}",
+        "retfq",
+        "2:",
+        "mov ds, {data:x}",
+        "mov es, {data:x}",
+        "mov fs, {data:x}",
+        "mov gs, {data:x}",
+        "mov ss, {data:x}",
+        code = in(reg) u64::from(code_sel.0),
+        data = in(reg) data_sel.0,
+        tmp = out(reg) _,
+        options(preserves_flags),
+    );
+
+    FsBase::write(fs_base);
+    GsBase::write(gs_base);
+}
+
+/// Switches the double fault handler to the stack ending at `stack_top`,
+/// usually the top of a `memory::KernelStack`.
+///
+/// The stack must stay mapped for as long as the TSS is in use.
+pub fn set_double_fault_stack(stack_top: VirtAddr) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        TSS.lock().interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = stack_top;
+    });
+}
+
+/// Sets `rsp0` of the TSS, the stack the CPU switches to when an interrupt or exception
+/// arrives in ring 3. Has to be called with the kernel stack top of the next process
+/// on every context switch.
+///
+/// The stack must stay mapped for as long as it is installed.
+pub fn set_rsp0(stack_top: VirtAddr) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        TSS.lock().privilege_stack_table[0] = stack_top;
+    });
+}
+
+/// Returns the stack top installed with `set_rsp0`.
+pub fn rsp0() -> VirtAddr {
+    x86_64::instructions::interrupts::without_interrupts(|| TSS.lock().privilege_stack_table[0])
+}
+
+#[test_case]
+fn test_segments_use_kernel_selectors() {
+    use x86_64::instructions::segmentation::{Segment, CS, DS, SS};
+
+    assert_eq!(CS::get_reg(), GDT.1.code_selector);
+    assert_eq!(DS::get_reg(), GDT.1.data_selector);
+    assert_eq!(SS::get_reg(), GDT.1.data_selector);
+}
+
+#[test_case]
+fn test_star_selects_user_segments() {
+    use x86_64::registers::model_specific::Star;
+    use x86_64::PrivilegeLevel;
+
+    let (sysret_cs, sysret_ss, syscall_cs, syscall_ss) = Star::read();
+    assert_eq!((sysret_cs, sysret_ss), user_selectors());
+    assert_eq!((syscall_cs, syscall_ss), (GDT.1.code_selector, GDT.1.data_selector));
+    assert_eq!(sysret_cs.rpl(), PrivilegeLevel::Ring3);
+}
+
+#[test_case]
+fn test_set_rsp0() {
+    let previous = rsp0();
+    set_rsp0(VirtAddr::new(0x5555_8000_1000));
+    assert_eq!(rsp0(), VirtAddr::new(0x5555_8000_1000));
+    set_rsp0(previous);
 }
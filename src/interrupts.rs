@@ -1,9 +1,19 @@
-use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
-use crate::{gdt, print, println, hlt_loop};
+use x86_64::structures::idt::{
+    DivergingHandlerFunc, HandlerFunc, HandlerFuncType, InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode,
+};
+use x86_64::{PrivilegeLevel, VirtAddr};
+use crate::{gdt, println, println_from_interrupt, hlt_loop};
+#[cfg(feature = "timer_dots")]
+use crate::print_from_interrupt;
+use crate::debug::{self, ExceptionContext};
+use crate::cpu::KernelFeatures;
 
 use lazy_static::lazy_static;
 use pic8259::ChainedPics;
 use spin;
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::fmt;
+use alloc::collections::BTreeMap;
 
 // Initialize the Programmable Interrupt Controller (PIC) once
 // setting the offsets for the pic to range from 32 to 47
@@ -23,39 +33,312 @@ pub static PICS: spin::Mutex<ChainedPics> =
 lazy_static! {
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
-        idt.breakpoint.set_handler_fn(breakpoint_handler);
+        // the fault handlers go through entry stubs that save all registers
         unsafe {
-            idt.double_fault
-                .set_handler_fn(double_fault_handler)
-                .set_stack_index(gdt::DOUBLE_FAULT_IST_INDEX);
+            IdtEntryBuilder::new(breakpoint_handler as HandlerFunc).install(&mut idt, 3);
+            IdtEntryBuilder::from_addr(debug::entry_address(double_fault_entry))
+                .with_ist(gdt::DOUBLE_FAULT_IST_INDEX)
+                .install(&mut idt, 8);
+            IdtEntryBuilder::from_addr(debug::entry_address(page_fault_entry))
+                .install(&mut idt, 14);
+            IdtEntryBuilder::new(machine_check_handler as DivergingHandlerFunc).install(&mut idt, 18);
+            IdtEntryBuilder::new(timer_interrupt_handler as HandlerFunc)
+                .install(&mut idt, InterruptIndex::Timer.as_u8());
+            IdtEntryBuilder::new(keyboard_interrupt_handler as HandlerFunc)
+                .install(&mut idt, InterruptIndex::Keyboard.as_u8());
         }
-        // idt implements Index trait so we can use it as an array
-        idt[InterruptIndex::Timer.as_usize()]
-            .set_handler_fn(timer_interrupt_handler);
-        idt[InterruptIndex::Keyboard.as_usize()]
-            .set_handler_fn(keyboard_interrupt_handler);
-        idt.page_fault.set_handler_fn(page_fault_handler);
         idt
     };
 }
 
+/// The privilege level that is allowed to raise an interrupt with the `int` instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DplLevel {
+    /// Only ring 0, other rings get a general protection fault.
+    Kernel,
+    /// Every ring, e.g. for `int3` breakpoints or system calls from user mode.
+    User,
+}
+
+/// Configures an IDT entry in a single call, by default without an interrupt stack
+/// and only for the kernel:
+/// `IdtEntryBuilder::new(handler).with_ist(0).with_privilege(DplLevel::Kernel).install(idt, vector)`.
+#[derive(Debug, Clone, Copy)]
+pub struct IdtEntryBuilder {
+    handler: VirtAddr,
+    ist: Option<u16>,
+    privilege: DplLevel,
+}
+
+impl IdtEntryBuilder {
+    /// Starts an entry for a handler function of the `x86_64` crate's handler types.
+    pub fn new<F: HandlerFuncType>(handler: F) -> IdtEntryBuilder {
+        IdtEntryBuilder::from_addr(handler.to_virt_addr())
+    }
+
+    /// Starts an entry for a handler given by its address, e.g. an assembly entry stub.
+    pub const fn from_addr(handler: VirtAddr) -> IdtEntryBuilder {
+        IdtEntryBuilder { handler, ist: None, privilege: DplLevel::Kernel }
+    }
+
+    /// Switches to the interrupt stack `index` of the TSS on entry.
+    /// The TSS has 7 interrupt stacks, in a const context a larger index fails to compile.
+    pub const fn with_ist(mut self, index: u16) -> IdtEntryBuilder {
+        assert!(index < 7, "the IST index must be 0-6");
+        self.ist = Some(index);
+        self
+    }
+
+    pub const fn with_privilege(mut self, privilege: DplLevel) -> IdtEntryBuilder {
+        self.privilege = privilege;
+        self
+    }
+
+    /// Writes the entry for `vector` to `idt`.
+    ///
+    /// This function is unsafe because the handler must have the signature the CPU
+    /// expects for `vector` (e.g. with an error code for a page fault), and the
+    /// interrupt stack, if any, must be set up in the TSS.
+    pub unsafe fn install(self, idt: &mut InterruptDescriptorTable, vector: u8) {
+        let options = match vector {
+            // the exceptions that push an error code or never return are separate fields
+            8 => idt.double_fault.set_handler_addr(self.handler),
+            10 => idt.invalid_tss.set_handler_addr(self.handler),
+            11 => idt.segment_not_present.set_handler_addr(self.handler),
+            12 => idt.stack_segment_fault.set_handler_addr(self.handler),
+            13 => idt.general_protection_fault.set_handler_addr(self.handler),
+            14 => idt.page_fault.set_handler_addr(self.handler),
+            17 => idt.alignment_check.set_handler_addr(self.handler),
+            18 => idt.machine_check.set_handler_addr(self.handler),
+            29 => idt.vmm_communication_exception.set_handler_addr(self.handler),
+            30 => idt.security_exception.set_handler_addr(self.handler),
+            // panics for the reserved vectors
+            vector => idt[usize::from(vector)].set_handler_addr(self.handler),
+        };
+        options.set_privilege_level(match self.privilege {
+            DplLevel::Kernel => PrivilegeLevel::Ring0,
+            DplLevel::User => PrivilegeLevel::Ring3,
+        });
+        if let Some(index) = self.ist {
+            options.set_stack_index(index);
+        }
+    }
+}
+
+/// Number of timer interrupts since the PICs were initialized, read it with `ticks`.
+pub static CURRENT_TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Returns the number of timer interrupts since the PICs were initialized.
+pub fn ticks() -> u64 {
+    CURRENT_TICKS.load(Ordering::Relaxed)
+}
+
 pub fn init_idt() {
+    use x86_64::registers::control::{Cr4, Cr4Flags};
+
     IDT.load();
+
+    // without CR4.MCE a machine check shuts the CPU down instead of raising #MC
+    if KernelFeatures::get().has(KernelFeatures::HAS_MCE) {
+        unsafe { Cr4::update(|flags| flags.insert(Cr4Flags::MACHINE_CHECK_EXCEPTION)) };
+    }
 }
 
-extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
-    println!("EXCEPTION: BREAKPOINT\n{:#?}", stack_frame);
+/// Prints the interrupt stack frame with one labeled register per line
+/// and the RFLAGS decoded, e.g. `RFLAGS: 0x246 [IF|ZF|PF]`.
+pub fn print_stack_frame(frame: &InterruptStackFrame) {
+    println!("{}", StackFrameDisplay(frame));
+}
 
+/// Formats an interrupt stack frame like `print_stack_frame`,
+/// for use in panic messages.
+pub struct StackFrameDisplay<'a>(pub &'a InterruptStackFrame);
+
+impl fmt::Display for StackFrameDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let frame = self.0;
+        writeln!(f, "RIP:    {:#x}", frame.instruction_pointer.as_u64())?;
+        writeln!(f, "CS:     {:#x}", frame.code_segment)?;
+        writeln!(f, "RFLAGS: {:#x} [{}]", frame.cpu_flags, RflagsDisplay(frame.cpu_flags))?;
+        writeln!(f, "RSP:    {:#x}", frame.stack_pointer.as_u64())?;
+        write!(f, "SS:     {:#x}", frame.stack_segment)
+    }
 }
 
-extern "x86-interrupt" fn double_fault_handler(
-    stack_frame: InterruptStackFrame, _error_code: u64) -> !
-{
-    panic!("EXCEPTION: DOUBLE FAULT\n{:#?}", stack_frame);
+// the names of the RFLAGS bits, from the highest to the lowest bit
+const RFLAGS_NAMES: [(u64, &str); 16] = [
+    (1 << 21, "ID"), (1 << 20, "VIP"), (1 << 19, "VIF"), (1 << 18, "AC"),
+    (1 << 17, "VM"), (1 << 16, "RF"), (1 << 14, "NT"), (1 << 11, "OF"),
+    (1 << 10, "DF"), (1 << 9, "IF"), (1 << 8, "TF"), (1 << 7, "SF"),
+    (1 << 6, "ZF"), (1 << 4, "AF"), (1 << 2, "PF"), (1 << 0, "CF"),
+];
+
+// formats the set RFLAGS bits separated by `|`, plus the I/O privilege level if it is not 0
+struct RflagsDisplay(u64);
+
+impl fmt::Display for RflagsDisplay {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut separator = "";
+        for (bit, name) in RFLAGS_NAMES {
+            if self.0 & bit != 0 {
+                write!(f, "{}{}", separator, name)?;
+                separator = "|";
+            }
+            // the IOPL field sits between NT and OF
+            if bit == 1 << 14 && (self.0 >> 12) & 0b11 != 0 {
+                write!(f, "{}IOPL={}", separator, (self.0 >> 12) & 0b11)?;
+                separator = "|";
+            }
+        }
+        Ok(())
+    }
+}
+
+/// An exception as seen by its handler.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExceptionInfo {
+    pub vector: u8,
+    /// The instruction pointer in the stack frame: the faulting instruction for faults,
+    /// the next instruction for traps like #BP.
+    pub rip: u64,
+    /// The error code pushed by the CPU, for the exceptions that have one.
+    pub error_code: Option<u64>,
+}
+
+/// The last exception that reached a handler, set before the handler returns or halts.
+pub static LAST_EXCEPTION: spin::Mutex<Option<ExceptionInfo>> = spin::Mutex::new(None);
+/// Set by every exception handler, tests can clear it before raising an exception.
+pub static EXCEPTION_OCCURRED: AtomicBool = AtomicBool::new(false);
+
+/// Returns the last exception that reached a handler.
+pub fn last_exception() -> Option<ExceptionInfo> {
+    x86_64::instructions::interrupts::without_interrupts(|| *LAST_EXCEPTION.lock())
+}
+
+fn record_exception(vector: u8, stack_frame: &InterruptStackFrame, error_code: Option<u64>) {
+    EXCEPTION_OCCURRED.store(true, Ordering::SeqCst);
+    // locked if the exception interrupted `last_exception`, the info is lost then
+    if let Some(mut last) = LAST_EXCEPTION.try_lock() {
+        *last = Some(ExceptionInfo {
+            vector,
+            rip: stack_frame.instruction_pointer.as_u64(),
+            error_code,
+        });
+    }
+}
+
+extern "x86-interrupt" fn breakpoint_handler(mut stack_frame: InterruptStackFrame) {
+    record_exception(3, &stack_frame, None);
+    println!("EXCEPTION: BREAKPOINT");
+    print_stack_frame(&stack_frame);
+
+    // `int3` is one byte long, so a software breakpoint is one byte before the return address
+    let addr = stack_frame.instruction_pointer - 1u64;
+    if let Some(original) = SOFTWARE_BREAKPOINTS.lock().remove(&addr.as_u64()) {
+        unsafe {
+            write_code_byte(addr, original);
+            // execute the restored instruction when returning
+            stack_frame.as_mut().update(|frame| frame.instruction_pointer = addr);
+        }
+    }
+}
+
+// the original bytes at the addresses of software breakpoints that have not been hit yet
+static SOFTWARE_BREAKPOINTS: spin::Mutex<BTreeMap<u64, u8>> = spin::Mutex::new(BTreeMap::new());
+// the opcode of `int3`
+const INT3_OPCODE: u8 = 0xCC;
+
+/// Raises a breakpoint exception. The return address in the stack frame points into the caller.
+#[inline]
+pub fn trigger_breakpoint() {
+    x86_64::instructions::interrupts::int3();
+}
+
+/// Puts a one-shot software breakpoint at `addr` and calls it. The breakpoint handler
+/// restores the original byte, so the function then runs as usual.
+///
+/// This function is unsafe because `addr` must be the start of an `extern "C" fn()`
+/// that is safe to call, and no other CPU may execute it while it is patched.
+pub unsafe fn trigger_breakpoint_at(addr: VirtAddr) {
+    let ptr = addr.as_ptr::<u8>();
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut breakpoints = SOFTWARE_BREAKPOINTS.lock();
+        breakpoints.entry(addr.as_u64()).or_insert_with(|| ptr.read_volatile());
+        write_code_byte(addr, INT3_OPCODE);
+    });
+
+    let function: extern "C" fn() = core::mem::transmute(ptr);
+    function();
+
+    // in case the call did not reach the breakpoint, e.g. because it was removed concurrently
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        if let Some(original) = SOFTWARE_BREAKPOINTS.lock().remove(&addr.as_u64()) {
+            write_code_byte(addr, original);
+        }
+    });
+}
+
+// writes to a byte of the read-only kernel code
+unsafe fn write_code_byte(addr: VirtAddr, byte: u8) {
+    use x86_64::registers::control::{Cr0, Cr0Flags};
+
+    // with CR0.WP cleared the kernel can write to read-only pages
+    let cr0 = Cr0::read();
+    Cr0::write(cr0 - Cr0Flags::WRITE_PROTECT);
+    addr.as_mut_ptr::<u8>().write_volatile(byte);
+    Cr0::write(cr0);
+}
+
+crate::exception_entry_with_error_code!(double_fault_entry, double_fault_handler);
+
+extern "C" fn double_fault_handler(context: &mut ExceptionContext) -> ! {
+    record_exception(8, context.stack_frame(), Some(context.error_code));
+    let regs = context.register_dump();
+    debug::record(regs);
+    panic!("EXCEPTION: DOUBLE FAULT\n{}\n{}", StackFrameDisplay(context.stack_frame()), regs);
+}
+
+// the global machine check MSRs, MCG_CAP bits 0-7 hold the number of banks
+const MCG_CAP: u32 = 0x179;
+const MCG_STATUS: u32 = 0x17A;
+// the status register of bank n is at MC0_STATUS + 4 * n
+const MC0_STATUS: u32 = 0x401;
+// only the first banks are printed to keep the output on screen
+const MAX_MC_BANKS: u32 = 4;
+
+extern "x86-interrupt" fn machine_check_handler(stack_frame: InterruptStackFrame) -> ! {
+    use x86_64::registers::model_specific::Msr;
+
+    record_exception(18, &stack_frame, None);
+    println!("EXCEPTION: MACHINE CHECK");
+    // the MSRs only exist with the machine check architecture
+    if KernelFeatures::get().has(KernelFeatures::HAS_MCA) {
+        unsafe {
+            println!("MCG_STATUS: {:#018x}", Msr::new(MCG_STATUS).read());
+            let banks = (Msr::new(MCG_CAP).read() & 0xff) as u32;
+            for bank in 0..banks.min(MAX_MC_BANKS) {
+                let status = Msr::new(MC0_STATUS + 4 * bank).read();
+                // bit 63 marks a bank that holds a valid error
+                if status & (1 << 63) != 0 {
+                    println!("MC{}_STATUS: {:#018x}", bank, status);
+                }
+            }
+        }
+    }
+    print_stack_frame(&stack_frame);
+    hlt_loop();
 }
 
 extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFrame) {
-    print!(".");
+    #[cfg(feature = "timer_dots")]
+    print_from_interrupt!(".");
+    CURRENT_TICKS.fetch_add(1, Ordering::Relaxed);
+    // wake the async tasks that are waiting on a `Delay`
+    crate::task::delay::on_tick();
+    // end a beep started with `speaker::start_beep`
+    crate::drivers::speaker::on_tick();
+    // fail a `#[kernel_test]` that has run past its timeout
+    crate::testing::check_timeout();
     // signal end of interrupt to the PIC
     // because interrupt controller expects an signal to know that the interrupt is handled
     unsafe {
@@ -66,7 +349,8 @@ extern "x86-interrupt" fn timer_interrupt_handler(_stack_frame: InterruptStackFr
 extern "x86-interrupt" fn keyboard_interrupt_handler(
     _stack_frame: InterruptStackFrame)
 {
-    use x86_64::instructions::port::Port;
+    use crate::drivers::ps2_controller::Controller;
+    use crate::io::ports::KbdData;
     use pc_keyboard::{layouts, HandleControl, Keyboard, ScancodeSet1};
     use spin::Mutex;
 
@@ -87,12 +371,14 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(
     // on each interrupt, lock the keyboard, read the scancode and process it
     // let mut keyboard = KEYBOARD.lock();
 
-    // read scancode from the keyboard port
-    // 0x60 is the port number for the keyboard
-    let mut port = Port::new(0x60);
+    // read scancode from the keyboard data port
     // read scancode from the keyboard port is important
     // otherwise the keyboard will not work next time
-    let scancode: u8 = unsafe { port.read() };
+    let scancode = match Controller::get() {
+        Some(controller) => controller.read_byte(),
+        // the controller could not be initialized, but the byte must be read anyway
+        None => KbdData::new().read(),
+    };
     
     // // get the key from the scancode using a match statement
     // if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
@@ -110,7 +396,10 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(
     // the above code is replaced by the following code
     // which is more efficient and less error-prone
     // it uses async/await to handle the keyboard input
-    crate::task::keyboard::add_scancode(scancode);
+    crate::task::keyboard::add_scancode(scancode).unwrap_or_else(|_| {
+        crate::task::keyboard::DROPPED_SCANCODES.fetch_add(1, Ordering::Relaxed);
+        println_from_interrupt!("WARNING: scancode queue full; dropping keyboard input");
+    });
 
     unsafe {
         PICS.lock()
@@ -118,23 +407,79 @@ extern "x86-interrupt" fn keyboard_interrupt_handler(
     }
 }
 
-extern "x86-interrupt" fn page_fault_handler(
-    stack_frame: InterruptStackFrame,
-    error_code: PageFaultErrorCode,
-) {
+crate::exception_entry_with_error_code!(page_fault_entry, page_fault_handler);
+
+extern "C" fn page_fault_handler(context: &mut ExceptionContext) {
     use x86_64::registers::control::Cr2;
 
+    record_exception(14, context.stack_frame(), Some(context.error_code));
+    // e.g. a write to a copy-on-write page is retried once the page was copied
+    let error_code = PageFaultErrorCode::from_bits_truncate(context.error_code);
+    if crate::memory::page_fault_handler_recoverable(Cr2::read(), error_code).is_ok() {
+        return;
+    }
+
+    let regs = context.register_dump();
+    debug::record(regs);
     println!("EXCEPTION: PAGE FAULT");
     println!("Accessed Address: {:?}", Cr2::read());
     println!("Error Code: {:?}", error_code);
-    println!("{:#?}", stack_frame);
+    print_stack_frame(context.stack_frame());
+    println!("{}", regs);
     hlt_loop();
 }
 
+#[test_case]
+fn test_rflags_display() {
+    use crate::collections::string::KernelString;
+    use core::fmt::Write;
+
+    let mut s = KernelString::<32>::new();
+    write!(s, "{}", RflagsDisplay(0x247)).unwrap();
+    assert_eq!(s.as_str(), "IF|ZF|PF|CF");
+
+    s.clear();
+    write!(s, "{}", RflagsDisplay(0x3200)).unwrap();
+    assert_eq!(s.as_str(), "IOPL=3|IF");
+}
+
+#[test_case]
+fn test_idt_entry_builder() {
+    // evaluated at compile time, an IST index of 7 would not compile
+    const BUILDER: IdtEntryBuilder = IdtEntryBuilder::from_addr(VirtAddr::zero()).with_ist(6);
+    assert_eq!(BUILDER.ist, Some(6));
+
+    let mut idt = InterruptDescriptorTable::new();
+    unsafe { IdtEntryBuilder::new(breakpoint_handler as HandlerFunc).install(&mut idt, 3) };
+    assert_eq!(idt.breakpoint.handler_addr(), (breakpoint_handler as HandlerFunc).to_virt_addr());
+}
+
 #[test_case]
 fn test_breakpoint_exception() {
+    EXCEPTION_OCCURRED.store(false, Ordering::SeqCst);
     // invoke a breakpoint exception
-    x86_64::instructions::interrupts::int3();
+    trigger_breakpoint();
+    assert!(EXCEPTION_OCCURRED.load(Ordering::SeqCst));
+    let info = last_exception().expect("breakpoint handler did not run");
+    assert_eq!(info.vector, 3);
+    assert_eq!(info.error_code, None);
+}
+
+#[test_case]
+fn test_software_breakpoint() {
+    static CALLS: AtomicU64 = AtomicU64::new(0);
+
+    #[inline(never)]
+    extern "C" fn target() {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    let addr = debug::entry_address(target);
+    let original = unsafe { addr.as_ptr::<u8>().read_volatile() };
+    unsafe { trigger_breakpoint_at(addr) };
+    assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    assert_eq!(unsafe { addr.as_ptr::<u8>().read_volatile() }, original);
+    assert!(SOFTWARE_BREAKPOINTS.lock().is_empty());
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -148,9 +493,5 @@ impl InterruptIndex {
     fn as_u8(self) -> u8 {
         self as u8
     }
-
-    fn as_usize(self) -> usize {
-        usize::from(self.as_u8())
-    }
 }
 
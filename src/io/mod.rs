@@ -0,0 +1,66 @@
+//! Port I/O helpers.
+
+pub mod ports;
+pub mod port_range;
+
+pub use port_range::{PortConflict, PortRange};
+
+use crate::cpu::cpuid;
+use crate::sync::Once;
+use ports::PostCode;
+
+// the TSC frequency in MHz as reported by CPUID, 0 if it is not reported
+static TSC_MHZ: Once<u64> = Once::new();
+
+/// Waits about a microsecond by writing to the unused POST code port, for legacy
+/// devices that need time between two accesses.
+pub fn io_delay() {
+    PostCode::new().write(0);
+}
+
+/// Waits at least `us` microseconds with `io_delay`.
+///
+/// Uses the TSC if CPUID reports its frequency, otherwise assumes that every
+/// `io_delay` takes a microsecond, which is too short on some virtual machines.
+pub fn io_delay_us(us: u32) {
+    let mhz = *TSC_MHZ.call_once(tsc_mhz);
+    if mhz == 0 {
+        for _ in 0..us {
+            io_delay();
+        }
+        return;
+    }
+    let start = unsafe { core::arch::x86_64::_rdtsc() };
+    let cycles = u64::from(us) * mhz;
+    while unsafe { core::arch::x86_64::_rdtsc() }.wrapping_sub(start) < cycles {
+        io_delay();
+    }
+}
+
+// reads the TSC frequency from CPUID leaf 0x15 (TSC/crystal ratio) or 0x16 (base frequency)
+fn tsc_mhz() -> u64 {
+    let max_leaf = cpuid(0, 0).eax;
+    if max_leaf >= 0x15 {
+        let leaf = cpuid(0x15, 0);
+        // EBX/EAX is the ratio of the TSC to the crystal, ECX the crystal frequency in Hz
+        if leaf.eax != 0 && leaf.ebx != 0 && leaf.ecx != 0 {
+            return u64::from(leaf.ecx) * u64::from(leaf.ebx) / u64::from(leaf.eax) / 1_000_000;
+        }
+    }
+    if max_leaf >= 0x16 {
+        // EAX[15:0] is the base frequency in MHz
+        return u64::from(cpuid(0x16, 0).eax & 0xffff);
+    }
+    0
+}
+
+#[test_case]
+fn test_io_delay_us() {
+    io_delay_us(0);
+    let start = unsafe { core::arch::x86_64::_rdtsc() };
+    io_delay_us(10);
+    let mhz = *TSC_MHZ.call_once(tsc_mhz);
+    if mhz != 0 {
+        assert!(unsafe { core::arch::x86_64::_rdtsc() } - start >= 10 * mhz);
+    }
+}
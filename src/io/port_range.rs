@@ -0,0 +1,89 @@
+//! Exclusive ownership of I/O port ranges by drivers.
+
+use alloc::collections::BTreeMap;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+/// The driver owning each requested port.
+pub static PORT_REGISTRY: Mutex<BTreeMap<u16, &'static str>> = Mutex::new(BTreeMap::new());
+
+/// Returned by `PortRange::request` if a port is already owned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PortConflict {
+    /// The first port of the requested range that is taken.
+    pub port: u16,
+    /// The driver that owns it.
+    pub owner: &'static str,
+}
+
+/// A range of I/O ports owned by one driver, released again when dropped.
+#[derive(Debug)]
+pub struct PortRange {
+    base: u16,
+    count: u16,
+}
+
+impl PortRange {
+    /// Registers the ports `base..base + count` for `driver`.
+    ///
+    /// Fails without registering anything if one of the ports is owned already.
+    /// Panics if the range is empty or extends past port 0xFFFF.
+    pub fn request(base: u16, count: u16, driver: &'static str) -> Result<PortRange, PortConflict> {
+        assert!(count > 0, "empty port range");
+        let end = base.checked_add(count - 1).expect("port range past 0xFFFF");
+        let mut registry = PORT_REGISTRY.lock();
+        if let Some((&port, &owner)) = registry.range(base..=end).next() {
+            return Err(PortConflict { port, owner });
+        }
+        for port in base..=end {
+            registry.insert(port, driver);
+        }
+        Ok(PortRange { base, count })
+    }
+
+    pub fn base(&self) -> u16 {
+        self.base
+    }
+
+    pub fn count(&self) -> u16 {
+        self.count
+    }
+
+    /// Reads the port at `offset` from the base. Panics if `offset` is outside of the range.
+    pub fn read_u8(&self, offset: u16) -> u8 {
+        unsafe { Port::new(self.port(offset)).read() }
+    }
+
+    /// Writes the port at `offset` from the base. Panics if `offset` is outside of the range.
+    pub fn write_u8(&self, offset: u16, value: u8) {
+        unsafe { Port::new(self.port(offset)).write(value) }
+    }
+
+    fn port(&self, offset: u16) -> u16 {
+        assert!(offset < self.count, "port offset {} outside of range of {} ports", offset, self.count);
+        self.base + offset
+    }
+}
+
+impl Drop for PortRange {
+    fn drop(&mut self) {
+        let mut registry = PORT_REGISTRY.lock();
+        for port in self.base..=self.base + (self.count - 1) {
+            registry.remove(&port);
+        }
+    }
+}
+
+#[test_case]
+fn test_port_range_conflicts() {
+    // only registers the ports, nothing is read or written
+    let range = PortRange::request(0xe000, 8, "test").unwrap();
+    assert_eq!(
+        PortRange::request(0xe007, 2, "other").unwrap_err(),
+        PortConflict { port: 0xe007, owner: "test" }
+    );
+    assert!(PortRange::request(0xe008, 2, "other").is_ok());
+
+    drop(range);
+    assert!(PortRange::request(0xe000, 8, "other").is_ok());
+}
@@ -0,0 +1,115 @@
+//! Named wrappers for the well-known I/O ports of the PC platform.
+//!
+//! Accessing these ports cannot violate memory safety, so the wrappers contain
+//! the `unsafe` of `Port` and expose safe `read` and `write` methods.
+
+use x86_64::instructions::port::Port;
+
+pub const PIC_MASTER_COMMAND: u16 = 0x20;
+pub const PIC_MASTER_DATA: u16 = 0x21;
+pub const PIC_SLAVE_COMMAND: u16 = 0xA0;
+pub const PIC_SLAVE_DATA: u16 = 0xA1;
+pub const KBD_DATA: u16 = 0x60;
+pub const KBD_STATUS: u16 = 0x64;
+pub const PIT_CHANNEL_0: u16 = 0x40;
+pub const PIT_CHANNEL_2: u16 = 0x42;
+pub const PIT_COMMAND: u16 = 0x43;
+// bit 0 gates PIT channel 2, bit 1 connects its output to the speaker
+pub const SYSTEM_CONTROL_B: u16 = 0x61;
+pub const CMOS_INDEX: u16 = 0x70;
+pub const CMOS_DATA: u16 = 0x71;
+// the POST code register, which nothing listens to after boot
+pub const POST_CODE: u16 = 0x80;
+pub const LPT1_DATA: u16 = 0x378;
+pub const LPT1_STATUS: u16 = 0x379;
+pub const LPT1_CONTROL: u16 = 0x37A;
+pub const VGA_CRTC_INDEX: u16 = 0x3D4;
+pub const VGA_CRTC_DATA: u16 = 0x3D5;
+// the isa-debug-exit device QEMU is started with for tests
+pub const DEBUG_EXIT: u16 = 0xF4;
+
+// defines a wrapper type around `Port<$ty>` for the port at `$port`
+macro_rules! port_wrapper {
+    ($(#[$meta:meta])* $name:ident, $port:expr, $ty:ty) => {
+        $(#[$meta])*
+        pub struct $name(Port<$ty>);
+
+        impl $name {
+            pub const fn new() -> Self {
+                $name(Port::new($port))
+            }
+
+            pub fn read(&mut self) -> $ty {
+                unsafe { self.0.read() }
+            }
+
+            pub fn write(&mut self, value: $ty) {
+                unsafe { self.0.write(value) }
+            }
+        }
+
+        impl Default for $name {
+            fn default() -> Self {
+                Self::new()
+            }
+        }
+    };
+}
+
+port_wrapper!(
+    /// Command and status register of the master PIC.
+    PicMasterCommand, PIC_MASTER_COMMAND, u8);
+port_wrapper!(
+    /// Interrupt mask register of the master PIC.
+    PicMasterData, PIC_MASTER_DATA, u8);
+port_wrapper!(
+    /// Command and status register of the slave PIC.
+    PicSlaveCommand, PIC_SLAVE_COMMAND, u8);
+port_wrapper!(
+    /// Interrupt mask register of the slave PIC.
+    PicSlaveData, PIC_SLAVE_DATA, u8);
+port_wrapper!(
+    /// Data port of the PS/2 controller, delivers the keyboard scancodes.
+    KbdData, KBD_DATA, u8);
+port_wrapper!(
+    /// Status register (read) and command register (write) of the PS/2 controller.
+    KbdStatus, KBD_STATUS, u8);
+port_wrapper!(
+    /// Data port of PIT channel 0, which drives the timer interrupt.
+    PitChannel0, PIT_CHANNEL_0, u8);
+port_wrapper!(
+    /// Data port of PIT channel 2, which drives the PC speaker.
+    PitChannel2, PIT_CHANNEL_2, u8);
+port_wrapper!(
+    /// Mode/command register of the PIT.
+    PitCommand, PIT_COMMAND, u8);
+port_wrapper!(
+    /// System control port B, gates PIT channel 2 and the PC speaker.
+    SystemControlB, SYSTEM_CONTROL_B, u8);
+port_wrapper!(
+    /// Selects the CMOS register accessed through `CmosData`, bit 7 disables NMIs.
+    CmosIndex, CMOS_INDEX, u8);
+port_wrapper!(
+    /// Reads and writes the CMOS register selected with `CmosIndex`.
+    CmosData, CMOS_DATA, u8);
+port_wrapper!(
+    /// The POST code register, a write to it takes about a microsecond on the ISA bus.
+    PostCode, POST_CODE, u8);
+port_wrapper!(
+    /// Data register of the first parallel port, holds the byte on the data lines.
+    Lpt1Data, LPT1_DATA, u8);
+port_wrapper!(
+    /// Status register of the first parallel port, the lines driven by the printer.
+    Lpt1Status, LPT1_STATUS, u8);
+port_wrapper!(
+    /// Control register of the first parallel port, drives STROBE among others.
+    Lpt1Control, LPT1_CONTROL, u8);
+port_wrapper!(
+    /// Selects the VGA CRT controller register accessed through `VgaCrtcData`.
+    VgaCrtcIndex, VGA_CRTC_INDEX, u8);
+port_wrapper!(
+    /// Reads and writes the VGA CRT controller register selected with `VgaCrtcIndex`.
+    VgaCrtcData, VGA_CRTC_DATA, u8);
+port_wrapper!(
+    /// QEMU's isa-debug-exit device, writing `value` exits with status `(value << 1) | 1`.
+    DebugExitPort, DEBUG_EXIT, u32);
@@ -11,6 +11,8 @@
 #![reexport_test_harness_main = "test_main"]
 // to use x86-interrupt calling convention
 #![feature(abi_x86_interrupt)]
+// to halt with a useful message when the heap is exhausted
+#![feature(alloc_error_handler)]
 
 pub mod serial;
 pub mod vga_buffer;
@@ -18,12 +20,27 @@ pub mod gdt;
 pub mod memory;
 pub mod allocator;
 pub mod task;
+pub mod drivers;
+pub mod boot;
+pub mod sync;
+pub mod cpu;
+pub mod net;
+pub mod fs;
+pub mod process;
+pub mod collections;
+pub mod time;
+pub mod testing;
+pub mod panic_buffer;
+pub mod io;
+pub mod debug;
 
 use core::panic::PanicInfo;
 #[cfg(test)]
 use bootloader::{entry_point, BootInfo};
 
 extern crate alloc;
+// lets `#[kernel_test]` refer to `::turiya` from inside this crate as well
+extern crate self as turiya;
 pub trait Testable {
     fn run(&self) -> ();
 }
@@ -44,18 +61,36 @@ where
 
 // no cf(test) since we want to make this public
 pub fn test_runner(tests: &[&dyn Testable]) {
-    serial_println!("Running {} tests", tests.len());
+    // timestamps of the `timestamped_log` feature count from the start of the tests
+    time::set_log_epoch();
+    serial_println!("Running {} tests", tests.len() + testing::kernel_tests().count());
     for test in tests {
         test.run();
     }
+    // tests registered with `#[kernel_test]` run after the `#[test_case]` ones
+    testing::run_from(0);
     // exit qemu when tests are done
     exit_qemu(QemuExitCode::Success);
 }
 
 pub fn test_panic_handler(info: &PanicInfo) -> ! {
+    // does not return if the panic happened inside `testing::expect_panic`
+    // or, in the unit tests, in a task spawned with `spawn_safe`
+    testing::recover_panic(info);
+    // keep the message in memory in case the serial output gets lost
+    panic_buffer::record(info);
+    // does not return if the running test expects to panic
+    testing::on_panic();
     serial_println!("[failed]\n");
+    if let Some(name) = task::current_task_name() {
+        serial_println!("task '{}' panicked", name);
+    }
     serial_println!("Error: {}\n", info);
-    exit_qemu(QemuExitCode::Failed);
+    if testing::is_assertion_failure(info) {
+        exit_qemu(QemuExitCode::Failed);
+    } else {
+        exit_qemu(QemuExitCode::Panic);
+    }
     hlt_loop();
 }
 
@@ -65,8 +100,20 @@ entry_point!(test_kernel_main);
 #[cfg(test)]
 // #[no_mangle] not required since we are using entry_point macro
 // need a start here because lib.rs is tested independently
-fn test_kernel_main(_boot_info: &'static BootInfo) -> ! {
+fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
+    use x86_64::VirtAddr;
+
     init();
+
+    // set up the heap so that unit tests can use `alloc` types
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        memory::BootInfoFrameAllocator::init(&boot_info.memory_map)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
     test_main();
     hlt_loop();
 }
@@ -80,25 +127,33 @@ fn panic(info: &PanicInfo) -> ! {
 // exit qemu
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u32)]
+/// The code written to the isa-debug-exit device, QEMU exits with `(code << 1) | 1`.
 pub enum QemuExitCode {
     Success = 0x10,
+    /// An assertion failed, or a test did not behave as expected.
     Failed = 0x11,
+    /// A test panicked for another reason than a failed assertion.
+    Panic = 0x12,
+    /// A test ran longer than its timeout.
+    Timeout = 0x13,
 }
 
 pub fn exit_qemu(exit_code: QemuExitCode) {
-    use x86_64::instructions::port::Port;
-
-    unsafe {
-        let mut port = Port::new(0xf4);
-        port.write(exit_code as u32);
-    }
+    io::ports::DebugExitPort::new().write(exit_code as u32);
 }
 
 pub mod interrupts;
 
 pub fn init() {
+    // subsystems below check the detected features
+    cpu::features::init();
     gdt::init();
+    cpu::percpu::init_bsp();
     interrupts::init_idt();
+    // interrupts are still disabled, as the controller setup requires
+    if let Err(err) = drivers::ps2_controller::Controller::init() {
+        println!("PS/2 controller initialization failed: {:?}", err);
+    }
     unsafe {
         interrupts::PICS.lock().initialize();
     }
@@ -106,6 +161,21 @@ pub fn init() {
     x86_64::instructions::interrupts::enable();
 }
 
+/// Flushes pending output and turns the machine off through ACPI.
+/// Resets the machine if powering off fails, and halts if that fails as well.
+pub fn shutdown() -> ! {
+    use drivers::acpi_poweroff;
+
+    x86_64::instructions::interrupts::disable();
+    serial::flush();
+    let err = acpi_poweroff::power_off();
+    serial_println!("ACPI power off failed: {:?}", err);
+    serial::flush();
+    let err = acpi_poweroff::reset();
+    serial_println!("ACPI reset failed: {:?}", err);
+    hlt_loop();
+}
+
 pub fn hlt_loop() -> ! {
     loop {
         x86_64::instructions::hlt();
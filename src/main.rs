@@ -12,7 +12,8 @@ use core::panic::PanicInfo;
 use turiya::println;
 use bootloader::{BootInfo, entry_point};
 use alloc::{boxed::Box, vec, vec::Vec, rc::Rc};
-use turiya::task::{Task, executor, keyboard};
+use turiya::task::{executor, keyboard};
+use turiya::cpu::KernelFeatures;
 
 extern crate alloc;
 
@@ -20,6 +21,24 @@ extern crate alloc;
 /// This function is called on panic. originally found in std but we are using no_std env
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
+    use core::fmt::Write;
+    use turiya::collections::string::KernelString;
+    use turiya::vga_buffer::{self, BUFFER_WIDTH, WRITER};
+
+    turiya::panic_buffer::record(_info);
+
+    // the panic may have happened while `WRITER` was locked, so show it without the lock first
+    let mut message = KernelString::<{ 2 * BUFFER_WIDTH }>::new();
+    // a message that does not fit is cut off
+    let _ = write!(message.truncating(), "{}", _info);
+    vga_buffer::emergency_print(message.as_str());
+    if WRITER.is_locked() {
+        turiya::hlt_loop();
+    }
+
+    if let Some(name) = turiya::task::current_task_name() {
+        println!("task '{}' panicked", name);
+    }
     println!("{}", _info);
     turiya::hlt_loop();
 }
@@ -43,10 +62,22 @@ entry_point!(kernel_main);
 // boot_info is a struct that contains information about the system
 // &'static is a lifetime specifier, which means the reference is valid for the entire program
 fn kernel_main(boot_info: &'static BootInfo) -> ! {
+    // the initialization below must only run once, on the bootstrap processor
+    if !turiya::cpu::is_bsp() {
+        turiya::cpu::ap_halt_loop();
+    }
+    turiya::boot::verify_memory_map(boot_info).expect("invalid boot information");
+
     println!("Hello World{}", "!");
     
     turiya::init(); 
 
+    let cpu = turiya::cpu::cpu_info();
+    println!("features: {:?}", KernelFeatures::get());
+    println!("CPU: {} ({}), family {} model {} stepping {}, {} cores / {} threads",
+        cpu.brand, cpu.vendor, cpu.family, cpu.model, cpu.stepping,
+        cpu.physical_cores, cpu.logical_cores);
+
     // fn stack_overflow() {
     //     stack_overflow(); // for each recursion, the return address is pushed
     // }
@@ -75,22 +106,43 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     use x86_64::{structures::paging::Page, VirtAddr};
     use turiya::{memory, allocator};
 
+    turiya::boot::print_memory_map(&boot_info.memory_map);
     let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
     let mut mapper = unsafe { memory::init(phys_mem_offset) };
     let mut frame_allocator = unsafe { 
         memory::BootInfoFrameAllocator::init(&boot_info.memory_map)
     };
+    println!("Physical memory: {} MB available", frame_allocator.remaining_frames() * 4096 / (1024 * 1024));
+    // needed by `turiya::shutdown`, the kernel keeps running without it
+    match unsafe { turiya::drivers::acpi_poweroff::init(phys_mem_offset) } {
+        Ok(_) => KernelFeatures::enable(KernelFeatures::HAS_ACPI),
+        Err(err) => println!("ACPI initialization failed: {:?}", err),
+    }
     
     // map an unused page
     let page = Page::containing_address(VirtAddr::new(0));
-    memory::create_example_mapping(page, &mut mapper, &mut frame_allocator);
+    let mapped = memory::create_example_mapping(page, &mut mapper, &mut frame_allocator)
+        .expect("example mapping failed");
 
     // write the string `New!` to the screen through the new mapping
-    let page_ptr: *mut u64 = page.start_address().as_mut_ptr();
+    let page_ptr: *mut u64 = mapped.page().start_address().as_mut_ptr();
     unsafe { page_ptr.offset(400).write_volatile(0x_f021_f077_f065_f04e)};
+    drop(mapped);
 
-    allocator::init_heap(&mut mapper, &mut frame_allocator)
+    let heap = allocator::init_heap(&mut mapper, &mut frame_allocator)
         .expect("heap initialization failed");
+    println!("Heap: {} KB at {:#x}, {} pages, {} frames used",
+        heap.size / 1024, heap.start.as_u64(), heap.page_count, heap.phys_frames_used);
+
+    // replace the static boot stack of the double fault handler with one that has a guard page
+    let double_fault_stack = memory::KernelStack::<{ 4096 * 5 }>::new(&mut mapper, &mut frame_allocator)
+        .expect("double fault stack allocation failed");
+    turiya::gdt::set_double_fault_stack(double_fault_stack.top());
+
+    // from here on only the page fault handler allocates frames, to copy pages
+    memory::cow::init(phys_mem_offset, frame_allocator);
+
+    turiya::boot::cmdline::init();
 
     // allocate a number on the heap
     let heap_value = Box::new(41);
@@ -111,8 +163,8 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     println!("reference count is {} now", Rc::strong_count(&cloned_reference));
 
     let mut executor = executor::Executor::new();
-    executor.spawn(Task::new(example_task()));
-    executor.spawn(Task::new(keyboard::print_keypresses()));
+    executor.spawn_named("example", example_task());
+    executor.spawn_named("keyboard", keyboard::print_keypresses());
     executor.run();
 
     #[cfg(test)]
@@ -126,6 +178,40 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     // not needed because the run_ready_tasks function will never return
 }
 
+// entry point used when the kernel is booted by a Multiboot2 bootloader such as GRUB
+// instead of the `bootloader` crate
+// the bootloader passes its magic value in eax and the boot information address in ebx,
+// the assembly stub that switches to long mode forwards them as the two arguments,
+// the image built with `bootloader` contains neither a Multiboot2 header nor that stub,
+// so it only boots through here when both are linked in
+// unsafe because `info` must point to the boot information left by the bootloader
+#[no_mangle]
+pub unsafe extern "C" fn multiboot2_main(magic: u32, info: *const u8) -> ! {
+    use turiya::boot::multiboot2;
+
+    if !turiya::cpu::is_bsp() {
+        turiya::cpu::ap_halt_loop();
+    }
+
+    let info = match multiboot2::parse(magic, info) {
+        Ok(info) => info,
+        Err(err) => panic!("invalid multiboot2 boot information: {:?}", err),
+    };
+
+    if let Some(cmdline) = info.command_line() {
+        turiya::boot::cmdline::set(cmdline);
+    }
+
+    turiya::init();
+
+    println!("command line: {}", *turiya::boot::cmdline::CMDLINE_STR.lock());
+    for area in info.memory_map() {
+        println!("{:#x} - {:#x} {:?}", area.base_addr, area.base_addr + area.length, area.area_type);
+    }
+
+    turiya::hlt_loop();
+}
+
 #[test_case]
 fn trivial_assertion() {
     assert_eq!(1, 1);
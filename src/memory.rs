@@ -1,11 +1,77 @@
 use x86_64::{
     structures::paging::{
         PageTable, OffsetPageTable, Page, 
-        PhysFrame, Size4KiB, FrameAllocator
+        PhysFrame, Size4KiB, FrameAllocator, FrameDeallocator,
+        Mapper, PageTableFlags, mapper::MapToError,
     },
     VirtAddr, PhysAddr,
 };
-use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use bootloader::{bootinfo::{MemoryMap, MemoryRegionType}, BootInfo};
+use core::ops::Range;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::Mutex;
+
+pub mod addr_ext;
+pub mod cow;
+pub mod page_fault;
+
+pub use addr_ext::{PhysAddrExt, VirtAddrExt};
+pub use cow::{mark_copy_on_write, share_page, COPY_ON_WRITE};
+pub use page_fault::{page_fault_handler_recoverable, register_resolver, PageFaultResolver, Unresolved};
+
+// the bootloader maps the physical memory with huge pages at a 1 GiB aligned offset
+const PHYSICAL_MEMORY_OFFSET_ALIGN: u64 = 1 << 30;
+
+/// Errors found by `validate_boot_info`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BootInfoError {
+    /// The physical memory offset is not aligned to 1 GiB.
+    MisalignedPhysicalMemoryOffset(u64),
+    /// The physical memory offset is not a canonical address.
+    NonCanonicalPhysicalMemoryOffset(u64),
+    /// The memory map has no regions.
+    EmptyMemoryMap,
+    /// The memory map has no usable region.
+    NoUsableMemory,
+    /// Two regions of the memory map, starting at the given addresses, overlap.
+    OverlappingRegions(u64, u64),
+    /// The physical memory offset is zero, the bootloader did not map the physical memory.
+    ZeroPhysicalMemoryOffset,
+    /// The heap overlaps the mapping of the region starting at the given physical address.
+    HeapOverlapsRegion(u64),
+    /// There is less usable memory than the heap needs, with room to spare.
+    NotEnoughMemory { usable: u64, required: u64 },
+}
+
+/// Checks the physical memory offset and the memory map the bootloader passed
+/// before they are used by `init` and `BootInfoFrameAllocator::init`.
+///
+/// The bootloader chooses the offset itself, so it can be in the lower half of the
+/// address space. Only alignment and canonical form are checked.
+pub fn validate_boot_info(boot_info: &BootInfo) -> Result<(), BootInfoError> {
+    let offset = boot_info.physical_memory_offset;
+    let addr = VirtAddr::try_new(offset)
+        .map_err(|_| BootInfoError::NonCanonicalPhysicalMemoryOffset(offset))?;
+    if !addr.is_aligned(PHYSICAL_MEMORY_OFFSET_ALIGN) {
+        return Err(BootInfoError::MisalignedPhysicalMemoryOffset(offset));
+    }
+
+    let regions = &boot_info.memory_map;
+    if regions.is_empty() {
+        return Err(BootInfoError::EmptyMemoryMap);
+    }
+    if !regions.iter().any(|r| r.region_type == MemoryRegionType::Usable) {
+        return Err(BootInfoError::NoUsableMemory);
+    }
+    // the bootloader sorts the regions, so overlapping ones are next to each other
+    for pair in regions.windows(2) {
+        if pair[1].range.start_addr() < pair[0].range.end_addr() {
+            return Err(BootInfoError::OverlappingRegions(
+                pair[0].range.start_addr(), pair[1].range.start_addr()));
+        }
+    }
+    Ok(())
+}
 
 /// Initialize a new OffsetPageTable.
 ///
@@ -14,6 +80,11 @@ use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
 /// `physical_memory_offset`. Also, this function must be only called once
 /// to avoid aliasing `&mut` references (which is undefined behavior).
 pub unsafe fn init(physical_memory_offset: VirtAddr) -> OffsetPageTable<'static> {
+    // `validate_boot_info` reports this as an error, it should never fire here,
+    // a `VirtAddr` is canonical already, which is the only other check it makes on the offset
+    debug_assert!(physical_memory_offset.is_aligned(PHYSICAL_MEMORY_OFFSET_ALIGN),
+        "physical memory offset {:#x} is not 1 GiB aligned", physical_memory_offset.as_u64());
+
     let level_4_table = active_level_4_table(physical_memory_offset);
     OffsetPageTable::new(level_4_table, physical_memory_offset)
 }
@@ -89,37 +160,348 @@ fn translate_addr_inner(addr: VirtAddr, physical_memory_offset: VirtAddr)
     Some(frame.start_address() + u64::from(addr.page_offset()))
 }
 
+// the end of the highest region in the memory map, recorded by `BootInfoFrameAllocator::init`;
+// the bootloader maps the physical memory up to there at the physical memory offset
+static PHYSICAL_MEMORY_END: AtomicU64 = AtomicU64::new(0);
+
+/// Returns whether `addr` is in the higher half of the address space (bit 47 set).
+///
+/// Note that bootloader 0.9 loads the kernel and the physical memory mapping into
+/// the lower half, so this does not hold for all kernel addresses.
+pub fn is_kernel_addr(addr: VirtAddr) -> bool {
+    addr.as_u64() & (1 << 47) != 0
+}
+
+/// Returns the address at which `phys` is accessible in the physical memory mapping
+/// at `offset`, or `None` if it is beyond the memory map.
+///
+/// Always returns `None` before `BootInfoFrameAllocator::init` was called.
+pub fn phys_to_virt(phys: PhysAddr, offset: VirtAddr) -> Option<VirtAddr> {
+    if phys.as_u64() >= PHYSICAL_MEMORY_END.load(Ordering::Relaxed) {
+        return None;
+    }
+    let addr = offset.as_u64().checked_add(phys.as_u64())?;
+    VirtAddr::try_new(addr).ok()
+}
+
+/// A page mapped by `create_example_mapping`, unmapped again when dropped.
+///
+/// The frame is not deallocated, it belongs to the VGA buffer.
+pub struct MappedPage<'a, 'b> {
+    page: Page<Size4KiB>,
+    mapper: &'a mut OffsetPageTable<'b>,
+}
+
+impl MappedPage<'_, '_> {
+    /// Returns the mapped page.
+    pub fn page(&self) -> Page<Size4KiB> {
+        self.page
+    }
+}
+
+impl Drop for MappedPage<'_, '_> {
+    fn drop(&mut self) {
+        if let Ok((_, flush)) = self.mapper.unmap(self.page) {
+            flush.flush();
+        }
+    }
+}
+
 /// Creates an example mapping for the given page to frame `0xb8000`.
-pub fn create_example_mapping(
-    page: Page, mapper: &mut OffsetPageTable, 
+///
+/// The mapping is removed when the returned `MappedPage` is dropped.
+pub fn create_example_mapping<'a, 'b>(
+    page: Page, mapper: &'a mut OffsetPageTable<'b>,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>
-)
+) -> Result<MappedPage<'a, 'b>, MapToError<Size4KiB>>
 {
-    use x86_64::structures::paging::Mapper;
     use x86_64::structures::paging::PageTableFlags as Flags;
 
     let frame = PhysFrame::containing_address(PhysAddr::new(0xb8000));
     let flags = Flags::PRESENT | Flags::WRITABLE;
 
-    let map_to_result = unsafe {
+    unsafe {
         // map to is unsafe because the caller must guarantee that the address is not already mapped
-        mapper.map_to(page, frame, flags, frame_allocator)
-    };
-    map_to_result.expect("map_to failed").flush();
+        mapper.map_to(page, frame, flags, frame_allocator)?.flush();
+    }
+    Ok(MappedPage { page, mapper })
+}
+
+/// Where `allocator::init_heap` mapped the kernel heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KernelHeapInfo {
+    pub start: VirtAddr,
+    /// The size of the heap in bytes.
+    pub size: usize,
+    pub page_count: usize,
+    /// The frames taken from the frame allocator, for the heap and for new page tables.
+    pub phys_frames_used: usize,
+}
+
+/// A frame allocator that can also hand out physically contiguous frames.
+pub trait ContiguousFrameAllocator: FrameAllocator<Size4KiB> {
+    /// Allocates `count` physically contiguous frames and returns the first of them,
+    /// or `None` if the allocator cannot provide a contiguous range right now.
+    fn allocate_contiguous(&mut self, count: usize) -> Option<PhysFrame>;
+}
+
+/// Maps `count` pages starting at the page containing `start` to newly allocated frames.
+///
+/// The frames are taken in a single contiguous allocation if possible and one by one
+/// otherwise. Nothing is mapped if any page of the range is mapped already, which
+/// is reported as `MapToError::PageAlreadyMapped`. If mapping fails part way, the pages
+/// mapped so far are unmapped and all frames taken for them are given back.
+pub fn map_page_range(
+    start: VirtAddr,
+    count: usize,
+    flags: PageTableFlags,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut (impl ContiguousFrameAllocator + FrameDeallocator<Size4KiB>),
+) -> Result<(), MapToError<Size4KiB>> {
+    let first_page = Page::<Size4KiB>::containing_address(start);
+    let pages = Page::range(first_page, first_page + count as u64);
+
+    // check the whole range before changing anything
+    for page in pages {
+        if let Ok(frame) = mapper.translate_page(page) {
+            return Err(MapToError::PageAlreadyMapped(frame));
+        }
+    }
+
+    let first_frame = frame_allocator.allocate_contiguous(count);
+    for (i, page) in pages.enumerate() {
+        let frame = match first_frame {
+            Some(first_frame) => Some(first_frame + i as u64),
+            None => frame_allocator.allocate_frame(),
+        };
+        let result = match frame {
+            Some(frame) => unsafe { mapper.map_to(page, frame, flags, frame_allocator) },
+            None => Err(MapToError::FrameAllocationFailed),
+        };
+        match result {
+            Ok(flush) => flush.flush(),
+            Err(err) => {
+                // give back the frames not mapped yet, for a single allocation the rest of it
+                match (first_frame, frame) {
+                    (Some(first_frame), _) => {
+                        for j in (i..count).rev() {
+                            unsafe { frame_allocator.deallocate_frame(first_frame + j as u64) };
+                        }
+                    }
+                    (None, Some(frame)) => unsafe { frame_allocator.deallocate_frame(frame) },
+                    (None, None) => {}
+                }
+                unmap_and_free(first_page, i as u64, mapper, frame_allocator);
+                return Err(err);
+            }
+        }
+    }
+    Ok(())
+}
+
+// MMIO regions mapped with `map_physical_range` are placed one after another starting here,
+// up to the kernel stacks
+const MMIO_VIRT_BASE: u64 = 0x_5555_2000_0000;
+const MMIO_VIRT_END: u64 = KERNEL_STACK_BASE;
+static NEXT_MMIO_ADDR: Mutex<u64> = Mutex::new(MMIO_VIRT_BASE);
+
+/// Errors returned by `map_physical_range`.
+#[derive(Debug)]
+pub enum MmioError {
+    /// The virtual address range for MMIO mappings has no room for `pages` more pages.
+    RegionExhausted { pages: u64 },
+    /// Mapping a page failed.
+    Map(MapToError<Size4KiB>),
+}
+
+impl From<MapToError<Size4KiB>> for MmioError {
+    fn from(err: MapToError<Size4KiB>) -> Self {
+        MmioError::Map(err)
+    }
+}
+
+/// Maps the `size` bytes of physical memory starting at `phys` to a free range of
+/// virtual addresses and returns the virtual address of `phys`.
+///
+/// Meant for MMIO registers of devices, which are not necessarily covered by the
+/// physical memory mapping of the bootloader. The frames are not taken from a frame
+/// allocator, `frame_allocator` only provides frames for new page tables. Pass
+/// `PageTableFlags::NO_CACHE` in `flags` for device registers.
+///
+/// The mapping is never removed. Returns `MmioError::RegionExhausted` if the range
+/// reserved for MMIO mappings is used up.
+pub fn map_physical_range(
+    phys: PhysAddr,
+    size: usize,
+    flags: PageTableFlags,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<VirtAddr, MmioError> {
+    let first_frame = PhysFrame::<Size4KiB>::containing_address(phys);
+    let pages = (phys.offset_in_page() + size as u64).div_ceil(4096);
+
+    let mut next = NEXT_MMIO_ADDR.lock();
+    if pages > (MMIO_VIRT_END - *next) / 4096 {
+        return Err(MmioError::RegionExhausted { pages });
+    }
+    let first_page = Page::<Size4KiB>::containing_address(VirtAddr::new(*next));
+    for i in 0..pages {
+        let flags = flags | PageTableFlags::PRESENT;
+        match unsafe { mapper.map_to(first_page + i, first_frame + i, flags, frame_allocator) } {
+            Ok(flush) => flush.flush(),
+            Err(err) => {
+                // leave nothing behind, the range is handed out again by the next call
+                for page in Page::range(first_page, first_page + i) {
+                    if let Ok((_, flush)) = mapper.unmap(page) {
+                        flush.flush();
+                    }
+                }
+                return Err(err.into());
+            }
+        }
+    }
+    *next += pages * 4096;
+    Ok(first_page.start_address() + phys.offset_in_page())
+}
+
+// kernel stacks are mapped one after another starting here,
+// each with an unmapped guard page below it
+const KERNEL_STACK_BASE: u64 = 0x_5555_8000_0000;
+static NEXT_STACK_ADDR: Mutex<u64> = Mutex::new(KERNEL_STACK_BASE);
+
+/// A kernel stack of at least `SIZE` bytes with an unmapped guard page below it,
+/// so that a stack overflow causes a page fault instead of silently corrupting memory.
+///
+/// The stack is never unmapped, it stays valid for the whole runtime of the kernel.
+pub struct KernelStack<const SIZE: usize> {
+    bottom: VirtAddr,
+}
+
+impl<const SIZE: usize> KernelStack<SIZE> {
+    // the number of mapped pages, SIZE rounded up to whole pages
+    const PAGES: u64 = (SIZE as u64).div_ceil(4096);
+
+    /// Maps a new stack with freshly allocated frames.
+    ///
+    /// If mapping fails, the pages mapped so far are unmapped and their frames are
+    /// returned to `frame_allocator`, and the address range is handed out again.
+    pub fn new(
+        mapper: &mut impl Mapper<Size4KiB>,
+        frame_allocator: &mut (impl FrameAllocator<Size4KiB> + FrameDeallocator<Size4KiB>),
+    ) -> Result<Self, MapToError<Size4KiB>> {
+        // held while mapping, so the range is only used up once the stack is mapped
+        let mut next = NEXT_STACK_ADDR.lock();
+        // the guard page itself is simply left unmapped
+        let bottom = VirtAddr::new(*next + 4096);
+
+        let first_page = Page::<Size4KiB>::containing_address(bottom);
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+        for (i, page) in Page::range(first_page, first_page + Self::PAGES).enumerate() {
+            let result = match frame_allocator.allocate_frame() {
+                Some(frame) => unsafe {
+                    mapper
+                        .map_to(page, frame, flags, frame_allocator)
+                        .inspect_err(|_| frame_allocator.deallocate_frame(frame))
+                },
+                None => Err(MapToError::FrameAllocationFailed),
+            };
+            match result {
+                Ok(flush) => flush.flush(),
+                Err(err) => {
+                    unmap_and_free(first_page, i as u64, mapper, frame_allocator);
+                    return Err(err);
+                }
+            }
+        }
+
+        *next += (Self::PAGES + 1) * 4096;
+        Ok(KernelStack { bottom })
+    }
+
+    /// Returns the lowest address of the stack.
+    pub fn bottom(&self) -> VirtAddr {
+        self.bottom
+    }
+
+    /// Returns the initial stack pointer, the stack grows down from here.
+    pub fn top(&self) -> VirtAddr {
+        // the System V ABI requires a 16 byte aligned stack
+        (self.bottom + Self::PAGES * 4096).align_down(16u64)
+    }
+}
+
+// unmaps `count` pages from `first_page` on and gives their frames back, in reverse so
+// that `BootInfoFrameAllocator` can take back frames it just handed out
+fn unmap_and_free(
+    first_page: Page<Size4KiB>,
+    count: u64,
+    mapper: &mut impl Mapper<Size4KiB>,
+    frame_allocator: &mut impl FrameDeallocator<Size4KiB>,
+) {
+    for i in (0..count).rev() {
+        if let Ok((frame, flush)) = mapper.unmap(first_page + i) {
+            flush.flush();
+            unsafe { frame_allocator.deallocate_frame(frame) };
+        }
+    }
 }
 
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        if self.recycled_count > 0 {
+            self.recycled_count -= 1;
+            return self.recycled[self.recycled_count].take();
+        }
         let frame = self.usable_frames().nth(self.next);
         self.next += 1;
         frame
     }
 }
 
+impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
+    /// Takes back the most recently allocated frame, or keeps up to `RECYCLED_FRAMES`
+    /// other frames for `allocate_frame`. Further frames are lost.
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        if self.next > 0 && self.usable_frames().nth(self.next - 1) == Some(frame) {
+            self.next -= 1;
+        } else if self.recycled_count < RECYCLED_FRAMES {
+            self.recycled[self.recycled_count] = Some(frame);
+            self.recycled_count += 1;
+        }
+    }
+}
+
+impl ContiguousFrameAllocator for BootInfoFrameAllocator {
+    fn allocate_contiguous(&mut self, count: usize) -> Option<PhysFrame> {
+        if count == 0 {
+            return None;
+        }
+        let mut frames = self.usable_frames().skip(self.next);
+        let first = frames.next()?;
+        // the other frames must directly follow the first one, without a gap between regions
+        let contiguous = frames.take(count - 1).enumerate()
+            .take_while(|&(i, frame)| frame == first + (i + 1) as u64)
+            .count();
+        if contiguous != count - 1 {
+            return None;
+        }
+        self.next += count;
+        Some(first)
+    }
+}
+
+// how many freed frames `BootInfoFrameAllocator` keeps for reuse
+const RECYCLED_FRAMES: usize = 64;
+
 /// A FrameAllocator that returns usable frames from the bootloader's memory map.
 pub struct BootInfoFrameAllocator {
     memory_map: &'static MemoryMap,
     next: usize,
+    // the number of usable frames in the memory map, counted once by `init`
+    total_frames: usize,
+    // frames given back with `deallocate_frame`, handed out again first
+    recycled: [Option<PhysFrame>; RECYCLED_FRAMES],
+    recycled_count: usize,
 }
 
 impl BootInfoFrameAllocator {
@@ -129,15 +511,34 @@ impl BootInfoFrameAllocator {
     /// memory map is valid. The main requirement is that all frames that are marked
     /// as `USABLE` in it are really unused.
     pub unsafe fn init(memory_map: &'static MemoryMap) -> Self {
+        let end = memory_map.iter().map(|r| r.range.end_addr()).max().unwrap_or(0);
+        PHYSICAL_MEMORY_END.store(end, Ordering::Relaxed);
+        let total_frames = memory_map.iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable)
+            .map(|r| (r.range.end_addr() - r.range.start_addr()).div_ceil(4096) as usize)
+            .sum();
         BootInfoFrameAllocator {
             memory_map,
             next: 0,
+            total_frames,
+            recycled: [None; RECYCLED_FRAMES],
+            recycled_count: 0,
         }
     }
 
+    /// Returns the number of frames that can still be allocated.
+    ///
+    /// Frames are handed out in the order of the memory map, so these are all usable
+    /// frames after the last allocated one, plus the freed frames kept for reuse.
+    pub fn remaining_frames(&self) -> usize {
+        self.total_frames.saturating_sub(self.next) + self.recycled_count
+    }
+
 
     /// Returns an iterator over the usable frames specified in the memory map.
-    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
+    ///
+    /// This includes the frames that have already been allocated.
+    pub fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
         // get usable regions from memory map
         let regions = self.memory_map.iter();
         let usable_regions = regions
@@ -154,4 +555,36 @@ impl BootInfoFrameAllocator {
         frame_addresses
             .map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
     }
-}
\ No newline at end of file
+
+    /// Returns the usable memory as ranges of contiguous frames, merging adjacent regions.
+    pub fn frame_ranges(&self) -> impl Iterator<Item = Range<PhysFrame>> {
+        let mut regions = self.memory_map.iter()
+            .filter(|r| r.region_type == MemoryRegionType::Usable)
+            .map(|r| {
+                let start = PhysFrame::containing_address(PhysAddr::new(r.range.start_addr()));
+                let end = PhysFrame::containing_address(PhysAddr::new(r.range.end_addr()));
+                start..end
+            })
+            .peekable();
+
+        core::iter::from_fn(move || {
+            let mut range = regions.next()?;
+            // the memory map is sorted, so adjacent regions follow each other
+            while let Some(next) = regions.next_if(|next| next.start == range.end) {
+                range.end = next.end;
+            }
+            Some(range)
+        })
+    }
+}
+
+#[test_case]
+fn test_address_checks() {
+    assert!(is_kernel_addr(VirtAddr::new(0xffff_8000_0000_0000)));
+    assert!(!is_kernel_addr(VirtAddr::new(0x4444_4444_0000)));
+
+    // the test kernel initializes a frame allocator, so the memory end is known
+    let offset = VirtAddr::new(0x1_0000_0000);
+    assert_eq!(phys_to_virt(PhysAddr::new(0x1000), offset), Some(VirtAddr::new(0x1_0000_1000)));
+    assert_eq!(phys_to_virt(PhysAddr::new(0x000f_ffff_ffff_ffff), offset), None);
+}
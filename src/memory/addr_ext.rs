@@ -0,0 +1,88 @@
+//! Page alignment helpers for the address types of the `x86_64` crate.
+
+use x86_64::{PhysAddr, VirtAddr};
+
+const PAGE_SIZE: u64 = 4096;
+
+/// Page alignment operations on `VirtAddr`.
+pub trait VirtAddrExt {
+    /// Rounds down to the start of the containing 4 KiB page.
+    fn align_down_to_page(self) -> Self;
+    /// Rounds up to the next 4 KiB page boundary.
+    fn align_up_to_page(self) -> Self;
+    /// Returns the offset into the 4 KiB page, bits 0-11.
+    ///
+    /// Named differently from `VirtAddr::page_offset`, which returns a `PageOffset`.
+    fn offset_in_page(self) -> u64;
+    fn is_page_aligned(&self) -> bool;
+    /// Returns the number of the containing 4 KiB page, i.e. the address divided by 4096.
+    fn page_number(self) -> u64;
+}
+
+/// Page alignment operations on `PhysAddr`.
+pub trait PhysAddrExt {
+    /// Rounds down to the start of the containing 4 KiB frame.
+    fn align_down_to_page(self) -> Self;
+    /// Rounds up to the next 4 KiB frame boundary.
+    fn align_up_to_page(self) -> Self;
+    /// Returns the offset into the 4 KiB frame, bits 0-11.
+    fn offset_in_page(self) -> u64;
+    fn is_page_aligned(&self) -> bool;
+    /// Returns the number of the containing 4 KiB frame, i.e. the address divided by 4096.
+    fn page_number(self) -> u64;
+}
+
+impl VirtAddrExt for VirtAddr {
+    fn align_down_to_page(self) -> Self {
+        self.align_down(PAGE_SIZE)
+    }
+
+    fn align_up_to_page(self) -> Self {
+        self.align_up(PAGE_SIZE)
+    }
+
+    fn offset_in_page(self) -> u64 {
+        self.as_u64() % PAGE_SIZE
+    }
+
+    fn is_page_aligned(&self) -> bool {
+        self.is_aligned(PAGE_SIZE)
+    }
+
+    fn page_number(self) -> u64 {
+        self.as_u64() / PAGE_SIZE
+    }
+}
+
+impl PhysAddrExt for PhysAddr {
+    fn align_down_to_page(self) -> Self {
+        self.align_down(PAGE_SIZE)
+    }
+
+    fn align_up_to_page(self) -> Self {
+        self.align_up(PAGE_SIZE)
+    }
+
+    fn offset_in_page(self) -> u64 {
+        self.as_u64() % PAGE_SIZE
+    }
+
+    fn is_page_aligned(&self) -> bool {
+        self.is_aligned(PAGE_SIZE)
+    }
+
+    fn page_number(self) -> u64 {
+        self.as_u64() / PAGE_SIZE
+    }
+}
+
+#[test_case]
+fn test_page_alignment() {
+    let addr = PhysAddr::new(0x1234_5678);
+    assert_eq!(addr.align_down_to_page(), PhysAddr::new(0x1234_5000));
+    assert_eq!(addr.align_up_to_page(), PhysAddr::new(0x1234_6000));
+    assert_eq!(addr.offset_in_page(), 0x678);
+    assert_eq!(addr.page_number(), 0x12345);
+    assert!(!addr.is_page_aligned());
+    assert!(VirtAddr::new(0x4444_4444_0000).is_page_aligned());
+}
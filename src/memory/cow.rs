@@ -0,0 +1,133 @@
+//! Copy-on-write pages.
+//!
+//! A shared page is mapped without `WRITABLE` and with the `COPY_ON_WRITE` bit, which
+//! the CPU ignores. A write to it raises a page fault, and the `CopyOnWriteResolver`
+//! gives the writing mapping a private copy of the frame.
+//!
+//! There is no reference count per frame, so the last mapping of a shared frame
+//! still gets a copy and the original frame is never freed.
+
+use alloc::boxed::Box;
+use core::ptr;
+use super::page_fault::{self, PageFaultResolver, Unresolved};
+use x86_64::{
+    structures::idt::PageFaultErrorCode,
+    structures::paging::{
+        mapper::{FlagUpdateError, MapToError, MappedFrame, TranslateResult},
+        FrameAllocator, Mapper, OffsetPageTable, Page, PageTableFlags, PhysFrame, Size4KiB,
+        Translate,
+    },
+    VirtAddr,
+};
+
+/// Marks a page as copy-on-write, one of the PTE bits available to the OS.
+pub const COPY_ON_WRITE: PageTableFlags = PageTableFlags::BIT_9;
+
+const PAGE_SIZE: usize = 4096;
+
+/// Resolves writes to copy-on-write pages by copying them.
+pub struct CopyOnWriteResolver {
+    physical_memory_offset: VirtAddr,
+    frame_allocator: Box<dyn FrameAllocator<Size4KiB> + Send>,
+}
+
+/// Registers a `CopyOnWriteResolver` that takes the frames for the copies from
+/// `frame_allocator`. Copy-on-write faults are not resolved before this is called.
+pub fn init(
+    physical_memory_offset: VirtAddr,
+    frame_allocator: impl FrameAllocator<Size4KiB> + Send + 'static,
+) {
+    page_fault::register_resolver(Box::new(CopyOnWriteResolver {
+        physical_memory_offset,
+        frame_allocator: Box::new(frame_allocator),
+    }));
+}
+
+/// Maps the frame behind `src_virt` in the active page table to the same address
+/// in `dst_mapper`, read-only and marked as copy-on-write.
+///
+/// The source mapping is not changed. If it is writable, call `mark_copy_on_write`
+/// on it as well, otherwise writes through it show up in the shared page.
+///
+/// Panics if `src_virt` is not mapped in the active page table.
+pub fn share_page(
+    src_virt: VirtAddr,
+    dst_mapper: &mut OffsetPageTable,
+    frame_allocator: &mut impl FrameAllocator<Size4KiB>,
+) -> Result<(), MapToError<Size4KiB>> {
+    let page = Page::<Size4KiB>::containing_address(src_virt);
+    let active = unsafe { super::translate_addr(page.start_address(), dst_mapper.phys_offset()) };
+    let frame = PhysFrame::containing_address(active.expect("shared page is not mapped"));
+
+    let flags = PageTableFlags::PRESENT | COPY_ON_WRITE;
+    unsafe { dst_mapper.map_to(page, frame, flags, frame_allocator)?.flush() };
+    Ok(())
+}
+
+/// Makes `page` read-only and marks it as copy-on-write, so that the next write to it
+/// copies the frame.
+pub fn mark_copy_on_write(
+    page: Page<Size4KiB>,
+    mapper: &mut OffsetPageTable,
+) -> Result<(), FlagUpdateError> {
+    let flags = match mapper.translate(page.start_address()) {
+        TranslateResult::Mapped { flags, .. } => flags,
+        _ => return Err(FlagUpdateError::PageNotMapped),
+    };
+    let flags = (flags - PageTableFlags::WRITABLE) | COPY_ON_WRITE;
+    unsafe { mapper.update_flags(page, flags)?.flush() };
+    Ok(())
+}
+
+impl PageFaultResolver for CopyOnWriteResolver {
+    fn resolve(&mut self, addr: VirtAddr, error_code: PageFaultErrorCode) -> Result<(), Unresolved> {
+        let write_to_present = PageFaultErrorCode::PROTECTION_VIOLATION | PageFaultErrorCode::CAUSED_BY_WRITE;
+        if !error_code.contains(write_to_present) {
+            return Err(Unresolved);
+        }
+        copy_page(addr, self.physical_memory_offset, &mut *self.frame_allocator).then_some(()).ok_or(Unresolved)
+    }
+}
+
+// gives the page containing `addr` a private, writable copy of its frame
+// if it is marked as copy-on-write, and returns whether it did
+fn copy_page(
+    addr: VirtAddr,
+    offset: VirtAddr,
+    frame_allocator: &mut (dyn FrameAllocator<Size4KiB> + Send),
+) -> bool {
+    // the fault can interrupt code that holds another `OffsetPageTable`, but that code
+    // is not running while the handler changes this one entry
+    let mut mapper = unsafe { OffsetPageTable::new(super::active_level_4_table(offset), offset) };
+
+    let page = Page::<Size4KiB>::containing_address(addr);
+    let (old_frame, flags) = match mapper.translate(page.start_address()) {
+        TranslateResult::Mapped { frame: MappedFrame::Size4KiB(frame), flags, .. } => (frame, flags),
+        _ => return false,
+    };
+    if !flags.contains(COPY_ON_WRITE) || flags.contains(PageTableFlags::WRITABLE) {
+        return false;
+    }
+    let new_frame = match frame_allocator.allocate_frame() {
+        Some(frame) => frame,
+        None => return false,
+    };
+
+    unsafe {
+        let src = (offset + old_frame.start_address().as_u64()).as_ptr::<u8>();
+        let dst = (offset + new_frame.start_address().as_u64()).as_mut_ptr::<u8>();
+        ptr::copy_nonoverlapping(src, dst, PAGE_SIZE);
+
+        let flags = (flags - COPY_ON_WRITE) | PageTableFlags::WRITABLE;
+        match mapper.unmap(page) {
+            Ok((_, flush)) => flush.flush(),
+            Err(_) => return false,
+        }
+        // the page tables exist already, so `map_to` does not allocate
+        match mapper.map_to(page, new_frame, flags, frame_allocator) {
+            Ok(flush) => flush.flush(),
+            Err(_) => return false,
+        }
+    }
+    true
+}
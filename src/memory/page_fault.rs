@@ -0,0 +1,78 @@
+//! Page faults that the kernel can resolve, e.g. by copying or loading a page.
+//!
+//! The page fault handler asks the registered resolvers in order. If one of them
+//! resolves the fault, the handler returns and the faulting instruction is retried.
+
+use alloc::{boxed::Box, vec::Vec};
+use spin::Mutex;
+use x86_64::{structures::idt::PageFaultErrorCode, VirtAddr};
+
+/// Returned by a `PageFaultResolver` that is not responsible for a fault.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Unresolved;
+
+/// Resolves page faults of one kind, e.g. writes to copy-on-write pages.
+///
+/// Resolvers run in the page fault handler, so they must not cause page faults themselves
+/// and must not wait for locks that the faulting code might hold.
+pub trait PageFaultResolver: Send {
+    /// Makes the access to `addr` succeed when it is retried, or returns `Err(Unresolved)`
+    /// without changing anything.
+    fn resolve(&mut self, addr: VirtAddr, error_code: PageFaultErrorCode) -> Result<(), Unresolved>;
+}
+
+/// The resolvers asked by the page fault handler, in order.
+pub static PAGE_FAULT_RESOLVERS: Mutex<Vec<Box<dyn PageFaultResolver>>> = Mutex::new(Vec::new());
+
+/// Adds a resolver to the end of the chain.
+pub fn register_resolver(resolver: Box<dyn PageFaultResolver>) {
+    // a page fault while the lock is held would leave the fault unresolved
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        PAGE_FAULT_RESOLVERS.lock().push(resolver);
+    });
+}
+
+/// Called by the page fault handler. Returns `Ok` if a resolver resolved the fault,
+/// in which case the handler returns and the faulting instruction is retried.
+pub fn page_fault_handler_recoverable(
+    addr: VirtAddr,
+    error_code: PageFaultErrorCode,
+) -> Result<(), Unresolved> {
+    // the fault can happen while the chain is locked, e.g. inside a resolver
+    let mut resolvers = PAGE_FAULT_RESOLVERS.try_lock().ok_or(Unresolved)?;
+    for resolver in resolvers.iter_mut() {
+        if resolver.resolve(addr, error_code).is_ok() {
+            return Ok(());
+        }
+    }
+    Err(Unresolved)
+}
+
+#[test_case]
+fn test_resolvers_run_in_order() {
+    struct Counting(&'static core::sync::atomic::AtomicUsize, bool);
+
+    impl PageFaultResolver for Counting {
+        fn resolve(&mut self, _: VirtAddr, _: PageFaultErrorCode) -> Result<(), Unresolved> {
+            self.0.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+            if self.1 { Ok(()) } else { Err(Unresolved) }
+        }
+    }
+
+    use core::sync::atomic::{AtomicUsize, Ordering};
+    static FIRST: AtomicUsize = AtomicUsize::new(0);
+    static SECOND: AtomicUsize = AtomicUsize::new(0);
+
+    // an address no other resolver is responsible for
+    let addr = VirtAddr::new(0xdead_b000);
+    let error_code = PageFaultErrorCode::CAUSED_BY_WRITE;
+    let registered = PAGE_FAULT_RESOLVERS.lock().len();
+    register_resolver(Box::new(Counting(&FIRST, false)));
+    assert_eq!(page_fault_handler_recoverable(addr, error_code), Err(Unresolved));
+    register_resolver(Box::new(Counting(&SECOND, true)));
+    assert_eq!(page_fault_handler_recoverable(addr, error_code), Ok(()));
+    assert_eq!(FIRST.load(Ordering::Relaxed), 2);
+    assert_eq!(SECOND.load(Ordering::Relaxed), 1);
+
+    PAGE_FAULT_RESOLVERS.lock().truncate(registered);
+}
@@ -0,0 +1,128 @@
+use alloc::collections::BTreeMap;
+use spin::Mutex;
+use super::{ethernet, Ipv4Addr, MacAddr, INTERFACE};
+
+// length of an ARP packet for IPv4 over Ethernet
+const ARP_LEN: usize = 28;
+/// Length of an Ethernet frame carrying an ARP packet.
+pub const FRAME_LEN: usize = ethernet::HEADER_LEN + ARP_LEN;
+
+const HTYPE_ETHERNET: u16 = 1;
+const OPER_REQUEST: u16 = 1;
+const OPER_REPLY: u16 = 2;
+
+/// Maps IPv4 addresses to the MAC addresses they were last seen with.
+#[derive(Debug, Default)]
+pub struct ArpTable {
+    entries: BTreeMap<Ipv4Addr, MacAddr>,
+}
+
+impl ArpTable {
+    pub const fn new() -> Self {
+        ArpTable { entries: BTreeMap::new() }
+    }
+
+    pub fn insert(&mut self, ip: Ipv4Addr, mac: MacAddr) {
+        self.entries.insert(ip, mac);
+    }
+
+    pub fn lookup(&self, ip: Ipv4Addr) -> Option<MacAddr> {
+        self.entries.get(&ip).copied()
+    }
+}
+
+static ARP_TABLE: Mutex<ArpTable> = Mutex::new(ArpTable::new());
+
+/// A complete Ethernet frame containing an ARP packet, ready to be sent.
+#[derive(Clone, Copy)]
+pub struct ArpFrame([u8; FRAME_LEN]);
+
+/// The reply to an ARP request for our address.
+pub type ArpResponse = ArpFrame;
+/// A broadcast request asking for the MAC address of an IPv4 address.
+pub type ArpRequestPacket = ArpFrame;
+
+impl ArpFrame {
+    fn new(oper: u16, dst: MacAddr, target_mac: MacAddr, target_ip: Ipv4Addr) -> ArpFrame {
+        let interface = INTERFACE.lock();
+        let mut frame = [0; FRAME_LEN];
+        ethernet::write_header(&mut frame, dst, interface.mac, ethernet::ETHERTYPE_ARP);
+
+        let arp = &mut frame[ethernet::HEADER_LEN..];
+        arp[0..2].copy_from_slice(&HTYPE_ETHERNET.to_be_bytes());
+        arp[2..4].copy_from_slice(&ethernet::ETHERTYPE_IPV4.to_be_bytes());
+        arp[4] = 6; // hardware address length
+        arp[5] = 4; // protocol address length
+        arp[6..8].copy_from_slice(&oper.to_be_bytes());
+        arp[8..14].copy_from_slice(&interface.mac.0);
+        arp[14..18].copy_from_slice(&interface.ip.0);
+        arp[18..24].copy_from_slice(&target_mac.0);
+        arp[24..28].copy_from_slice(&target_ip.0);
+        ArpFrame(frame)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Handles a received Ethernet frame carrying an ARP packet.
+///
+/// Records the sender in the ARP table and returns the reply to send
+/// if the packet is a request for our IPv4 address.
+pub fn handle_packet(pkt: &[u8]) -> Option<ArpResponse> {
+    let frame = ethernet::parse(pkt)?;
+    if frame.ethertype != ethernet::ETHERTYPE_ARP || frame.payload.len() < ARP_LEN {
+        return None;
+    }
+
+    let arp = frame.payload;
+    let htype = u16::from_be_bytes([arp[0], arp[1]]);
+    let ptype = u16::from_be_bytes([arp[2], arp[3]]);
+    if htype != HTYPE_ETHERNET || ptype != ethernet::ETHERTYPE_IPV4 || arp[4] != 6 || arp[5] != 4 {
+        return None;
+    }
+    let oper = u16::from_be_bytes([arp[6], arp[7]]);
+    let sender_mac = MacAddr([arp[8], arp[9], arp[10], arp[11], arp[12], arp[13]]);
+    let sender_ip = Ipv4Addr([arp[14], arp[15], arp[16], arp[17]]);
+    let target_ip = Ipv4Addr([arp[24], arp[25], arp[26], arp[27]]);
+
+    ARP_TABLE.lock().insert(sender_ip, sender_mac);
+
+    let our_ip = INTERFACE.lock().ip;
+    if oper == OPER_REQUEST && target_ip == our_ip && our_ip != Ipv4Addr::UNSPECIFIED {
+        Some(ArpFrame::new(OPER_REPLY, sender_mac, sender_mac, sender_ip))
+    } else {
+        None
+    }
+}
+
+/// Returns the MAC address of `ip` if it is in the ARP table.
+pub fn lookup(ip: Ipv4Addr) -> Option<MacAddr> {
+    ARP_TABLE.lock().lookup(ip)
+}
+
+/// Builds a broadcast request for the MAC address of `ip`.
+pub fn send_request(ip: Ipv4Addr) -> ArpRequestPacket {
+    ArpFrame::new(OPER_REQUEST, MacAddr::BROADCAST, MacAddr::default(), ip)
+}
+
+#[test_case]
+fn test_arp_request_for_our_ip() {
+    let our_mac = MacAddr([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+    let our_ip = Ipv4Addr::new(10, 0, 2, 15);
+
+    // build the request as the gateway would
+    let gateway_mac = MacAddr([0x52, 0x55, 0x0a, 0x00, 0x02, 0x02]);
+    let gateway_ip = Ipv4Addr::new(10, 0, 2, 2);
+    super::set_interface(gateway_mac, gateway_ip);
+    let request = send_request(our_ip);
+    super::set_interface(our_mac, our_ip);
+
+    let reply = handle_packet(request.as_bytes()).expect("no reply to ARP request");
+    assert_eq!(lookup(gateway_ip), Some(gateway_mac));
+    let frame = ethernet::parse(reply.as_bytes()).unwrap();
+    assert_eq!(frame.dst, gateway_mac);
+    assert_eq!(frame.src, our_mac);
+    assert_eq!(&frame.payload[6..8], &OPER_REPLY.to_be_bytes());
+}
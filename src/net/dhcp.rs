@@ -0,0 +1,277 @@
+use crate::interrupts::ticks;
+use super::{udp::UdpSocket, Ipv4Addr, MacAddr, NetError, NetworkDevice};
+
+/// UDP port DHCP clients listen on.
+pub const CLIENT_PORT: u16 = 68;
+/// UDP port DHCP servers listen on.
+pub const SERVER_PORT: u16 = 67;
+
+// BOOTP operations
+const BOOTREQUEST: u8 = 1;
+const BOOTREPLY: u8 = 2;
+// fixed part of a BOOTP message up to the magic cookie
+const BOOTP_LEN: usize = 236;
+const MAGIC_COOKIE: [u8; 4] = [0x63, 0x82, 0x53, 0x63];
+// offset of the first option
+const OPTIONS_START: usize = BOOTP_LEN + 4;
+// largest message a client must be able to receive
+const MAX_MESSAGE_LEN: usize = 548;
+
+// options from RFC 2132
+const OPT_PAD: u8 = 0;
+const OPT_SUBNET_MASK: u8 = 1;
+const OPT_ROUTER: u8 = 3;
+const OPT_DNS: u8 = 6;
+const OPT_REQUESTED_IP: u8 = 50;
+const OPT_LEASE_TIME: u8 = 51;
+const OPT_MESSAGE_TYPE: u8 = 53;
+const OPT_SERVER_ID: u8 = 54;
+const OPT_PARAMETER_LIST: u8 = 55;
+const OPT_END: u8 = 255;
+
+// values of the message type option
+const DHCPDISCOVER: u8 = 1;
+const DHCPOFFER: u8 = 2;
+const DHCPREQUEST: u8 = 3;
+const DHCPACK: u8 = 5;
+const DHCPNAK: u8 = 6;
+
+// how long to wait for each reply, about 5 seconds at the default PIT rate
+const REPLY_TIMEOUT_TICKS: u64 = 91;
+
+/// The configuration handed out by the DHCP server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DhcpLease {
+    pub ip: Ipv4Addr,
+    pub gateway: Ipv4Addr,
+    pub subnet: Ipv4Addr,
+    pub dns: Ipv4Addr,
+    pub lease_seconds: u32,
+}
+
+/// Errors returned by `DhcpClient::discover`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DhcpError {
+    /// Sending a message failed.
+    Net(NetError),
+    /// No matching reply arrived in time.
+    Timeout,
+    /// The server declined our request.
+    Nak,
+}
+
+impl From<NetError> for DhcpError {
+    fn from(err: NetError) -> Self {
+        DhcpError::Net(err)
+    }
+}
+
+/// A DHCP client performing the DISCOVER, OFFER, REQUEST, ACK exchange.
+pub struct DhcpClient {
+    mac: MacAddr,
+    xid: u32,
+}
+
+// the fields of a server reply we care about
+#[derive(Debug, Default)]
+struct Reply {
+    message_type: u8,
+    your_ip: Ipv4Addr,
+    server_id: Option<Ipv4Addr>,
+    subnet: Ipv4Addr,
+    gateway: Ipv4Addr,
+    dns: Ipv4Addr,
+    lease_seconds: u32,
+}
+
+impl DhcpClient {
+    pub fn new(mac: MacAddr) -> DhcpClient {
+        // the transaction ID only has to differ between clients and attempts
+        let [_, _, a, b, c, d] = mac.0;
+        let ticks = ticks() as u32;
+        DhcpClient {
+            mac,
+            xid: u32::from_be_bytes([a, b, c, d]) ^ ticks,
+        }
+    }
+
+    /// Obtains a lease and configures the network interface with the leased address.
+    ///
+    /// `socket` must be bound to `CLIENT_PORT`.
+    pub async fn discover<D: NetworkDevice>(
+        &mut self,
+        socket: &mut UdpSocket<'_, D>,
+    ) -> Result<DhcpLease, DhcpError> {
+        let mut message = [0u8; MAX_MESSAGE_LEN];
+
+        let len = self.build_message(&mut message, DHCPDISCOVER, None);
+        socket.send_to(&message[..len], Ipv4Addr::BROADCAST, SERVER_PORT)?;
+        let offer = self.wait_for_reply(socket, &[DHCPOFFER]).await?;
+
+        let len = self.build_message(&mut message, DHCPREQUEST, Some(&offer));
+        socket.send_to(&message[..len], Ipv4Addr::BROADCAST, SERVER_PORT)?;
+        let ack = self.wait_for_reply(socket, &[DHCPACK, DHCPNAK]).await?;
+        if ack.message_type == DHCPNAK {
+            return Err(DhcpError::Nak);
+        }
+
+        let lease = DhcpLease {
+            ip: ack.your_ip,
+            gateway: ack.gateway,
+            subnet: ack.subnet,
+            dns: ack.dns,
+            lease_seconds: ack.lease_seconds,
+        };
+        super::set_interface(self.mac, lease.ip);
+        Ok(lease)
+    }
+
+    /// Writes a client message into `buf` and returns its length.
+    ///
+    /// A DHCPREQUEST asks for the address in `offer` from the server that offered it.
+    fn build_message(&self, buf: &mut [u8; MAX_MESSAGE_LEN], message_type: u8, offer: Option<&Reply>) -> usize {
+        buf.fill(0);
+        buf[0] = BOOTREQUEST;
+        buf[1] = 1; // hardware type Ethernet
+        buf[2] = 6; // hardware address length
+        buf[4..8].copy_from_slice(&self.xid.to_be_bytes());
+        // ask the server to broadcast its replies since we have no address yet
+        buf[10] = 0x80;
+        buf[28..34].copy_from_slice(&self.mac.0);
+        buf[BOOTP_LEN..OPTIONS_START].copy_from_slice(&MAGIC_COOKIE);
+
+        let mut options = OptionWriter { buf, pos: OPTIONS_START };
+        options.push(OPT_MESSAGE_TYPE, &[message_type]);
+        if let Some(offer) = offer {
+            options.push(OPT_REQUESTED_IP, &offer.your_ip.0);
+            if let Some(server_id) = offer.server_id {
+                options.push(OPT_SERVER_ID, &server_id.0);
+            }
+        }
+        options.push(OPT_PARAMETER_LIST, &[OPT_SUBNET_MASK, OPT_ROUTER, OPT_DNS, OPT_LEASE_TIME]);
+        options.buf[options.pos] = OPT_END;
+
+        // BOOTP relays expect messages of at least 300 bytes
+        (options.pos + 1).max(300)
+    }
+
+    async fn wait_for_reply<D: NetworkDevice>(
+        &self,
+        socket: &mut UdpSocket<'_, D>,
+        message_types: &[u8],
+    ) -> Result<Reply, DhcpError> {
+        let deadline = ticks() + REPLY_TIMEOUT_TICKS;
+        let mut buf = [0u8; MAX_MESSAGE_LEN];
+
+        while ticks() < deadline {
+            if let Some((len, _, _)) = socket.try_recv_from(&mut buf) {
+                match self.parse_reply(&buf[..len]) {
+                    Some(reply) if message_types.contains(&reply.message_type) => return Ok(reply),
+                    _ => continue,
+                }
+            }
+            crate::task::yield_now().await;
+        }
+        Err(DhcpError::Timeout)
+    }
+
+    /// Parses a server reply, ignoring replies to other transactions and other clients.
+    fn parse_reply(&self, msg: &[u8]) -> Option<Reply> {
+        if msg.len() < OPTIONS_START || msg[0] != BOOTREPLY
+            || msg[4..8] != self.xid.to_be_bytes() || msg[28..34] != self.mac.0
+            || msg[BOOTP_LEN..OPTIONS_START] != MAGIC_COOKIE
+        {
+            return None;
+        }
+
+        let mut reply = Reply {
+            your_ip: read_addr(&msg[16..20]),
+            ..Reply::default()
+        };
+
+        let mut pos = OPTIONS_START;
+        while pos < msg.len() {
+            let code = msg[pos];
+            if code == OPT_END {
+                break;
+            }
+            if code == OPT_PAD {
+                pos += 1;
+                continue;
+            }
+            let len = usize::from(*msg.get(pos + 1)?);
+            let value = msg.get(pos + 2..pos + 2 + len)?;
+            match (code, len) {
+                (OPT_MESSAGE_TYPE, 1) => reply.message_type = value[0],
+                (OPT_SERVER_ID, 4) => reply.server_id = Some(read_addr(value)),
+                (OPT_SUBNET_MASK, 4) => reply.subnet = read_addr(value),
+                // routers and DNS servers are lists, we only use the first entry
+                (OPT_ROUTER, 4..) => reply.gateway = read_addr(value),
+                (OPT_DNS, 4..) => reply.dns = read_addr(value),
+                (OPT_LEASE_TIME, 4) => {
+                    reply.lease_seconds = u32::from_be_bytes([value[0], value[1], value[2], value[3]])
+                }
+                _ => {}
+            }
+            pos += 2 + len;
+        }
+
+        Some(reply)
+    }
+}
+
+// appends options behind the magic cookie
+struct OptionWriter<'a> {
+    buf: &'a mut [u8; MAX_MESSAGE_LEN],
+    pos: usize,
+}
+
+impl OptionWriter<'_> {
+    fn push(&mut self, code: u8, value: &[u8]) {
+        self.buf[self.pos] = code;
+        self.buf[self.pos + 1] = value.len() as u8;
+        self.buf[self.pos + 2..self.pos + 2 + value.len()].copy_from_slice(value);
+        self.pos += 2 + value.len();
+    }
+}
+
+fn read_addr(bytes: &[u8]) -> Ipv4Addr {
+    Ipv4Addr([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+#[test_case]
+fn test_build_message_and_parse_reply() {
+    let client = DhcpClient { mac: MacAddr([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]), xid: 0x1234_5678 };
+    let mut message = [0u8; MAX_MESSAGE_LEN];
+    let len = client.build_message(&mut message, DHCPDISCOVER, None);
+    assert!(len >= 300);
+    assert_eq!(&message[4..8], &0x1234_5678u32.to_be_bytes());
+    assert_eq!(&message[28..34], &client.mac.0);
+    assert_eq!(&message[OPTIONS_START..OPTIONS_START + 3], &[OPT_MESSAGE_TYPE, 1, DHCPDISCOVER]);
+
+    // turn the discover into the offer a server would send back
+    message[0] = BOOTREPLY;
+    message[16..20].copy_from_slice(&[10, 0, 2, 15]);
+    message[OPTIONS_START..].fill(0);
+    let mut options = OptionWriter { buf: &mut message, pos: OPTIONS_START };
+    options.push(OPT_MESSAGE_TYPE, &[DHCPOFFER]);
+    options.push(OPT_SERVER_ID, &[10, 0, 2, 2]);
+    options.push(OPT_SUBNET_MASK, &[255, 255, 255, 0]);
+    options.push(OPT_ROUTER, &[10, 0, 2, 2]);
+    options.push(OPT_DNS, &[10, 0, 2, 3, 8, 8, 8, 8]);
+    options.push(OPT_LEASE_TIME, &86400u32.to_be_bytes());
+    options.buf[options.pos] = OPT_END;
+
+    let reply = client.parse_reply(&message).expect("offer not accepted");
+    assert_eq!(reply.message_type, DHCPOFFER);
+    assert_eq!(reply.your_ip, Ipv4Addr::new(10, 0, 2, 15));
+    assert_eq!(reply.server_id, Some(Ipv4Addr::new(10, 0, 2, 2)));
+    assert_eq!(reply.subnet, Ipv4Addr::new(255, 255, 255, 0));
+    assert_eq!(reply.gateway, Ipv4Addr::new(10, 0, 2, 2));
+    assert_eq!(reply.dns, Ipv4Addr::new(10, 0, 2, 3));
+    assert_eq!(reply.lease_seconds, 86400);
+
+    // the same transaction ID from a client with another address
+    let other = DhcpClient { mac: MacAddr([0x52, 0x54, 0x00, 0x12, 0x34, 0x57]), xid: client.xid };
+    assert!(other.parse_reply(&message).is_none());
+}
@@ -0,0 +1,41 @@
+use super::MacAddr;
+
+/// Length of an Ethernet II header.
+pub const HEADER_LEN: usize = 14;
+
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+
+/// Writes an Ethernet II header to the start of `buf`.
+///
+/// Panics if `buf` is shorter than `HEADER_LEN`.
+pub fn write_header(buf: &mut [u8], dst: MacAddr, src: MacAddr, ethertype: u16) {
+    buf[0..6].copy_from_slice(&dst.0);
+    buf[6..12].copy_from_slice(&src.0);
+    buf[12..14].copy_from_slice(&ethertype.to_be_bytes());
+}
+
+/// The fields of a received Ethernet II frame.
+pub struct Frame<'a> {
+    pub dst: MacAddr,
+    pub src: MacAddr,
+    pub ethertype: u16,
+    pub payload: &'a [u8],
+}
+
+/// Splits a received frame into its header fields and payload.
+pub fn parse(frame: &[u8]) -> Option<Frame<'_>> {
+    if frame.len() < HEADER_LEN {
+        return None;
+    }
+    let mut dst = [0; 6];
+    let mut src = [0; 6];
+    dst.copy_from_slice(&frame[0..6]);
+    src.copy_from_slice(&frame[6..12]);
+    Some(Frame {
+        dst: MacAddr(dst),
+        src: MacAddr(src),
+        ethertype: u16::from_be_bytes([frame[12], frame[13]]),
+        payload: &frame[HEADER_LEN..],
+    })
+}
@@ -0,0 +1,71 @@
+use alloc::vec::Vec;
+use super::{checksum, ipv4::{Ipv4Packet, PROTOCOL_ICMP}, Ipv4Addr, NetError, INTERFACE};
+
+const TYPE_ECHO_REPLY: u8 = 0;
+const TYPE_ECHO_REQUEST: u8 = 8;
+// type, code, checksum, identifier and sequence number
+const ECHO_HEADER_LEN: usize = 8;
+
+/// An echo reply to be sent back to the host that pinged us.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IcmpReply {
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+    /// The ICMP message including its header.
+    pub message: Vec<u8>,
+}
+
+impl IcmpReply {
+    /// Writes the reply as an IPv4 packet into `buf` and returns its length.
+    pub fn write_ipv4(&self, buf: &mut [u8]) -> Result<usize, NetError> {
+        let mut packet = Ipv4Packet::new(buf)?;
+        packet.set_src(self.src);
+        packet.set_dst(self.dst);
+        packet.set_protocol(PROTOCOL_ICMP);
+        packet.set_payload(&self.message)?;
+        Ok(packet.finalize())
+    }
+}
+
+/// Handles an ICMP message received from `src_ip`.
+///
+/// Returns the echo reply if `data` is a valid echo request, the identifier,
+/// sequence number and data are copied from the request.
+pub fn handle_packet(src_ip: Ipv4Addr, data: &[u8]) -> Option<IcmpReply> {
+    if data.len() < ECHO_HEADER_LEN || checksum(data) != 0 {
+        return None;
+    }
+    let (typ, code) = (data[0], data[1]);
+    if typ != TYPE_ECHO_REQUEST || code != 0 {
+        return None;
+    }
+
+    let mut message = Vec::from(data);
+    message[0] = TYPE_ECHO_REPLY;
+    message[2..4].fill(0);
+    let sum = checksum(&message);
+    message[2..4].copy_from_slice(&sum.to_be_bytes());
+
+    Some(IcmpReply {
+        src: INTERFACE.lock().ip,
+        dst: src_ip,
+        message,
+    })
+}
+
+#[test_case]
+fn test_echo_request_reply() {
+    // echo request with identifier 1, sequence number 1 and "abcd" as data
+    let request = [0x08, 0x00, 0x33, 0x37, 0x00, 0x01, 0x00, 0x01, b'a', b'b', b'c', b'd'];
+    let expected = [0x00, 0x00, 0x3B, 0x37, 0x00, 0x01, 0x00, 0x01, b'a', b'b', b'c', b'd'];
+
+    let sender = Ipv4Addr::new(10, 0, 2, 2);
+    let reply = handle_packet(sender, &request).expect("no reply to echo request");
+    assert_eq!(reply.dst, sender);
+    assert_eq!(&reply.message[..], &expected[..]);
+
+    // a corrupted checksum must be ignored
+    let mut corrupted = request;
+    corrupted[2] = 0;
+    assert_eq!(handle_packet(sender, &corrupted), None);
+}
@@ -0,0 +1,145 @@
+use super::{checksum, Ipv4Addr, NetError};
+
+/// IP protocol number of ICMP.
+pub const PROTOCOL_ICMP: u8 = 1;
+/// IP protocol number of UDP.
+pub const PROTOCOL_UDP: u8 = 17;
+
+/// Length of a header without options.
+pub const HEADER_LEN: usize = 20;
+const DEFAULT_TTL: u8 = 64;
+
+/// An IPv4 packet in a caller provided buffer.
+///
+/// Packets built by the kernel never carry options, so the header is always 20 bytes.
+pub struct Ipv4Packet<'a> {
+    buf: &'a mut [u8],
+}
+
+impl<'a> Ipv4Packet<'a> {
+    /// Starts a new packet in `buf` with an empty payload.
+    pub fn new(buf: &'a mut [u8]) -> Result<Self, NetError> {
+        if buf.len() < HEADER_LEN {
+            return Err(NetError::BufferTooSmall);
+        }
+        buf[..HEADER_LEN].fill(0);
+        // version 4, header length of 5 32 bit words
+        buf[0] = 0x45;
+        buf[8] = DEFAULT_TTL;
+        let mut packet = Ipv4Packet { buf };
+        packet.set_total_len(HEADER_LEN);
+        Ok(packet)
+    }
+
+    /// Wraps a received packet, checking that the header and length fields are consistent.
+    pub fn parse(buf: &'a mut [u8]) -> Result<Self, NetError> {
+        if buf.len() < HEADER_LEN || buf[0] >> 4 != 4 {
+            return Err(NetError::Malformed);
+        }
+        let packet = Ipv4Packet { buf };
+        let header_len = packet.header_len();
+        if header_len < HEADER_LEN || packet.total_len() < header_len
+            || packet.total_len() > packet.buf.len()
+        {
+            return Err(NetError::Malformed);
+        }
+        Ok(packet)
+    }
+
+    pub fn set_src(&mut self, addr: Ipv4Addr) {
+        self.buf[12..16].copy_from_slice(&addr.0);
+    }
+
+    pub fn set_dst(&mut self, addr: Ipv4Addr) {
+        self.buf[16..20].copy_from_slice(&addr.0);
+    }
+
+    pub fn set_protocol(&mut self, proto: u8) {
+        self.buf[9] = proto;
+    }
+
+    pub fn set_ttl(&mut self, ttl: u8) {
+        self.buf[8] = ttl;
+    }
+
+    /// Copies `data` behind the header and updates the total length.
+    pub fn set_payload(&mut self, data: &[u8]) -> Result<(), NetError> {
+        let header_len = self.header_len();
+        let total_len = header_len + data.len();
+        if total_len > self.buf.len() || total_len > u16::MAX as usize {
+            return Err(NetError::BufferTooSmall);
+        }
+        self.buf[header_len..total_len].copy_from_slice(data);
+        self.set_total_len(total_len);
+        Ok(())
+    }
+
+    /// Computes the header checksum and returns the total length of the packet.
+    ///
+    /// Must be called after the last header field was changed.
+    pub fn finalize(&mut self) -> usize {
+        let header_len = self.header_len();
+        self.buf[10..12].fill(0);
+        let sum = checksum(&self.buf[..header_len]);
+        self.buf[10..12].copy_from_slice(&sum.to_be_bytes());
+        self.total_len()
+    }
+
+    pub fn src(&self) -> Ipv4Addr {
+        Ipv4Addr([self.buf[12], self.buf[13], self.buf[14], self.buf[15]])
+    }
+
+    pub fn dst(&self) -> Ipv4Addr {
+        Ipv4Addr([self.buf[16], self.buf[17], self.buf[18], self.buf[19]])
+    }
+
+    pub fn protocol(&self) -> u8 {
+        self.buf[9]
+    }
+
+    pub fn ttl(&self) -> u8 {
+        self.buf[8]
+    }
+
+    pub fn header_len(&self) -> usize {
+        usize::from(self.buf[0] & 0x0F) * 4
+    }
+
+    pub fn total_len(&self) -> usize {
+        usize::from(u16::from_be_bytes([self.buf[2], self.buf[3]]))
+    }
+
+    /// Returns whether the header checksum is valid.
+    pub fn verify_checksum(&self) -> bool {
+        checksum(&self.buf[..self.header_len()]) == 0
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.buf[self.header_len()..self.total_len()]
+    }
+
+    /// Returns the whole packet including the header.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.total_len()]
+    }
+
+    fn set_total_len(&mut self, len: usize) {
+        self.buf[2..4].copy_from_slice(&(len as u16).to_be_bytes());
+    }
+}
+
+#[test_case]
+fn test_ipv4_finalize_checksum() {
+    let mut buf = [0u8; 64];
+    let mut packet = Ipv4Packet::new(&mut buf).unwrap();
+    packet.set_src(Ipv4Addr::new(10, 0, 2, 15));
+    packet.set_dst(Ipv4Addr::new(10, 0, 2, 2));
+    packet.set_protocol(PROTOCOL_UDP);
+    packet.set_payload(b"turiya").unwrap();
+    assert_eq!(packet.finalize(), HEADER_LEN + 6);
+    assert!(packet.verify_checksum());
+
+    let packet = Ipv4Packet::parse(&mut buf).unwrap();
+    assert_eq!(packet.dst(), Ipv4Addr::new(10, 0, 2, 2));
+    assert_eq!(packet.payload(), b"turiya");
+}
@@ -0,0 +1,131 @@
+//! A minimal network stack.
+
+pub mod ethernet;
+pub mod ipv4;
+pub mod udp;
+pub mod arp;
+pub mod icmp;
+pub mod loopback;
+pub mod dhcp;
+
+use core::fmt;
+use spin::Mutex;
+
+/// An IPv4 address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Ipv4Addr(pub [u8; 4]);
+
+impl Ipv4Addr {
+    pub const UNSPECIFIED: Ipv4Addr = Ipv4Addr([0, 0, 0, 0]);
+    pub const BROADCAST: Ipv4Addr = Ipv4Addr([255, 255, 255, 255]);
+
+    pub const fn new(a: u8, b: u8, c: u8, d: u8) -> Ipv4Addr {
+        Ipv4Addr([a, b, c, d])
+    }
+
+    pub fn octets(&self) -> [u8; 4] {
+        self.0
+    }
+}
+
+impl fmt::Display for Ipv4Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [a, b, c, d] = self.0;
+        write!(f, "{}.{}.{}.{}", a, b, c, d)
+    }
+}
+
+/// An Ethernet MAC address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    pub const BROADCAST: MacAddr = MacAddr([0xFF; 6]);
+
+    pub fn octets(&self) -> [u8; 6] {
+        self.0
+    }
+}
+
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [a, b, c, d, e, g] = self.0;
+        write!(f, "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}", a, b, c, d, e, g)
+    }
+}
+
+/// The addresses of the kernel's network interface.
+#[derive(Debug, Clone, Copy)]
+pub struct Interface {
+    pub mac: MacAddr,
+    /// Our IPv4 address, unspecified until it was configured.
+    pub ip: Ipv4Addr,
+}
+
+/// The configuration of the kernel's only network interface.
+pub static INTERFACE: Mutex<Interface> = Mutex::new(Interface {
+    mac: MacAddr([0; 6]),
+    ip: Ipv4Addr::UNSPECIFIED,
+});
+
+/// Sets the MAC and IPv4 address of the network interface.
+pub fn set_interface(mac: MacAddr, ip: Ipv4Addr) {
+    *INTERFACE.lock() = Interface { mac, ip };
+}
+
+/// Errors returned when building or parsing packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetError {
+    /// The buffer is too small for the header and payload.
+    BufferTooSmall,
+    /// The packet is truncated or has invalid header fields.
+    Malformed,
+    /// The device cannot accept more packets right now.
+    QueueFull,
+    /// The MAC address of the destination is not in the ARP table.
+    Unresolved,
+    /// The network device failed to send the packet.
+    DeviceError,
+}
+
+/// A device that sends and receives Ethernet frames.
+pub trait NetworkDevice {
+    type Error;
+
+    /// Returns the hardware address of the device.
+    fn mac_address(&self) -> MacAddr;
+
+    /// Queues `packet` for transmission.
+    fn send(&mut self, packet: &[u8]) -> Result<(), Self::Error>;
+
+    /// Copies the next received packet into `buf` and returns its length, or 0 if there is none.
+    fn recv(&mut self, buf: &mut [u8]) -> usize;
+}
+
+/// Computes the internet checksum (RFC 1071) of `data`.
+///
+/// The checksum field inside `data` must be zero when computing a new checksum.
+/// Computing the checksum over data that contains a valid checksum yields 0.
+pub fn checksum(data: &[u8]) -> u16 {
+    finish_checksum(sum_words(0, data))
+}
+
+// adds up `data` as big endian 16 bit words, padding an odd last byte with zero
+pub(crate) fn sum_words(mut sum: u32, data: &[u8]) -> u32 {
+    let mut chunks = data.chunks_exact(2);
+    for chunk in &mut chunks {
+        sum += u32::from(u16::from_be_bytes([chunk[0], chunk[1]]));
+    }
+    if let [last] = chunks.remainder() {
+        sum += u32::from(*last) << 8;
+    }
+    sum
+}
+
+// folds the carries back into the lower 16 bits and takes the one's complement
+pub(crate) fn finish_checksum(mut sum: u32) -> u16 {
+    while sum >> 16 != 0 {
+        sum = (sum & 0xFFFF) + (sum >> 16);
+    }
+    !(sum as u16)
+}
@@ -0,0 +1,210 @@
+use super::{arp, ethernet, finish_checksum, sum_words, Ipv4Addr, MacAddr, NetError, NetworkDevice, INTERFACE};
+use super::ipv4::{self, Ipv4Packet, PROTOCOL_UDP};
+
+/// Length of the UDP header.
+pub const HEADER_LEN: usize = 8;
+
+/// A UDP datagram in a caller provided buffer, usually the payload of an `Ipv4Packet`.
+pub struct UdpPacket<'a> {
+    buf: &'a mut [u8],
+}
+
+impl<'a> UdpPacket<'a> {
+    /// Starts a new datagram in `buf` with an empty payload.
+    pub fn new(buf: &'a mut [u8]) -> Result<Self, NetError> {
+        if buf.len() < HEADER_LEN {
+            return Err(NetError::BufferTooSmall);
+        }
+        buf[..HEADER_LEN].fill(0);
+        let mut packet = UdpPacket { buf };
+        packet.set_len(HEADER_LEN);
+        Ok(packet)
+    }
+
+    /// Wraps a received datagram, checking the length field.
+    pub fn parse(buf: &'a mut [u8]) -> Result<Self, NetError> {
+        if buf.len() < HEADER_LEN {
+            return Err(NetError::Malformed);
+        }
+        let packet = UdpPacket { buf };
+        if packet.len() < HEADER_LEN || packet.len() > packet.buf.len() {
+            return Err(NetError::Malformed);
+        }
+        Ok(packet)
+    }
+
+    pub fn set_src_port(&mut self, port: u16) {
+        self.buf[0..2].copy_from_slice(&port.to_be_bytes());
+    }
+
+    pub fn set_dst_port(&mut self, port: u16) {
+        self.buf[2..4].copy_from_slice(&port.to_be_bytes());
+    }
+
+    /// Copies `data` behind the header and updates the length field.
+    pub fn set_payload(&mut self, data: &[u8]) -> Result<(), NetError> {
+        let len = HEADER_LEN + data.len();
+        if len > self.buf.len() || len > u16::MAX as usize {
+            return Err(NetError::BufferTooSmall);
+        }
+        self.buf[HEADER_LEN..len].copy_from_slice(data);
+        self.set_len(len);
+        Ok(())
+    }
+
+    /// Computes the checksum over the IPv4 pseudo header and the datagram
+    /// and returns the length of the datagram.
+    pub fn finalize(&mut self, src: Ipv4Addr, dst: Ipv4Addr) -> usize {
+        self.buf[6..8].fill(0);
+        let mut sum = self.pseudo_header_sum(src, dst);
+        sum = sum_words(sum, &self.buf[..self.len()]);
+        // a computed checksum of 0 is transmitted as all ones, 0 means "no checksum"
+        let checksum = match finish_checksum(sum) {
+            0 => 0xFFFF,
+            checksum => checksum,
+        };
+        self.buf[6..8].copy_from_slice(&checksum.to_be_bytes());
+        self.len()
+    }
+
+    pub fn src_port(&self) -> u16 {
+        u16::from_be_bytes([self.buf[0], self.buf[1]])
+    }
+
+    pub fn dst_port(&self) -> u16 {
+        u16::from_be_bytes([self.buf[2], self.buf[3]])
+    }
+
+    /// Returns the length of header and payload.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        usize::from(u16::from_be_bytes([self.buf[4], self.buf[5]]))
+    }
+
+    pub fn payload(&self) -> &[u8] {
+        &self.buf[HEADER_LEN..self.len()]
+    }
+
+    /// Returns the whole datagram including the header.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len()]
+    }
+
+    fn set_len(&mut self, len: usize) {
+        self.buf[4..6].copy_from_slice(&(len as u16).to_be_bytes());
+    }
+
+    fn pseudo_header_sum(&self, src: Ipv4Addr, dst: Ipv4Addr) -> u32 {
+        let mut sum = sum_words(0, &src.0);
+        sum = sum_words(sum, &dst.0);
+        sum += u32::from(PROTOCOL_UDP);
+        sum + self.len() as u32
+    }
+}
+
+// largest Ethernet frame without the frame check sequence
+const MAX_FRAME_LEN: usize = 1514;
+
+/// A UDP endpoint bound to a local port on a network device.
+pub struct UdpSocket<'d, D: NetworkDevice> {
+    device: &'d mut D,
+    port: u16,
+}
+
+impl<'d, D: NetworkDevice> UdpSocket<'d, D> {
+    /// Binds a socket to `port` on `device`.
+    pub fn bind(device: &'d mut D, port: u16) -> Self {
+        UdpSocket { device, port }
+    }
+
+    pub fn local_port(&self) -> u16 {
+        self.port
+    }
+
+    /// Sends `data` to `dst:dst_port` from our interface address.
+    ///
+    /// The destination must be the broadcast address or already be in the ARP table.
+    pub fn send_to(&mut self, data: &[u8], dst: Ipv4Addr, dst_port: u16) -> Result<(), NetError> {
+        let dst_mac = if dst == Ipv4Addr::BROADCAST {
+            MacAddr::BROADCAST
+        } else {
+            arp::lookup(dst).ok_or(NetError::Unresolved)?
+        };
+        let src = INTERFACE.lock().ip;
+
+        let mut udp_buf = [0u8; MAX_FRAME_LEN - ethernet::HEADER_LEN - ipv4::HEADER_LEN];
+        let mut udp = UdpPacket::new(&mut udp_buf)?;
+        udp.set_src_port(self.port);
+        udp.set_dst_port(dst_port);
+        udp.set_payload(data)?;
+        udp.finalize(src, dst);
+
+        let mut frame = [0u8; MAX_FRAME_LEN];
+        ethernet::write_header(&mut frame, dst_mac, self.device.mac_address(), ethernet::ETHERTYPE_IPV4);
+        let mut ip = Ipv4Packet::new(&mut frame[ethernet::HEADER_LEN..])?;
+        ip.set_src(src);
+        ip.set_dst(dst);
+        ip.set_protocol(PROTOCOL_UDP);
+        ip.set_payload(udp.as_bytes())?;
+        let len = ethernet::HEADER_LEN + ip.finalize();
+
+        self.device.send(&frame[..len]).map_err(|_| NetError::DeviceError)
+    }
+
+    /// Receives a datagram addressed to our port without waiting.
+    ///
+    /// Returns the payload length and the sender's address and port. Other packets
+    /// pending on the device are dropped, ARP requests for our address are answered.
+    pub fn try_recv_from(&mut self, buf: &mut [u8]) -> Option<(usize, Ipv4Addr, u16)> {
+        let mut frame = [0u8; MAX_FRAME_LEN];
+        loop {
+            let len = self.device.recv(&mut frame);
+            if len == 0 {
+                return None;
+            }
+
+            let ethertype = match ethernet::parse(&frame[..len]) {
+                Some(parsed) => parsed.ethertype,
+                None => continue,
+            };
+            if ethertype == ethernet::ETHERTYPE_ARP {
+                if let Some(reply) = arp::handle_packet(&frame[..len]) {
+                    let _ = self.device.send(reply.as_bytes());
+                }
+                continue;
+            }
+            if ethertype != ethernet::ETHERTYPE_IPV4 {
+                continue;
+            }
+
+            let (src, udp_start, udp_end) = match Ipv4Packet::parse(&mut frame[ethernet::HEADER_LEN..len]) {
+                Ok(ip) if ip.protocol() == PROTOCOL_UDP => {
+                    let start = ethernet::HEADER_LEN + ip.header_len();
+                    (ip.src(), start, ethernet::HEADER_LEN + ip.total_len())
+                }
+                _ => continue,
+            };
+            let udp = match UdpPacket::parse(&mut frame[udp_start..udp_end]) {
+                Ok(udp) if udp.dst_port() == self.port => udp,
+                _ => continue,
+            };
+
+            let payload = udp.payload();
+            let copied = payload.len().min(buf.len());
+            buf[..copied].copy_from_slice(&payload[..copied]);
+            return Some((copied, src, udp.src_port()));
+        }
+    }
+
+    /// Waits for the next datagram addressed to our port.
+    ///
+    /// The device has no receive interrupt, so this polls it once per executor round.
+    pub async fn recv_from(&mut self, buf: &mut [u8]) -> (usize, Ipv4Addr, u16) {
+        loop {
+            if let Some(received) = self.try_recv_from(buf) {
+                return received;
+            }
+            crate::task::yield_now().await;
+        }
+    }
+}
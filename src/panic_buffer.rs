@@ -0,0 +1,29 @@
+//! Keeps the message of the last panic in memory.
+//!
+//! When the kernel runs headless the VGA output of a panic is lost. The buffer can
+//! still be read afterwards, e.g. with a debugger, even if the serial port is broken.
+
+use crate::collections::string::KernelString;
+use core::{fmt::Write, panic::PanicInfo};
+use spin::Mutex;
+
+/// The message of the last panic. Not mangled so debuggers can find it by name.
+#[no_mangle]
+pub static PANIC_BUFFER: Mutex<KernelString<1024>> = Mutex::new(KernelString::new());
+
+/// Stores `info` in `PANIC_BUFFER`, truncated if it is too long.
+///
+/// Does nothing if the buffer is locked, i.e. if the panic happened while recording
+/// an earlier one.
+pub fn record(info: &PanicInfo) {
+    if let Some(mut buffer) = PANIC_BUFFER.try_lock() {
+        buffer.clear();
+        // a message that does not fit is cut off at the argument that overflows
+        let _ = write!(buffer, "{}", info);
+    }
+}
+
+/// Returns a copy of the recorded panic message, empty if there was no panic.
+pub fn last_panic() -> KernelString<1024> {
+    *PANIC_BUFFER.lock()
+}
@@ -0,0 +1,136 @@
+use crate::fs::{console, vfs::{self, VfsNode}, FsError};
+use alloc::{collections::BTreeMap, sync::Arc};
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+// error numbers returned (negated) by the syscalls, matching the values used by Linux
+pub const ENOENT: i64 = 2;
+pub const EBADF: i64 = 9;
+pub const EISDIR: i64 = 21;
+pub const EINVAL: i64 = 22;
+pub const ENOTSUP: i64 = 95;
+
+/// The open files of a process, indexed by file descriptor.
+pub struct FdTable {
+    fds: BTreeMap<u32, Arc<dyn VfsNode>>,
+    // the current read/write position of each descriptor
+    offsets: BTreeMap<u32, usize>,
+    next_fd: u32,
+}
+
+impl FdTable {
+    /// Creates a table with stdin, stdout (VGA) and stderr (serial) open as FD 0, 1 and 2.
+    pub fn new() -> FdTable {
+        let mut table = FdTable {
+            fds: BTreeMap::new(),
+            offsets: BTreeMap::new(),
+            next_fd: 0,
+        };
+        table.insert(Arc::new(console::Stdin));
+        table.insert(Arc::new(console::VgaOutput));
+        table.insert(Arc::new(console::SerialOutput));
+        table
+    }
+
+    /// Opens the file at `path` and returns its descriptor or a negative error number.
+    ///
+    /// `flags` is accepted for compatibility, access checks are left to the node itself.
+    pub fn open(&mut self, path: &str, _flags: u32) -> i64 {
+        match vfs::resolve(path) {
+            Ok(node) => i64::from(self.insert(node)),
+            Err(err) => -errno(err),
+        }
+    }
+
+    /// Reads up to `len` bytes from `fd` into `buf` and returns the number of bytes read
+    /// or a negative error number.
+    ///
+    /// This function is unsafe because the caller must guarantee that `buf` is valid for
+    /// writes of `len` bytes.
+    pub unsafe fn read(&mut self, fd: u32, buf: *mut u8, len: usize) -> i64 {
+        if buf.is_null() {
+            return -EINVAL;
+        }
+        let buf = core::slice::from_raw_parts_mut(buf, len);
+        let Some(node) = self.fds.get(&fd) else { return -EBADF };
+        let offset = self.offsets.entry(fd).or_insert(0);
+        match node.read(*offset, buf) {
+            Ok(count) => {
+                *offset += count;
+                count as i64
+            }
+            Err(err) => -errno(err),
+        }
+    }
+
+    /// Writes `len` bytes from `buf` to `fd` and returns the number of bytes written
+    /// or a negative error number.
+    ///
+    /// This function is unsafe because the caller must guarantee that `buf` is valid for
+    /// reads of `len` bytes.
+    pub unsafe fn write(&mut self, fd: u32, buf: *const u8, len: usize) -> i64 {
+        if buf.is_null() {
+            return -EINVAL;
+        }
+        let buf = core::slice::from_raw_parts(buf, len);
+        let Some(node) = self.fds.get(&fd) else { return -EBADF };
+        let offset = self.offsets.entry(fd).or_insert(0);
+        match node.write(*offset, buf) {
+            Ok(count) => {
+                *offset += count;
+                count as i64
+            }
+            Err(err) => -errno(err),
+        }
+    }
+
+    /// Closes `fd`, dropping the table's reference to its node.
+    pub fn close(&mut self, fd: u32) -> i64 {
+        self.offsets.remove(&fd);
+        match self.fds.remove(&fd) {
+            Some(_) => 0,
+            None => -EBADF,
+        }
+    }
+
+    fn insert(&mut self, node: Arc<dyn VfsNode>) -> u32 {
+        let fd = self.next_fd;
+        self.next_fd += 1;
+        self.fds.insert(fd, node);
+        fd
+    }
+}
+
+impl Default for FdTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+lazy_static! {
+    /// The file descriptor table of the kernel, the only process so far.
+    pub static ref FD_TABLE: Mutex<FdTable> = Mutex::new(FdTable::new());
+}
+
+fn errno(err: FsError) -> i64 {
+    match err {
+        FsError::NotFound => ENOENT,
+        FsError::InvalidPath | FsError::InvalidFilesystem => EINVAL,
+        FsError::IsDirectory => EISDIR,
+        FsError::NotSupported => ENOTSUP,
+    }
+}
+
+#[test_case]
+fn test_standard_descriptors() {
+    let mut table = FdTable::new();
+    let message = b"fd table test\n";
+    assert_eq!(unsafe { table.write(1, message.as_ptr(), message.len()) }, message.len() as i64);
+
+    let mut buf = [0u8; 4];
+    assert_eq!(unsafe { table.read(0, buf.as_mut_ptr(), buf.len()) }, 0);
+    assert_eq!(table.open("/missing.txt", 0), -ENOENT);
+
+    assert_eq!(table.close(0), 0);
+    assert_eq!(unsafe { table.read(0, buf.as_mut_ptr(), buf.len()) }, -EBADF);
+}
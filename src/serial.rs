@@ -1,27 +1,61 @@
-use uart_16550::SerialPort;
 use spin::Mutex;
 use lazy_static::lazy_static;
+use crate::drivers::debug_uart::{DebugOutput, PortDebugUart};
 
 lazy_static! {
-    pub static ref SERIAL1: Mutex<SerialPort> = {
-        let mut serial_port = unsafe { SerialPort::new(0x3F8) };
-        serial_port.init();
+    pub static ref SERIAL1: Mutex<PortDebugUart> = {
+        let serial_port = unsafe { PortDebugUart::new(0x3F8) };
         Mutex::new(serial_port)
     };
 }
 
 #[doc(hidden)]
 pub fn _print(args: ::core::fmt::Arguments) {
-    use core::fmt::Write;
     use x86_64::instructions::interrupts;
     
     // see explanation in vga_buffer.rs
     interrupts::without_interrupts(|| {
+        #[cfg(not(feature = "timestamped_log"))]
         SERIAL1.lock().write_fmt(args)
             .expect("Printing to serial failed");
+        #[cfg(feature = "timestamped_log")]
+        {
+            use core::fmt::Write;
+            use core::sync::atomic::AtomicBool;
+
+            static AT_LINE_START: AtomicBool = AtomicBool::new(true);
+            let mut port = SERIAL1.lock();
+            crate::time::TimestampWriter::new(&AT_LINE_START, |s| DebugOutput::write_str(&mut *port, s))
+                .write_fmt(args)
+                .expect("Printing to serial failed");
+        }
+        // unit tests keep a copy of the output to check it
+        #[cfg(test)]
+        crate::testing::serial_capture::capture(args);
     });
 }
 
+/// Sends `bytes` unchanged over `port`, e.g. packets of a binary protocol.
+pub fn write_bytes(port: &mut PortDebugUart, bytes: &[u8]) {
+    port.write_bytes(bytes);
+}
+
+/// Sends `bytes` unchanged over the first serial port.
+pub fn serial_print_bytes(bytes: &[u8]) {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| {
+        write_bytes(&mut SERIAL1.lock(), bytes);
+    });
+}
+
+/// Waits until everything printed to the first serial port has been sent.
+pub fn flush() {
+    use x86_64::instructions::interrupts;
+
+    interrupts::without_interrupts(|| SERIAL1.lock().flush());
+}
+
 /// Prints to the host through the serial interface.
 #[macro_export]
 macro_rules! serial_print {
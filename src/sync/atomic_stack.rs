@@ -0,0 +1,162 @@
+use core::marker::PhantomData;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// A node that can be linked into an `AtomicStack`.
+///
+/// This trait is unsafe because the stack relies on `next` returning
+/// exactly what was last passed to `set_next`.
+pub unsafe trait StackNode {
+    fn next(&self) -> *mut Self;
+    fn set_next(&mut self, next: *mut Self);
+}
+
+// `head` keeps the node pointer in its low 48 bits, the virtual address width,
+// and a tag in the upper 16 bits that every push and pop increments
+const POINTER_BITS: u32 = 48;
+const POINTER_MASK: u64 = (1 << POINTER_BITS) - 1;
+
+fn pack<T>(node: *mut T, tag: u64) -> u64 {
+    (node as u64 & POINTER_MASK) | (tag << POINTER_BITS)
+}
+
+fn unpack<T>(head: u64) -> (*mut T, u64) {
+    // sign extend bit 47 to get the canonical address back
+    let addr = (((head << (64 - POINTER_BITS)) as i64) >> (64 - POINTER_BITS)) as u64;
+    (addr as *mut T, head >> POINTER_BITS)
+}
+
+/// A lock-free intrusive stack (Treiber stack) of raw node pointers.
+///
+/// Nodes are never freed by the stack, so `pop` may read the `next` pointer of a
+/// node another CPU or an interrupt handler just took. The head carries a tag that
+/// changes with every push and pop, so a `pop` whose head was popped and pushed
+/// again in the meantime (the ABA problem) fails its `compare_exchange` and retries
+/// instead of installing a stale `next` pointer. The tag has 16 bits, so this only
+/// goes wrong if exactly a multiple of 65536 operations happen during a single `pop`.
+pub struct AtomicStack<T> {
+    head: AtomicU64,
+    _marker: PhantomData<*mut T>,
+}
+
+impl<T: StackNode> AtomicStack<T> {
+    pub const fn new() -> Self {
+        AtomicStack {
+            head: AtomicU64::new(0),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Pushes `node` on top of the stack.
+    ///
+    /// This function is unsafe because `node` must point to a valid node that is not
+    /// already in a stack, and it must stay valid memory for as long as the stack is used.
+    pub unsafe fn push(&self, node: *mut T) {
+        let mut current = self.head.load(Ordering::Relaxed);
+        loop {
+            let (head, tag) = unpack::<T>(current);
+            (*node).set_next(head);
+            let new = pack(node, tag.wrapping_add(1));
+            match self.head.compare_exchange_weak(current, new, Ordering::Release, Ordering::Relaxed) {
+                Ok(_) => return,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    /// Removes the top node and returns it, or a null pointer if the stack is empty.
+    pub fn pop(&self) -> *mut T {
+        let mut current = self.head.load(Ordering::Acquire);
+        loop {
+            let (head, tag) = unpack::<T>(current);
+            if head.is_null() {
+                return head;
+            }
+            // nodes stay valid memory as required by `push`, if `head` was taken in the
+            // meantime `next` may be stale, but then the tag changed and the exchange fails
+            let next = unsafe { (*head).next() };
+            let new = pack(next, tag.wrapping_add(1));
+            match self.head.compare_exchange_weak(current, new, Ordering::Acquire, Ordering::Acquire) {
+                Ok(_) => return head,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        unpack::<T>(self.head.load(Ordering::Relaxed)).0.is_null()
+    }
+}
+
+// the stack only hands out raw pointers, the nodes are owned by whoever pushed them
+unsafe impl<T> Send for AtomicStack<T> {}
+unsafe impl<T> Sync for AtomicStack<T> {}
+
+#[test_case]
+fn test_atomic_stack_is_lifo() {
+    use core::ptr;
+
+    struct Node {
+        next: *mut Node,
+        value: u32,
+    }
+
+    unsafe impl StackNode for Node {
+        fn next(&self) -> *mut Node {
+            self.next
+        }
+        fn set_next(&mut self, next: *mut Node) {
+            self.next = next;
+        }
+    }
+
+    let mut nodes = [0, 1, 2].map(|value| Node { next: ptr::null_mut(), value });
+    let stack = AtomicStack::<Node>::new();
+    assert!(stack.pop().is_null());
+
+    for node in nodes.iter_mut() {
+        unsafe { stack.push(node) };
+    }
+    for expected in [2, 1, 0] {
+        let node = stack.pop();
+        assert_eq!(unsafe { (*node).value }, expected);
+    }
+    assert!(stack.is_empty());
+}
+
+#[test_case]
+fn test_atomic_stack_tag_detects_reuse() {
+    use core::ptr;
+
+    struct Node {
+        next: *mut Node,
+    }
+
+    unsafe impl StackNode for Node {
+        fn next(&self) -> *mut Node {
+            self.next
+        }
+        fn set_next(&mut self, next: *mut Node) {
+            self.next = next;
+        }
+    }
+
+    let mut a = Node { next: ptr::null_mut() };
+    let mut b = Node { next: ptr::null_mut() };
+    let stack = AtomicStack::<Node>::new();
+    unsafe {
+        stack.push(&mut b);
+        stack.push(&mut a);
+    }
+    // the head a `pop` would have read before `a` and `b` were popped and `a` pushed again
+    let stale = stack.head.load(Ordering::Relaxed);
+    let a_ptr = stack.pop();
+    stack.pop();
+    unsafe { stack.push(a_ptr) };
+    let current = stack.head.load(Ordering::Relaxed);
+    assert_eq!(unpack::<Node>(current).0, unpack::<Node>(stale).0);
+    assert_ne!(current, stale);
+
+    // pointers in the upper half survive the packing
+    let high = 0xffff_8000_0000_1000u64 as *mut Node;
+    assert_eq!(unpack::<Node>(pack(high, 7)), (high, 7));
+}
@@ -0,0 +1,53 @@
+use super::Once;
+
+/// A global that is initialized explicitly with `init` and panics if used before.
+///
+/// In contrast to `lazy_static!` the value does not compute itself on first access,
+/// which turns a forgotten initialization into a clear panic instead of silently
+/// running the initializer at some arbitrary point.
+pub struct LazyInit<T>(Once<T>);
+
+impl<T> LazyInit<T> {
+    pub const fn new() -> Self {
+        LazyInit(Once::new())
+    }
+
+    /// Stores `val`. Panics if the value has already been initialized.
+    #[track_caller]
+    pub fn init(&self, val: T) {
+        let mut initialized = false;
+        self.0.call_once(|| {
+            initialized = true;
+            val
+        });
+        assert!(initialized, "LazyInit<{}> initialized twice", core::any::type_name::<T>());
+    }
+
+    /// Returns the value. Panics if `init` has not been called yet.
+    #[track_caller]
+    pub fn get(&self) -> &T {
+        match self.0.get() {
+            Some(val) => val,
+            None => panic!("LazyInit<{}> used before initialization", core::any::type_name::<T>()),
+        }
+    }
+
+    /// Returns the value if `init` has been called.
+    pub fn try_get(&self) -> Option<&T> {
+        self.0.get()
+    }
+}
+
+impl<T> Default for LazyInit<T> {
+    fn default() -> Self {
+        LazyInit::new()
+    }
+}
+
+#[test_case]
+fn test_lazy_init() {
+    let lazy = LazyInit::new();
+    assert_eq!(lazy.try_get(), None);
+    lazy.init(7);
+    assert_eq!(*lazy.get(), 7);
+}
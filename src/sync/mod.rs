@@ -0,0 +1,11 @@
+//! Synchronization primitives for kernel globals.
+
+pub mod once;
+pub mod rwlock;
+pub mod lazy_init;
+pub mod atomic_stack;
+
+pub use once::Once;
+pub use rwlock::SpinRwLock;
+pub use lazy_init::LazyInit;
+pub use atomic_stack::{AtomicStack, StackNode};
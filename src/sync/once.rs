@@ -0,0 +1,93 @@
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+// the state moves forward only: UNINITIALIZED -> INITIALIZING -> INITIALIZED
+const UNINITIALIZED: u8 = 0;
+const INITIALIZING: u8 = 1;
+const INITIALIZED: u8 = 2;
+
+/// A cell that is initialized exactly once, usable in a `static`.
+///
+/// Unlike `lazy_static!` the initializer is passed at the call site, so the
+/// same cell can be initialized explicitly during boot or lazily on first use.
+pub struct Once<T> {
+    state: AtomicU8,
+    data: UnsafeCell<MaybeUninit<T>>,
+}
+
+// the data is only written once by the thread that won the compare_exchange
+// and is only handed out as a shared reference afterwards
+unsafe impl<T: Send + Sync> Sync for Once<T> {}
+unsafe impl<T: Send> Send for Once<T> {}
+
+impl<T> Once<T> {
+    /// Creates an uninitialized cell.
+    pub const fn new() -> Self {
+        Once {
+            state: AtomicU8::new(UNINITIALIZED),
+            data: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// Initializes the cell with `f` if it is not yet initialized and returns its value.
+    ///
+    /// If another CPU is running the initializer at the same time, this spins until
+    /// it has finished. If `f` panics the cell stays in the initializing state forever.
+    pub fn call_once(&self, f: impl FnOnce() -> T) -> &T {
+        match self.state.compare_exchange(
+            UNINITIALIZED, INITIALIZING, Ordering::Acquire, Ordering::Acquire)
+        {
+            Ok(_) => {
+                // we won the race, so we are the only one accessing the data
+                unsafe { (*self.data.get()).write(f()) };
+                self.state.store(INITIALIZED, Ordering::Release);
+            }
+            Err(_) => {
+                while self.state.load(Ordering::Acquire) != INITIALIZED {
+                    spin_loop();
+                }
+            }
+        }
+        unsafe { (*self.data.get()).assume_init_ref() }
+    }
+
+    /// Returns the value if the cell has been initialized.
+    pub fn get(&self) -> Option<&T> {
+        if self.is_completed() {
+            Some(unsafe { (*self.data.get()).assume_init_ref() })
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether the initializer has finished running.
+    pub fn is_completed(&self) -> bool {
+        self.state.load(Ordering::Acquire) == INITIALIZED
+    }
+}
+
+impl<T> Default for Once<T> {
+    fn default() -> Self {
+        Once::new()
+    }
+}
+
+impl<T> Drop for Once<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == INITIALIZED {
+            unsafe { self.data.get_mut().assume_init_drop() };
+        }
+    }
+}
+
+#[test_case]
+fn test_call_once_runs_initializer_once() {
+    let once = Once::new();
+    assert_eq!(once.get(), None);
+    assert_eq!(*once.call_once(|| 1), 1);
+    // the second initializer must not run
+    assert_eq!(*once.call_once(|| 2), 1);
+    assert_eq!(once.get(), Some(&1));
+}
@@ -0,0 +1,151 @@
+use core::cell::UnsafeCell;
+use core::hint::spin_loop;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use crate::cpu::{CriticalSection, CriticalSectionGuard};
+
+/// A spinning reader-writer lock for data that is read often and written rarely.
+///
+/// Any number of readers can hold the lock at the same time, a writer has exclusive
+/// access. A waiting writer blocks new readers, so writers cannot starve.
+pub struct SpinRwLock<T> {
+    readers: AtomicUsize,
+    writer_lock: AtomicBool,
+    data: UnsafeCell<T>,
+}
+
+// readers only get shared references, so `T` must be `Sync` as well
+unsafe impl<T: Send + Sync> Sync for SpinRwLock<T> {}
+unsafe impl<T: Send> Send for SpinRwLock<T> {}
+
+/// Shared access to the data of a `SpinRwLock`, released on drop.
+pub struct SpinReadGuard<'a, T> {
+    lock: &'a SpinRwLock<T>,
+    // restores the interrupt state after the lock has been released in `drop`
+    _critical_section: Option<CriticalSectionGuard>,
+}
+
+/// Exclusive access to the data of a `SpinRwLock`, released on drop.
+pub struct SpinWriteGuard<'a, T> {
+    lock: &'a SpinRwLock<T>,
+    _critical_section: Option<CriticalSectionGuard>,
+}
+
+impl<T> SpinRwLock<T> {
+    pub const fn new(data: T) -> Self {
+        SpinRwLock {
+            readers: AtomicUsize::new(0),
+            writer_lock: AtomicBool::new(false),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    /// Spins until no writer holds or waits for the lock and returns shared access.
+    pub fn read(&self) -> SpinReadGuard<'_, T> {
+        self.acquire_read();
+        SpinReadGuard { lock: self, _critical_section: None }
+    }
+
+    /// Spins until all readers and writers are gone and returns exclusive access.
+    pub fn write(&self) -> SpinWriteGuard<'_, T> {
+        self.acquire_write();
+        SpinWriteGuard { lock: self, _critical_section: None }
+    }
+
+    /// Like `read`, but disables interrupts while the guard is held.
+    ///
+    /// Use this for locks that are also taken in interrupt handlers, otherwise an
+    /// interrupt arriving while the lock is held would deadlock.
+    pub fn read_irq(&self) -> SpinReadGuard<'_, T> {
+        let critical_section = CriticalSection::enter();
+        self.acquire_read();
+        SpinReadGuard { lock: self, _critical_section: Some(critical_section) }
+    }
+
+    /// Like `write`, but disables interrupts while the guard is held.
+    pub fn write_irq(&self) -> SpinWriteGuard<'_, T> {
+        let critical_section = CriticalSection::enter();
+        self.acquire_write();
+        SpinWriteGuard { lock: self, _critical_section: Some(critical_section) }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    fn acquire_read(&self) {
+        loop {
+            while self.writer_lock.load(Ordering::Relaxed) {
+                spin_loop();
+            }
+            // a writer may have taken the lock between the check and the increment,
+            // the increment and the check below pair with the writer's CAS and readers load,
+            // which needs SeqCst on both sides so that at least one of them sees the other
+            self.readers.fetch_add(1, Ordering::SeqCst);
+            if !self.writer_lock.load(Ordering::SeqCst) {
+                return;
+            }
+            self.readers.fetch_sub(1, Ordering::Release);
+        }
+    }
+
+    fn acquire_write(&self) {
+        while self.writer_lock.compare_exchange_weak(
+            false, true, Ordering::SeqCst, Ordering::Relaxed).is_err()
+        {
+            spin_loop();
+        }
+        // new readers back off now, wait for the current ones to finish
+        while self.readers.load(Ordering::SeqCst) != 0 {
+            spin_loop();
+        }
+    }
+}
+
+impl<'a, T> Deref for SpinReadGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T> Drop for SpinReadGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.readers.fetch_sub(1, Ordering::Release);
+    }
+}
+
+impl<'a, T> Deref for SpinWriteGuard<'a, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<'a, T> DerefMut for SpinWriteGuard<'a, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<'a, T> Drop for SpinWriteGuard<'a, T> {
+    fn drop(&mut self) {
+        self.lock.writer_lock.store(false, Ordering::Release);
+    }
+}
+
+#[test_case]
+fn test_rwlock_readers_and_writer() {
+    let lock = SpinRwLock::new(1);
+    {
+        // two readers at the same time must not deadlock
+        let first = lock.read();
+        let second = lock.read();
+        assert_eq!(*first + *second, 2);
+    }
+    *lock.write_irq() += 1;
+    assert!(x86_64::instructions::interrupts::are_enabled());
+    assert_eq!(*lock.read(), 2);
+}
@@ -0,0 +1,126 @@
+//! Printing to the VGA buffer from async tasks without taking the `WRITER` lock.
+//!
+//! Tasks queue their messages with `AsyncPrinter::print`, and `print_task` writes
+//! them to the screen in batches, taking the lock once per batch.
+
+use alloc::sync::Arc;
+use core::{pin::Pin, task::{Context, Poll}, future::Future};
+use crossbeam_queue::ArrayQueue;
+use futures_util::task::AtomicWaker;
+use x86_64::instructions::interrupts;
+use crate::collections::string::KernelString;
+use crate::vga_buffer::WRITER;
+use super::yield_now;
+
+/// A message queued for printing.
+pub type Message = KernelString<256>;
+
+/// A handle to the queue of messages waiting to be printed, cheap to clone.
+#[derive(Clone)]
+pub struct AsyncPrinter {
+    queue: Arc<ArrayQueue<Message>>,
+    // wakes `print_task` when a message is queued
+    waker: Arc<AtomicWaker>,
+}
+
+impl AsyncPrinter {
+    /// Creates a printer that queues up to `capacity` messages.
+    pub fn new(capacity: usize) -> AsyncPrinter {
+        AsyncPrinter {
+            queue: Arc::new(ArrayQueue::new(capacity)),
+            waker: Arc::new(AtomicWaker::new()),
+        }
+    }
+
+    /// Queues `msg` for printing. Waits for `print_task` to make room if the queue is full.
+    pub async fn print(&self, msg: Message) {
+        let mut msg = msg;
+        loop {
+            match self.queue.push(msg) {
+                Ok(()) => break,
+                Err(rejected) => {
+                    msg = rejected;
+                    self.waker.wake();
+                    yield_now().await;
+                }
+            }
+        }
+        self.waker.wake();
+    }
+
+    /// Returns the number of messages waiting to be printed.
+    pub fn pending(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Writes all queued messages to the screen and returns how many were written.
+    pub fn flush(&self) -> usize {
+        if self.queue.is_empty() {
+            return 0;
+        }
+        interrupts::without_interrupts(|| {
+            let mut writer = WRITER.lock();
+            let mut count = 0;
+            while let Some(msg) = self.queue.pop() {
+                writer.write_string(msg.as_str());
+                count += 1;
+            }
+            count
+        })
+    }
+
+    fn wait_for_messages(&self) -> WaitForMessages<'_> {
+        WaitForMessages { printer: self }
+    }
+}
+
+struct WaitForMessages<'a> {
+    printer: &'a AsyncPrinter,
+}
+
+impl Future for WaitForMessages<'_> {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if !self.printer.queue.is_empty() {
+            return Poll::Ready(());
+        }
+        self.printer.waker.register(cx.waker());
+        // a message queued before the registration would not have woken us
+        if self.printer.queue.is_empty() {
+            Poll::Pending
+        } else {
+            self.printer.waker.take();
+            Poll::Ready(())
+        }
+    }
+}
+
+/// Writes the messages queued on `printer` to the screen. Spawn this once per printer.
+pub async fn print_task(printer: AsyncPrinter) {
+    loop {
+        printer.wait_for_messages().await;
+        printer.flush();
+    }
+}
+
+#[test_case]
+fn test_async_printer_batches_messages() {
+    use core::fmt::Write;
+    use super::{executor::Executor, Task};
+
+    let printer = AsyncPrinter::new(2);
+    let producer = printer.clone();
+    let mut executor = Executor::new();
+    executor.spawn_or_panic(Task::new(async move {
+        for i in 0..3 {
+            let mut msg = Message::new();
+            write!(msg, "async printer message {}\n", i).unwrap();
+            producer.print(msg).await;
+        }
+    }));
+    executor.spawn_or_panic(Task::new(print_task(printer.clone())));
+    // the print task never finishes
+    let _ = executor.drain_with_timeout(100);
+    assert_eq!(printer.pending(), 0);
+}
@@ -0,0 +1,100 @@
+//! A barrier that lets a group of async tasks wait until all of them have arrived.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::{future::Future, pin::Pin};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::{Context, Poll, Waker};
+use spin::Mutex;
+
+/// Releases all waiting tasks once `n` tasks have called `wait`.
+///
+/// The barrier is used only once: after it has been released, `wait` completes immediately.
+pub struct Barrier {
+    n: usize,
+    count: AtomicUsize,
+    waiters: Mutex<Vec<Waker>>,
+}
+
+impl Barrier {
+    pub fn new(n: usize) -> Arc<Barrier> {
+        Arc::new(Barrier {
+            n,
+            count: AtomicUsize::new(0),
+            waiters: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// Arrives at the barrier and waits until `n` tasks have arrived.
+    ///
+    /// A task counts as arrived when the returned future is first polled.
+    pub fn wait(&self) -> impl Future<Output = ()> + '_ {
+        BarrierWait { barrier: self, arrived: false }
+    }
+
+    fn is_released(&self) -> bool {
+        self.count.load(Ordering::Acquire) >= self.n
+    }
+}
+
+struct BarrierWait<'a> {
+    barrier: &'a Barrier,
+    arrived: bool,
+}
+
+impl Future for BarrierWait<'_> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        let barrier = self.barrier;
+        if !self.arrived {
+            self.arrived = true;
+            if barrier.count.fetch_add(1, Ordering::AcqRel) + 1 == barrier.n {
+                // the last task to arrive releases everyone else
+                for waker in barrier.waiters.lock().drain(..) {
+                    waker.wake();
+                }
+                return Poll::Ready(());
+            }
+        }
+        if barrier.is_released() {
+            return Poll::Ready(());
+        }
+
+        let mut waiters = barrier.waiters.lock();
+        // the last task may have drained the waiters before we took the lock
+        if barrier.is_released() {
+            return Poll::Ready(());
+        }
+        if !waiters.iter().any(|waker| waker.will_wake(cx.waker())) {
+            waiters.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
+
+#[test_case]
+fn test_barrier_releases_all_tasks() {
+    use crate::task::{executor::Executor, Task};
+    use core::sync::atomic::AtomicUsize;
+
+    static PASSED: AtomicUsize = AtomicUsize::new(0);
+
+    let barrier = Barrier::new(3);
+    let mut executor = Executor::new();
+    for _ in 0..2 {
+        let barrier = barrier.clone();
+        executor.spawn_or_panic(Task::new(async move {
+            barrier.wait().await;
+            PASSED.fetch_add(1, Ordering::Relaxed);
+        }));
+    }
+    assert_eq!(executor.drain_with_timeout(10), Err(0));
+    assert_eq!(PASSED.load(Ordering::Relaxed), 0);
+
+    executor.spawn_or_panic(Task::new(async move {
+        barrier.wait().await;
+        PASSED.fetch_add(1, Ordering::Relaxed);
+    }));
+    assert_eq!(executor.drain(), 3);
+    assert_eq!(PASSED.load(Ordering::Relaxed), 3);
+}
@@ -0,0 +1,4 @@
+//! Channels for communication between async tasks.
+
+pub mod watch;
+pub mod barrier;
@@ -0,0 +1,128 @@
+//! A channel that only keeps the latest value, like `tokio::sync::watch`.
+//!
+//! Receivers that fall behind skip the values in between and see only the newest one,
+//! which suits state like the current time or the network link status.
+
+use alloc::{sync::Arc, vec::Vec};
+use core::{future::Future, pin::Pin};
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::task::{Context, Poll, Waker};
+use spin::Mutex;
+
+struct Shared<T> {
+    value: Mutex<T>,
+    // incremented with `value` locked on every send, receivers remember the version they have seen
+    version: AtomicU64,
+    // the receivers waiting in `changed`
+    wakers: Mutex<Vec<Waker>>,
+}
+
+/// Creates a channel holding `initial` and returns its sender and a first receiver.
+pub fn channel<T: Clone>(initial: T) -> (WatchSender<T>, WatchReceiver<T>) {
+    let shared = Arc::new(Shared {
+        value: Mutex::new(initial),
+        version: AtomicU64::new(0),
+        wakers: Mutex::new(Vec::new()),
+    });
+    let receiver = WatchReceiver { shared: shared.clone(), seen_version: 0 };
+    (WatchSender { shared }, receiver)
+}
+
+/// The sending half of a watch channel.
+pub struct WatchSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: Clone> WatchSender<T> {
+    /// Replaces the value and wakes all receivers waiting in `changed`.
+    pub fn send(&self, val: T) {
+        let mut value = self.shared.value.lock();
+        *value = val;
+        self.shared.version.fetch_add(1, Ordering::Release);
+        drop(value);
+        for waker in self.shared.wakers.lock().drain(..) {
+            waker.wake();
+        }
+    }
+}
+
+/// A receiving half of a watch channel, clones see the same values.
+#[derive(Clone)]
+pub struct WatchReceiver<T> {
+    shared: Arc<Shared<T>>,
+    seen_version: u64,
+}
+
+impl<T: Clone> WatchReceiver<T> {
+    /// Returns a copy of the current value without marking it as seen.
+    pub fn borrow(&self) -> T {
+        self.shared.value.lock().clone()
+    }
+
+    /// Waits until a value is sent that this receiver has not seen yet and returns it.
+    pub fn changed(&mut self) -> impl Future<Output = T> + '_ {
+        Changed { receiver: self }
+    }
+}
+
+struct Changed<'a, T> {
+    receiver: &'a mut WatchReceiver<T>,
+}
+
+impl<T: Clone> Changed<'_, T> {
+    // returns the value if there was a send since the receiver last looked
+    fn try_take(&mut self) -> Option<T> {
+        // the version matches the value while it is locked
+        let value = self.receiver.shared.value.lock();
+        let version = self.receiver.shared.version.load(Ordering::Acquire);
+        if version == self.receiver.seen_version {
+            return None;
+        }
+        let val = value.clone();
+        drop(value);
+        self.receiver.seen_version = version;
+        Some(val)
+    }
+}
+
+impl<T: Clone> Future for Changed<'_, T> {
+    type Output = T;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        if let Some(val) = self.try_take() {
+            return Poll::Ready(val);
+        }
+
+        let mut wakers = self.receiver.shared.wakers.lock();
+        // a task polled again before the next send is registered only once
+        if !wakers.iter().any(|waker| waker.will_wake(cx.waker())) {
+            wakers.push(cx.waker().clone());
+        }
+        drop(wakers);
+        // check again in case a send happened before the waker was registered
+        match self.try_take() {
+            Some(val) => Poll::Ready(val),
+            None => Poll::Pending,
+        }
+    }
+}
+
+#[test_case]
+fn test_watch_sees_latest_value() {
+    use core::pin::pin;
+    use futures_util::task::noop_waker;
+
+    let waker = noop_waker();
+    let mut cx = Context::from_waker(&waker);
+    let (sender, mut receiver) = channel(0);
+    let mut other = receiver.clone();
+
+    assert!(pin!(receiver.changed()).poll(&mut cx).is_pending());
+    sender.send(1);
+    sender.send(2);
+    // the receivers skip 1 and only see the latest value
+    assert_eq!(pin!(receiver.changed()).poll(&mut cx), Poll::Ready(2));
+    assert!(pin!(receiver.changed()).poll(&mut cx).is_pending());
+    assert_eq!(pin!(other.changed()).poll(&mut cx), Poll::Ready(2));
+    assert_eq!(receiver.borrow(), 2);
+}
@@ -0,0 +1,56 @@
+use conquer_once::spin::OnceCell;
+use core::{future::Future, pin::Pin};
+use core::task::{Context, Poll, Waker};
+use crossbeam_queue::ArrayQueue;
+use crate::interrupts::ticks;
+use crate::time::Duration;
+
+// wakers of the delays that are waiting for the next timer tick
+static TIMER_WAKERS: OnceCell<ArrayQueue<Waker>> = OnceCell::uninit();
+
+/// Called by the timer interrupt handler on every tick.
+///
+/// Must not block or allocate.
+pub(crate) fn on_tick() {
+    if let Ok(queue) = TIMER_WAKERS.try_get() {
+        // only wake the wakers that were registered before this tick,
+        // woken delays that are not yet due register themselves again
+        for _ in 0..queue.len() {
+            match queue.pop() {
+                Some(waker) => waker.wake(),
+                None => break,
+            }
+        }
+    }
+}
+
+/// A future that completes once the given duration has elapsed.
+pub struct Delay {
+    deadline: u64,
+}
+
+impl Delay {
+    /// Creates a delay that expires `duration` from now, e.g. `Delay::new(Duration::from_ms(100))`.
+    pub fn new(duration: Duration) -> Delay {
+        Delay {
+            deadline: ticks() + duration.as_ticks(),
+        }
+    }
+}
+
+impl Future for Delay {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if ticks() >= self.deadline {
+            return Poll::Ready(());
+        }
+
+        let queue = TIMER_WAKERS.get_or_init(|| ArrayQueue::new(100));
+        if queue.push(cx.waker().clone()).is_err() {
+            // too many sleepers, fall back to polling again right away
+            cx.waker().wake_by_ref();
+        }
+        Poll::Pending
+    }
+}
@@ -1,8 +1,14 @@
 // Import necessary types and modules
 use super::{Task, TaskId}; // `Task` and `TaskId` are used for managing individual tasks
 use alloc::{collections::BTreeMap, sync::Arc}; // `BTreeMap` for task storage, `Arc` for thread-safe shared ownership
+use core::future::Future;
+use core::pin::Pin;
+#[cfg(test)]
+use crate::testing::{call_recoverable, PanicHandlerMode};
 use core::task::{Waker, Context, Poll}; // Core types for async task management
 use crossbeam_queue::ArrayQueue; // Lock-free queue for task scheduling
+use futures_util::task::AtomicWaker;
+use spin::Mutex;
 
 /// The `Executor` struct is responsible for managing and running asynchronous tasks.
 /// It maintains a task queue, tracks tasks, and uses wakers for efficient task scheduling.
@@ -10,40 +16,119 @@ pub struct Executor {
     tasks: BTreeMap<TaskId, Task>, // Store all tasks by their ID for quick access
     task_queue: Arc<ArrayQueue<TaskId>>, // Queue of ready-to-run task IDs
     waker_cache: BTreeMap<TaskId, Waker>, // Cache wakers to avoid recreating them
+    max_tasks: usize, // Maximum number of tasks stored at the same time
 }
 
 impl Executor {
-    /// Create a new `Executor` instance.
+    /// Create a new `Executor` instance with room for 100 tasks.
     pub fn new() -> Self {
+        Executor::with_max_tasks(100)
+    }
+
+    /// Create an `Executor` that stores at most `max_tasks` tasks.
+    /// - The ready queue has room for all of them, so waking a task never overflows it.
+    pub fn with_max_tasks(max_tasks: usize) -> Self {
+        Executor::with_capacity(max_tasks, max_tasks)
+    }
+
+    /// Create an `Executor` whose ready queue holds `task_queue_cap` task IDs
+    /// and which stores at most `max_tasks` tasks.
+    /// - A `BTreeMap` cannot reserve memory up front, so `max_tasks` only limits its size.
+    pub fn with_capacity(task_queue_cap: usize, max_tasks: usize) -> Self {
         Executor {
             tasks: BTreeMap::new(),
-            task_queue: Arc::new(ArrayQueue::new(100)), // Supports up to 100 tasks
+            task_queue: Arc::new(ArrayQueue::new(task_queue_cap)),
             waker_cache: BTreeMap::new(),
+            max_tasks,
         }
     }
 
+    /// Add a new task to the executor, panicking if it is full.
+    /// - For call sites that know the task limit cannot be reached; use `spawn` to handle a full executor.
+    pub fn spawn_or_panic(&mut self, task: Task) {
+        if self.spawn(task).is_err() {
+            panic!("Task queue is full");
+        }
+    }
+
+    /// Add a new task with a name to the executor, panicking if it is full.
+    /// - The name is shown if the task panics.
+    pub fn spawn_named(&mut self, name: &'static str, future: impl Future<Output = ()> + 'static) {
+        self.spawn_or_panic(Task::new_named(name, future));
+    }
+
+    /// Add a task that is treated as completed if it panics, instead of halting the kernel.
+    /// - Test-only: the panic abandons the task's stack frames, see `PanicSafeTask`.
+    #[cfg(test)]
+    pub fn spawn_safe(&mut self, task: Task, name: &'static str) {
+        self.spawn_or_panic(Task::new(PanicSafeTask::new(task, name)));
+    }
+
+    /// Add a task that runs the synchronous function `f` and returns a handle to await its result.
+    /// - The task yields once before calling `f`, so the tasks that are already ready run first.
+    /// - `f` still blocks the executor while it runs; with SMP it could run on another CPU instead.
+    pub fn spawn_blocking<T: 'static>(&mut self, f: impl FnOnce() -> T + 'static) -> JoinHandle<T> {
+        let result = Arc::new(Mutex::new(None));
+        let waker = Arc::new(AtomicWaker::new());
+        let handle = JoinHandle { result: result.clone(), waker: waker.clone() };
+        self.spawn_or_panic(Task::new(async move {
+            super::yield_now().await;
+            *result.lock() = Some(f());
+            waker.wake();
+        }));
+        handle
+    }
+
     /// Add a new task to the executor.
     /// - Assigns the task to the task map using its unique ID.
     /// - Pushes the task ID into the task queue for execution.
-    pub fn spawn(&mut self, task: Task) {
+    /// - Returns the task if the executor already holds `max_tasks` tasks or the task queue is full.
+    pub fn spawn(&mut self, task: Task) -> Result<(), Task> {
         let task_id = task.id;
+        if self.tasks.len() >= self.max_tasks || self.task_queue.is_full() {
+            return Err(task);
+        }
         if self.tasks.insert(task_id, task).is_some() {
             panic!("Task with the same ID already exists in the executor");
         }
-        self.task_queue.push(task_id).expect("Task queue is full");
+        // the queue was checked above, only wakers of queued tasks push to it concurrently
+        if self.task_queue.push(task_id).is_err() {
+            return Err(self.tasks.remove(&task_id).unwrap());
+        }
+        Ok(())
+    }
+
+    /// Remove a task before it has completed, dropping its future.
+    /// - Returns `true` if the task existed.
+    /// - If the task ID is still in the task queue, `run_ready_tasks` skips it since it is no longer in the task map.
+    pub fn remove_task(&mut self, id: TaskId) -> bool {
+        self.waker_cache.remove(&id);
+        self.tasks.remove(&id).is_some()
     }
 
     /// Execute all tasks that are ready to run.
     /// - Polls each task in the queue, checking if it is ready or still pending.
-    pub fn run_ready_tasks(&mut self) {
+    /// - Returns the number of tasks polled.
+    pub fn run_ready_tasks(&mut self) -> usize {
+        self.run_ready_tasks_bounded(usize::MAX)
+    }
+
+    /// Like `run_ready_tasks`, but polls at most `max_polls` tasks.
+    fn run_ready_tasks_bounded(&mut self, max_polls: usize) -> usize {
         let Self {
             tasks,
             task_queue,
             waker_cache,
+            ..
         } = self;
+        let mut polled = 0;
 
         // Loop through all tasks in the queue
-        while let Some(task_id) = task_queue.pop() {
+        while polled < max_polls {
+            let task_id = match task_queue.pop() {
+                Some(task_id) => task_id,
+                None => break,
+            };
             // Retrieve the task from the map
             let task = match tasks.get_mut(&task_id) {
                 Some(task) => task,
@@ -57,6 +142,7 @@ impl Executor {
 
             // Create a `Context` for the task using the waker
             let mut context = Context::from_waker(waker);
+            polled += 1;
 
             // Poll the task to see if it's ready or still pending
             match task.poll(&mut context) {
@@ -68,6 +154,47 @@ impl Executor {
                 Poll::Pending => {} // If still pending, leave it in the map
             }
         }
+        polled
+    }
+
+    /// Run tasks until none is ready, e.g. before the assertions of a test.
+    /// - Returns the number of tasks polled.
+    /// - Never returns if tasks keep waking themselves, see `run_until_idle_or_n`.
+    pub fn run_until_idle(&mut self) -> usize {
+        self.run_until_idle_or_n(usize::MAX).0
+    }
+
+    /// Like `run_until_idle`, but polls at most `max_polls` tasks.
+    /// - Returns the number of tasks polled and whether no task is ready anymore.
+    pub fn run_until_idle_or_n(&mut self, max_polls: usize) -> (usize, bool) {
+        let polled = self.run_ready_tasks_bounded(max_polls);
+        (polled, self.task_queue.is_empty())
+    }
+
+    /// Run tasks until all of them have completed, e.g. at the end of a test.
+    /// - Gives up after 10,000 rounds of `run_ready_tasks`, so a task that keeps waking itself cannot hang the caller.
+    /// - Returns the number of tasks that completed.
+    pub fn drain(&mut self) -> usize {
+        self.drain_with_timeout(10_000).unwrap_or_else(|completed| completed)
+    }
+
+    /// Like `drain`, but gives up after `max_polls` rounds of `run_ready_tasks`.
+    /// - Returns `Err` with the number of completed tasks if tasks are left.
+    pub fn drain_with_timeout(&mut self, max_polls: usize) -> Result<usize, usize> {
+        let initial = self.tasks.len();
+        for _ in 0..max_polls {
+            if self.task_queue.is_empty() && self.tasks.is_empty() {
+                return Ok(initial);
+            }
+            self.run_ready_tasks();
+        }
+        // tasks cannot be spawned while we hold `&mut self`, so only completions shrink the map
+        let completed = initial - self.tasks.len();
+        if self.task_queue.is_empty() && self.tasks.is_empty() {
+            Ok(completed)
+        } else {
+            Err(completed)
+        }
     }
 
     /// Continuously run the executor until all tasks are completed.
@@ -75,6 +202,8 @@ impl Executor {
     pub fn run(&mut self) -> ! {
         loop {
             self.run_ready_tasks(); // Run all tasks that are ready
+            // print what interrupt handlers could not print while the screen was locked
+            crate::vga_buffer::drain_interrupt_print_buffer();
             self.sleep_if_idle();  // Enter sleep mode if no tasks are ready
         }
     }
@@ -129,3 +258,186 @@ impl Wake for TaskWaker {
         self.wake_task();
     }
 }
+
+/// Waits for the result of a function run with `Executor::spawn_blocking`.
+pub struct JoinHandle<T> {
+    result: Arc<Mutex<Option<T>>>,
+    // wakes the task awaiting the handle once the result is stored
+    waker: Arc<AtomicWaker>,
+}
+
+impl<T> JoinHandle<T> {
+    /// Returns whether the function has returned.
+    pub fn is_finished(&self) -> bool {
+        self.result.lock().is_some()
+    }
+}
+
+impl<T> Future for JoinHandle<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<T> {
+        if let Some(result) = self.result.lock().take() {
+            return Poll::Ready(result);
+        }
+        self.waker.register(cx.waker());
+        // the result may have been stored before the waker was registered
+        match self.result.lock().take() {
+            Some(result) => {
+                self.waker.take();
+                Poll::Ready(result)
+            }
+            None => Poll::Pending,
+        }
+    }
+}
+
+/// A task that completes instead of halting the kernel when it panics.
+/// - There is no unwinding: the stack of the panicked poll is abandoned, so nothing it owned is dropped and locks it held stay locked.
+/// - The future of the inner task is dropped in whatever state the panic left it, which is undefined behavior,
+///   so this only exists for the kernel's own tests.
+#[cfg(test)]
+pub struct PanicSafeTask {
+    inner: Task,
+    name: &'static str,
+}
+
+#[cfg(test)]
+impl PanicSafeTask {
+    pub fn new(inner: Task, name: &'static str) -> Self {
+        PanicSafeTask { inner, name }
+    }
+}
+
+#[cfg(test)]
+impl Future for PanicSafeTask {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        struct PollCall<'a, 'b> {
+            task: &'a mut Task,
+            cx: &'a mut Context<'b>,
+            result: Poll<()>,
+        }
+
+        extern "C" fn poll_inner(data: *mut u8) {
+            let call = unsafe { &mut *(data as *mut PollCall) };
+            call.result = call.task.poll(call.cx);
+        }
+
+        let name = self.name;
+        let mut call = PollCall { task: &mut self.inner, cx, result: Poll::Pending };
+        // the panic is recorded in the panic buffer like in `testing::expect_panic`
+        let panicked = call_recoverable(
+            poll_inner, &mut call as *mut PollCall as *mut u8, PanicHandlerMode::Expect);
+        if panicked {
+            crate::serial_println!("task panicked: {}", name);
+            return Poll::Ready(());
+        }
+        call.result
+    }
+}
+
+#[test_case]
+fn test_remove_task() {
+    let mut executor = Executor::new();
+    let task = Task::new(core::future::pending());
+    let id = task.id();
+    executor.spawn_or_panic(task);
+
+    assert!(executor.remove_task(id));
+    assert!(!executor.remove_task(id));
+    // the removed task is still queued and must be skipped
+    executor.run_ready_tasks();
+}
+
+#[test_case]
+fn test_spawn_returns_task_when_full() {
+    let mut executor = Executor::with_max_tasks(2);
+    assert!(executor.spawn(Task::new(core::future::pending())).is_ok());
+    assert!(executor.spawn(Task::new(core::future::pending())).is_ok());
+
+    let task = Task::new(core::future::pending());
+    let id = task.id();
+    match executor.spawn(task) {
+        Err(task) => assert_eq!(task.id(), id),
+        Ok(()) => panic!("spawned more tasks than the executor can hold"),
+    }
+}
+
+#[test_case]
+fn test_drain() {
+    let mut executor = Executor::new();
+    executor.spawn_or_panic(Task::new(async {}));
+    executor.spawn_or_panic(Task::new(async { super::yield_now().await }));
+    assert_eq!(executor.drain(), 2);
+
+    executor.spawn_or_panic(Task::new(core::future::pending()));
+    assert_eq!(executor.drain_with_timeout(10), Err(0));
+}
+
+#[test_case]
+fn test_spawn_safe_survives_panic() {
+    use alloc::rc::Rc;
+    use core::cell::Cell;
+
+    let ran_after = Rc::new(Cell::new(false));
+    let flag = ran_after.clone();
+    let mut executor = Executor::new();
+    executor.spawn_safe(Task::new(async { panic!("panic in a safe task") }), "panicking");
+    executor.spawn_or_panic(Task::new(async move { flag.set(true) }));
+    assert_eq!(executor.drain_with_timeout(10), Ok(2));
+    assert!(ran_after.get());
+    assert!(crate::panic_buffer::last_panic().contains("panic in a safe task"));
+}
+
+#[test_case]
+fn test_spawn_safe_inside_expect_panic() {
+    // the task catches its own panic, not the surrounding `expect_panic`
+    let panicked = crate::testing::expect_panic(|| {
+        let mut executor = Executor::new();
+        executor.spawn_safe(Task::new(async { panic!("inner safe task") }), "inner");
+        assert_eq!(executor.drain_with_timeout(10), Ok(1));
+    });
+    assert!(!panicked);
+    assert_eq!(crate::testing::panic_handler_mode(), crate::testing::PanicHandlerMode::Normal);
+
+    // no recovery point is left behind for the next safe task
+    let mut executor = Executor::new();
+    executor.spawn_safe(Task::new(async { panic!("outer safe task") }), "outer");
+    assert_eq!(executor.drain_with_timeout(10), Ok(1));
+    assert!(crate::panic_buffer::last_panic().contains("outer safe task"));
+}
+
+#[test_case]
+fn test_spawn_blocking() {
+    use alloc::rc::Rc;
+    use core::cell::Cell;
+
+    let received = Rc::new(Cell::new(0));
+    let output = received.clone();
+    let mut executor = Executor::new();
+    let handle = executor.spawn_blocking(|| 6 * 7);
+    assert!(!handle.is_finished());
+    executor.spawn_or_panic(Task::new(async move { output.set(handle.await) }));
+    assert_eq!(executor.drain_with_timeout(10), Ok(2));
+    assert_eq!(received.get(), 42);
+}
+
+#[test_case]
+fn test_run_until_idle() {
+    let mut executor = Executor::new();
+    executor.spawn_or_panic(Task::new(async {}));
+    executor.spawn_or_panic(Task::new(async { super::yield_now().await }));
+    executor.spawn_or_panic(Task::new(core::future::pending()));
+    // the yielding task is polled twice
+    assert_eq!(executor.run_until_idle(), 4);
+    assert_eq!(executor.run_until_idle(), 0);
+
+    executor.spawn_or_panic(Task::new(async {
+        loop {
+            super::yield_now().await;
+        }
+    }));
+    assert_eq!(executor.run_until_idle_or_n(10), (10, false));
+}
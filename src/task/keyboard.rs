@@ -1,22 +1,49 @@
 use conquer_once::spin::OnceCell;
 use crossbeam_queue::ArrayQueue;
-use crate::println;
+use crate::println_from_interrupt;
+use core::sync::atomic::{AtomicU64, Ordering};
 
 static SCANCODE_QUEUE: OnceCell<ArrayQueue<u8>> = OnceCell::uninit();
+/// Number of scancodes dropped because the queue was full, counted by the interrupt handler.
+pub(crate) static DROPPED_SCANCODES: AtomicU64 = AtomicU64::new(0);
+
+/// Why `add_scancode` could not queue a scancode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScancodeError {
+    /// The scancode was dropped because `print_keypresses` has not caught up.
+    QueueFull,
+}
 
 /// Called by the keyboard interrupt handler
 ///
-/// Must not block or allocate.
-pub(crate) fn add_scancode(scancode: u8) {
-    if let Ok(queue) = SCANCODE_QUEUE.try_get() {
-        if let Err(_) = queue.push(scancode) {
-            println!("WARNING: scancode queue full; dropping keyboard input");
-        }else {
-            WAKER.wake();
+/// Must not block or allocate. Scancodes that arrive before `ScancodeStream::new`
+/// created the queue have no reader and are dropped with a warning, without an error.
+pub(crate) fn add_scancode(scancode: u8) -> Result<(), ScancodeError> {
+    let queue = match SCANCODE_QUEUE.try_get() {
+        Ok(queue) => queue,
+        Err(_) => {
+            println_from_interrupt!("WARNING: scancode queue uninitialized");
+            return Ok(());
         }
-    } else {
-        println!("WARNING: scancode queue uninitialized");
-    }
+    };
+    queue.push(scancode).map_err(|_| ScancodeError::QueueFull)?;
+    WAKER.wake();
+    Ok(())
+}
+
+/// Returns how many scancodes the queue can hold, 0 before the queue is created.
+pub fn queue_capacity() -> usize {
+    SCANCODE_QUEUE.try_get().map_or(0, |queue| queue.capacity())
+}
+
+/// Returns the number of scancodes waiting to be processed.
+pub fn queue_len() -> usize {
+    SCANCODE_QUEUE.try_get().map_or(0, |queue| queue.len())
+}
+
+/// Returns the number of scancodes dropped so far because the queue was full.
+pub fn overrun_count() -> u64 {
+    DROPPED_SCANCODES.load(Ordering::Relaxed)
 }
 
 pub struct ScancodeStream {
@@ -59,23 +86,83 @@ impl Stream for ScancodeStream {
     }
 }
 
+use futures_util::future::{self, Either};
 use futures_util::stream::StreamExt;
-use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet1};
-use crate::print;
+use pc_keyboard::{layouts, DecodedKey, HandleControl, Keyboard, ScancodeSet2};
+use crate::drivers::ps2_controller::{Controller, KEYBOARD_ACK, KEYBOARD_RESET, KEYBOARD_SELF_TEST_PASSED};
+use crate::task::delay::Delay;
+use crate::time::Duration;
+use crate::{print, serial_println};
+
+// how long to wait for each response of the keyboard after a reset
+const RESET_TIMEOUT_MS: u64 = 1000;
+
+fn new_keyboard() -> Keyboard<layouts::Us104Key, ScancodeSet2> {
+    // the PS/2 controller is set up without translation, so the keyboard sends scancode set 2
+    Keyboard::new(ScancodeSet2::new(), layouts::Us104Key, HandleControl::Ignore)
+}
 
 pub async fn print_keypresses() {
     let mut scancodes = ScancodeStream::new();
-    let mut keyboard = Keyboard::new(ScancodeSet1::new(),
-        layouts::Us104Key, HandleControl::Ignore);
+    let mut keyboard = new_keyboard();
 
     while let Some(scancode) = scancodes.next().await {
-        if let Ok(Some(key_event)) = keyboard.add_byte(scancode) {
-            if let Some(key) = keyboard.process_keyevent(key_event) {
-                match key {
-                    DecodedKey::Unicode(character) => print!("{}", character),
-                    DecodedKey::RawKey(key) => print!("{:?}", key),
+        match keyboard.add_byte(scancode) {
+            Ok(Some(key_event)) => {
+                if let Some(key) = keyboard.process_keyevent(key_event) {
+                    match key {
+                        DecodedKey::Unicode(character) => print!("{}", character),
+                        DecodedKey::RawKey(key) => print!("{:?}", key),
+                    }
                 }
             }
+            Ok(None) => {}
+            Err(err) => {
+                // the decoder may be in the middle of a sequence that never completes
+                serial_println!("keyboard: {:?} on scancode {:#04x}, resetting", err, scancode);
+                if !reset_keyboard(&mut scancodes).await {
+                    serial_println!("keyboard: reset failed");
+                }
+                keyboard = new_keyboard();
+            }
+        }
+    }
+}
+
+// resets the keyboard and waits for its ACK and the result of its self test,
+// returns whether the keyboard passed
+async fn reset_keyboard(scancodes: &mut ScancodeStream) -> bool {
+    let controller = match Controller::get() {
+        Some(controller) => controller,
+        None => return false,
+    };
+    if controller.send_to_keyboard(KEYBOARD_RESET).is_err() {
+        return false;
+    }
+    // the responses arrive through the interrupt handler, after the scancodes
+    // that were already queued, which are dropped
+    for expected in [KEYBOARD_ACK, KEYBOARD_SELF_TEST_PASSED] {
+        loop {
+            let timeout = Delay::new(Duration::from_ms(RESET_TIMEOUT_MS));
+            match future::select(scancodes.next(), timeout).await {
+                Either::Left((Some(byte), _)) if byte == expected => break,
+                Either::Left((Some(_), _)) => continue,
+                Either::Left((None, _)) | Either::Right(_) => return false,
+            }
         }
     }
-}
\ No newline at end of file
+    true
+}
+
+#[test_case]
+fn test_add_scancode_reports_full_queue() {
+    let _stream = ScancodeStream::new();
+    let queue = SCANCODE_QUEUE.try_get().unwrap();
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        while !queue.is_full() {
+            assert_eq!(add_scancode(0x1c), Ok(()));
+        }
+        assert_eq!(add_scancode(0x1c), Err(ScancodeError::QueueFull));
+        while queue.pop().is_some() {}
+    });
+}
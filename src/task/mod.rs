@@ -5,9 +5,13 @@ use core::task::{Context, Poll};
 pub mod simple_executor;
 pub mod keyboard;
 pub mod executor;
+pub mod delay;
+pub mod channel;
+pub mod async_printer;
 
 pub struct Task {
     id: TaskId,
+    name: Option<&'static str>,
     future: Pin<Box<dyn Future<Output = ()>>>,
 }
 
@@ -15,19 +19,64 @@ impl Task {
     pub fn new(future: impl Future<Output = ()> + 'static) -> Task {
         Task {
             id: TaskId::new(),
+            name: None,
             future: Box::pin(future),
         }
     }
 
+    /// Creates a task with a name, which is shown if the task panics.
+    pub fn new_named(name: &'static str, future: impl Future<Output = ()> + 'static) -> Task {
+        let mut task = Task::new(future);
+        task.set_name(name);
+        task
+    }
+
+    pub fn set_name(&mut self, name: &'static str) {
+        self.name = Some(name);
+    }
+
+    pub fn name(&self) -> Option<&'static str> {
+        self.name
+    }
+
+    /// Returns the ID the task is identified by in the executor.
+    pub fn id(&self) -> TaskId {
+        self.id
+    }
+
     fn poll(&mut self, cx: &mut Context) -> Poll<()> {
-        self.future.as_mut().poll(cx)
+        // the panic handler reads the name, restored afterwards in case polls are nested
+        let name = self.name.unwrap_or("");
+        let previous_ptr = CURRENT_TASK_NAME.swap(name.as_ptr() as *mut u8, Ordering::Relaxed);
+        let previous_len = CURRENT_TASK_NAME_LEN.swap(name.len(), Ordering::Relaxed);
+        let result = self.future.as_mut().poll(cx);
+        CURRENT_TASK_NAME_LEN.store(previous_len, Ordering::Relaxed);
+        CURRENT_TASK_NAME.store(previous_ptr, Ordering::Relaxed);
+        result
     }
 }
 
+// the name of the task that is being polled, stored as pointer and length of a `&'static str`
+static CURRENT_TASK_NAME: AtomicPtr<u8> = AtomicPtr::new(core::ptr::null_mut());
+static CURRENT_TASK_NAME_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns the name of the task that is being polled, `None` outside of tasks
+/// and for tasks without a name. Used by the panic handlers.
+pub fn current_task_name() -> Option<&'static str> {
+    let ptr = CURRENT_TASK_NAME.load(Ordering::Relaxed);
+    let len = CURRENT_TASK_NAME_LEN.load(Ordering::Relaxed);
+    if ptr.is_null() || len == 0 {
+        return None;
+    }
+    // both were taken from a `&'static str` by `Task::poll`
+    Some(unsafe { core::str::from_utf8_unchecked(core::slice::from_raw_parts(ptr, len)) })
+}
+
+/// A unique identifier of a `Task`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-struct TaskId(u64);
+pub struct TaskId(u64);
 
-use core::sync::atomic::{AtomicU64, Ordering};
+use core::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
 
 impl TaskId {
     fn new() -> TaskId {
@@ -37,4 +86,77 @@ impl TaskId {
         TaskId(NEXT_ID.fetch_add(1, Ordering::Relaxed))
         // fetch_add(1, Ordering::Relaxed) atomically increments the value by 1 and returns the previous value
     }
-}
\ No newline at end of file
+}
+
+/// Returns a future that is pending exactly once, giving other tasks a chance to run.
+pub fn yield_now() -> impl Future<Output = ()> {
+    YieldNow { yielded: false }
+}
+
+struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.yielded {
+            return Poll::Ready(());
+        }
+        self.yielded = true;
+        // reschedule ourselves right away, we only want to go to the back of the queue
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Returns a future that is pending `n` times, going to the back of the ready queue
+/// each time, e.g. to let other tasks run during a long computation.
+pub fn yield_after(n: u64) -> impl Future<Output = ()> {
+    YieldAfter { polls_remaining: n }
+}
+
+/// The future returned by `yield_after`.
+pub struct YieldAfter {
+    polls_remaining: u64,
+}
+
+impl Future for YieldAfter {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if self.polls_remaining == 0 {
+            return Poll::Ready(());
+        }
+        self.polls_remaining -= 1;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+#[test_case]
+fn test_yield_after() {
+    use executor::Executor;
+
+    let mut executor = Executor::new();
+    executor.spawn_or_panic(Task::new(yield_after(0)));
+    assert_eq!(executor.run_until_idle(), 1);
+    executor.spawn_or_panic(Task::new(yield_after(3)));
+    assert_eq!(executor.run_until_idle(), 4);
+}
+
+#[test_case]
+fn test_current_task_name() {
+    use simple_executor::SimpleExecutor;
+
+    let mut executor = SimpleExecutor::new();
+    executor.spawn(Task::new_named("named", async {
+        assert_eq!(current_task_name(), Some("named"));
+    }));
+    executor.spawn(Task::new(async {
+        assert_eq!(current_task_name(), None);
+    }));
+    executor.run();
+    assert_eq!(current_task_name(), None);
+}
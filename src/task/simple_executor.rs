@@ -44,6 +44,32 @@ impl SimpleExecutor {
             }
         }
     }
+
+    // Polls tasks until all of them have completed and returns the number of polls.
+    // There are no wakers, so a pending task counts as ready and this never returns
+    // while a task is pending, see run_until_idle_or_n.
+    pub fn run_until_idle(&mut self) -> usize {
+        self.run_until_idle_or_n(usize::MAX).0
+    }
+
+    // Like run_until_idle, but polls at most max_polls times.
+    // Returns the number of polls and whether all tasks have completed.
+    pub fn run_until_idle_or_n(&mut self, max_polls: usize) -> (usize, bool) {
+        let mut polled = 0;
+        while polled < max_polls {
+            let mut task = match self.task_queue.pop_front() {
+                Some(task) => task,
+                None => break,
+            };
+            let waker = dummy_waker();
+            let mut context = Context::from_waker(&waker);
+            polled += 1;
+            if task.poll(&mut context).is_pending() {
+                self.task_queue.push_back(task);
+            }
+        }
+        (polled, self.task_queue.is_empty())
+    }
 }
 
 // Import Waker and RawWaker from core::task for creating a dummy waker
@@ -77,3 +103,14 @@ fn dummy_raw_waker() -> RawWaker {
     // Create and return a RawWaker with a null pointer and the vtable
     RawWaker::new(0 as *const (), vtable)
 }
+
+#[test_case]
+fn test_simple_executor_run_until_idle() {
+    let mut executor = SimpleExecutor::new();
+    executor.spawn(Task::new(async {}));
+    executor.spawn(Task::new(async { super::yield_now().await }));
+    assert_eq!(executor.run_until_idle(), 3);
+
+    executor.spawn(Task::new(core::future::pending()));
+    assert_eq!(executor.run_until_idle_or_n(5), (5, false));
+}
@@ -0,0 +1,325 @@
+//! Support for tests registered with the `#[kernel_test]` attribute.
+//!
+//! Every test is a `KernelTest` static in the `kernel_tests` link section. The linker
+//! defines `__start_kernel_tests` and `__stop_kernel_tests` around that section, so the
+//! test runner can find all tests without help from the compiler.
+
+pub use turiya_macros::kernel_test;
+
+#[cfg(test)]
+pub mod serial_capture;
+
+use core::arch::global_asm;
+use core::fmt;
+use core::panic::PanicInfo;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicU64, AtomicUsize, Ordering};
+use crate::{exit_qemu, interrupts::ticks, serial_print, serial_println, time::Duration, QemuExitCode};
+
+/// A test registered with `#[kernel_test]`.
+pub struct KernelTest {
+    pub name: &'static str,
+    pub test_fn: fn(),
+    /// The test fails if it runs longer than this.
+    pub timeout_ms: Option<u64>,
+    /// The test only passes if it panics.
+    pub should_panic: bool,
+}
+
+// makes sure the section exists in every binary, otherwise the linker
+// would not define the start and stop symbols
+#[used]
+#[link_section = "kernel_tests"]
+static PLACEHOLDER: KernelTest = KernelTest {
+    name: "",
+    test_fn: placeholder,
+    timeout_ms: None,
+    should_panic: false,
+};
+
+fn placeholder() {}
+
+// only the addresses of these symbols are meaningful
+extern "C" {
+    static __start_kernel_tests: u8;
+    static __stop_kernel_tests: u8;
+}
+
+// the index of the running test in `all_tests`, usize::MAX if none is running
+static CURRENT_TEST: AtomicUsize = AtomicUsize::new(usize::MAX);
+// the tick at which the running test times out, u64::MAX if it has no timeout
+static DEADLINE: AtomicU64 = AtomicU64::new(u64::MAX);
+
+// all entries of the section, including the placeholder
+fn all_tests() -> &'static [KernelTest] {
+    unsafe {
+        let start = core::ptr::addr_of!(__start_kernel_tests) as *const KernelTest;
+        let stop = core::ptr::addr_of!(__stop_kernel_tests) as *const KernelTest;
+        let count = (stop as usize - start as usize) / core::mem::size_of::<KernelTest>();
+        core::slice::from_raw_parts(start, count)
+    }
+}
+
+/// Returns all tests registered with `#[kernel_test]` in this binary.
+pub fn kernel_tests() -> impl Iterator<Item = &'static KernelTest> {
+    all_tests().iter().filter(|test| !core::ptr::eq(*test, &PLACEHOLDER))
+}
+
+/// Runs the registered tests, starting with the test at index `first` of the section.
+pub(crate) fn run_from(first: usize) {
+    for (index, test) in all_tests().iter().enumerate().skip(first) {
+        if core::ptr::eq(test, &PLACEHOLDER) {
+            continue;
+        }
+
+        serial_print!("{}...\t", test.name);
+        let deadline = match test.timeout_ms {
+            Some(ms) => ticks() + Duration::from_ms(ms).as_ticks(),
+            None => u64::MAX,
+        };
+        DEADLINE.store(deadline, Ordering::Relaxed);
+        CURRENT_TEST.store(index, Ordering::Relaxed);
+
+        (test.test_fn)();
+
+        CURRENT_TEST.store(usize::MAX, Ordering::Relaxed);
+        DEADLINE.store(u64::MAX, Ordering::Relaxed);
+        if test.should_panic {
+            serial_println!("[failed]\n");
+            serial_println!("Error: test did not panic\n");
+            exit_qemu(QemuExitCode::Failed);
+            crate::hlt_loop();
+        }
+        serial_println!("[ok]");
+    }
+}
+
+/// Called by the panic handler. If the running test expects a panic, this reports
+/// success and continues with the next test, so it never returns in that case.
+///
+/// The stack of the panicked test is abandoned, the remaining tests run on top of it.
+pub(crate) fn on_panic() {
+    let index = CURRENT_TEST.load(Ordering::Relaxed);
+    match all_tests().get(index) {
+        Some(test) if test.should_panic => {
+            CURRENT_TEST.store(usize::MAX, Ordering::Relaxed);
+            DEADLINE.store(u64::MAX, Ordering::Relaxed);
+            serial_println!("[ok]");
+            run_from(index + 1);
+            exit_qemu(QemuExitCode::Success);
+            crate::hlt_loop();
+        }
+        _ => {}
+    }
+}
+
+/// Called by the timer interrupt handler on every tick, fails the running test
+/// if it has exceeded its timeout.
+pub(crate) fn check_timeout() {
+    if ticks() >= DEADLINE.load(Ordering::Relaxed) {
+        serial_println!("[timeout]\n");
+        exit_qemu(QemuExitCode::Timeout);
+        crate::hlt_loop();
+    }
+}
+
+/// Returns whether the panic comes from `assert!`, `assert_eq!` or `assert_ne!`.
+pub(crate) fn is_assertion_failure(info: &PanicInfo) -> bool {
+    // compares the message with the prefix while it is formatted, without a buffer
+    struct PrefixMatcher {
+        prefix: &'static [u8],
+        matched: usize,
+    }
+
+    impl fmt::Write for PrefixMatcher {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            for &byte in s.as_bytes() {
+                if self.matched == self.prefix.len() || byte != self.prefix[self.matched] {
+                    // stop formatting, the result is known
+                    return Err(fmt::Error);
+                }
+                self.matched += 1;
+            }
+            Ok(())
+        }
+    }
+
+    // the messages of the assert macros all start with this
+    let mut matcher = PrefixMatcher { prefix: b"assertion", matched: 0 };
+    let _ = fmt::write(&mut matcher, format_args!("{}", info.message()));
+    matcher.matched == matcher.prefix.len()
+}
+
+/// How the test panic handler treats a panic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum PanicHandlerMode {
+    /// The panic fails the running test.
+    Normal = 0,
+    /// The panic is recorded in the panic buffer and `expect_panic` returns `true`.
+    /// A `PanicSafeTask` that is being polled catches panics in this mode as well.
+    Expect = 1,
+    /// Like `Expect`, but the panic is not recorded.
+    Suppress = 2,
+}
+
+// a place the panic handler can continue at, lives on the stack of `call_recoverable`
+struct RecoveryPoint {
+    buffer: JumpBuffer,
+    mode: PanicHandlerMode,
+    // the next outer point, or null
+    previous: *mut RecoveryPoint,
+}
+
+// the innermost recovery point of `expect_panic`, `suppress_panic` and the `PanicSafeTask`s
+// being polled, the outer ones are linked through `previous`
+static RECOVERY_POINTS: AtomicPtr<RecoveryPoint> = AtomicPtr::new(ptr::null_mut());
+
+/// Returns how the panic handler currently treats a panic.
+pub fn panic_handler_mode() -> PanicHandlerMode {
+    let point = RECOVERY_POINTS.load(Ordering::SeqCst);
+    if point.is_null() {
+        PanicHandlerMode::Normal
+    } else {
+        unsafe { (*point).mode }
+    }
+}
+
+/// Calls `f` and returns whether it panicked. The panic message can be read
+/// with `panic_buffer::last_panic` afterwards.
+///
+/// There is no unwinding, so if `f` panics its stack is abandoned: nothing `f` owns
+/// is dropped and locks it holds stay locked. Only panics that reach
+/// `test_panic_handler` are caught.
+pub fn expect_panic(f: impl FnOnce()) -> bool {
+    catch_panic(f, PanicHandlerMode::Expect)
+}
+
+/// Like `expect_panic`, but the panic is not recorded in the panic buffer.
+pub fn suppress_panic(f: impl FnOnce()) -> bool {
+    catch_panic(f, PanicHandlerMode::Suppress)
+}
+
+// the callee-saved registers, the stack pointer and the return address of `turiya_try_call`
+#[repr(C)]
+#[derive(Default)]
+struct JumpBuffer {
+    registers: [u64; 8],
+}
+
+extern "C" {
+    // calls `f(data)` and returns 0, or 1 if `turiya_long_jump` was called on `buffer`
+    fn turiya_try_call(f: extern "C" fn(*mut u8), data: *mut u8, buffer: *mut JumpBuffer) -> u64;
+    // continues after the `turiya_try_call` that filled `buffer`, which then returns 1
+    fn turiya_long_jump(buffer: *const JumpBuffer) -> !;
+}
+
+// System V ABI: the arguments are in rdi, rsi and rdx, rbx, rbp and r12-r15 are callee-saved
+global_asm!(
+    ".global turiya_try_call",
+    "turiya_try_call:",
+    "mov [rdx], rbx",
+    "mov [rdx + 8], rbp",
+    "mov [rdx + 16], r12",
+    "mov [rdx + 24], r13",
+    "mov [rdx + 32], r14",
+    "mov [rdx + 40], r15",
+    // the stack pointer after returning, and the return address
+    "lea rax, [rsp + 8]",
+    "mov [rdx + 48], rax",
+    "mov rax, [rsp]",
+    "mov [rdx + 56], rax",
+    // realign the stack to 16 bytes for the call
+    "sub rsp, 8",
+    "mov rax, rdi",
+    "mov rdi, rsi",
+    "call rax",
+    "add rsp, 8",
+    "xor eax, eax",
+    "ret",
+    "",
+    ".global turiya_long_jump",
+    "turiya_long_jump:",
+    "mov rbx, [rdi]",
+    "mov rbp, [rdi + 8]",
+    "mov r12, [rdi + 16]",
+    "mov r13, [rdi + 24]",
+    "mov r14, [rdi + 32]",
+    "mov r15, [rdi + 40]",
+    "mov rsp, [rdi + 48]",
+    "mov eax, 1",
+    "jmp qword ptr [rdi + 56]",
+);
+
+fn catch_panic<F: FnOnce()>(f: F, mode: PanicHandlerMode) -> bool {
+    extern "C" fn call<F: FnOnce()>(data: *mut u8) {
+        let f = unsafe { &mut *(data as *mut Option<F>) };
+        (f.take().unwrap())();
+    }
+
+    let mut f = Some(f);
+    call_recoverable(call::<F>, &mut f as *mut Option<F> as *mut u8, mode)
+}
+
+/// Calls `f(data)` with a recovery point in `mode` on top of the stack of recovery points
+/// and returns whether `f` panicked. Calls can be nested, a panic always continues after
+/// the innermost one.
+pub(crate) fn call_recoverable(f: extern "C" fn(*mut u8), data: *mut u8, mode: PanicHandlerMode) -> bool {
+    let mut point = RecoveryPoint { buffer: JumpBuffer::default(), mode, previous: ptr::null_mut() };
+    let point: *mut RecoveryPoint = &mut point;
+    // the panic can happen with interrupts disabled, e.g. inside `without_interrupts`
+    let flags = crate::cpu::save_flags();
+
+    let panicked = unsafe {
+        (*point).previous = RECOVERY_POINTS.swap(point, Ordering::SeqCst);
+        let panicked = turiya_try_call(f, data, &mut (*point).buffer) != 0;
+        // `recover_panic` pops the point before jumping here, this pops it if `f` returned
+        RECOVERY_POINTS.store((*point).previous, Ordering::SeqCst);
+        panicked
+    };
+
+    if panicked {
+        crate::cpu::restore_flags(flags);
+    }
+    panicked
+}
+
+/// Called first by the test panic handler. Does not return if the panic happened inside
+/// `expect_panic`, `suppress_panic` or while a `PanicSafeTask` was polled, but continues
+/// after the innermost of them.
+///
+/// The stack frames of the panicked code are abandoned, not unwound, so this must not
+/// be used by the panic handler of the kernel itself.
+pub fn recover_panic(info: &PanicInfo) {
+    let point = RECOVERY_POINTS.load(Ordering::SeqCst);
+    if point.is_null() {
+        return;
+    }
+    unsafe {
+        // popped first, so a panic while recording continues at the next outer point
+        RECOVERY_POINTS.store((*point).previous, Ordering::SeqCst);
+        if (*point).mode == PanicHandlerMode::Expect {
+            crate::panic_buffer::record(info);
+        }
+        turiya_long_jump(&(*point).buffer)
+    }
+}
+
+#[kernel_test(timeout_ms = 1000)]
+fn test_kernel_test_is_registered() {
+    assert!(kernel_tests().any(|test| test.name.ends_with("::test_kernel_test_is_registered")));
+}
+
+#[kernel_test(should_panic = true)]
+fn test_should_panic_continues() {
+    panic!("expected panic");
+}
+
+#[kernel_test]
+fn test_expect_panic() {
+    assert!(expect_panic(|| panic!("expected panic")));
+    assert!(crate::panic_buffer::last_panic().contains("expected panic"));
+    assert!(!expect_panic(|| {}));
+    assert!(suppress_panic(|| assert_eq!(1 + 1, 3)));
+    assert_eq!(panic_handler_mode(), PanicHandlerMode::Normal);
+}
@@ -0,0 +1,43 @@
+//! Keeps a copy of the serial output of the unit tests, so tests can check what was printed.
+
+use core::fmt::{self, Write};
+use spin::Mutex;
+use crate::{collections::string::KernelString, exit_qemu, serial_println, QemuExitCode};
+
+/// Everything printed with `serial_print!` since the last `clear`.
+///
+/// Output that does not fit anymore is dropped.
+pub static SERIAL_CAPTURE: Mutex<KernelString<4096>> = Mutex::new(KernelString::new());
+
+/// Called by `serial::_print` with interrupts disabled.
+pub(crate) fn capture(args: fmt::Arguments) {
+    // the lock is only held briefly, a nested print from a panic just skips the capture
+    if let Some(mut buffer) = SERIAL_CAPTURE.try_lock() {
+        let _ = buffer.write_fmt(args);
+    }
+}
+
+/// Empties the capture buffer, e.g. at the start of a test.
+pub fn clear() {
+    SERIAL_CAPTURE.lock().clear();
+}
+
+/// Fails the test run if `pat` was not printed to the serial port since the last `clear`.
+pub fn assert_contains(pat: &str) {
+    let found = SERIAL_CAPTURE.lock().contains(pat);
+    if !found {
+        serial_println!("[failed]\n");
+        serial_println!("Error: serial output does not contain {:?}\n", pat);
+        exit_qemu(QemuExitCode::Failed);
+        crate::hlt_loop();
+    }
+}
+
+#[test_case]
+fn test_serial_output_is_captured() {
+    clear();
+    crate::serial_print!("captured {} ", 42);
+    assert_contains("captured 42");
+    clear();
+    assert!(SERIAL_CAPTURE.lock().is_empty());
+}
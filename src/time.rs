@@ -0,0 +1,152 @@
+//! Time measurement based on the timer interrupt's tick counter.
+
+use core::ops::{Add, Sub};
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+#[cfg(any(test, feature = "timestamped_log"))]
+use core::{fmt, sync::atomic::AtomicBool};
+use crate::interrupts::ticks;
+
+/// The input frequency of the programmable interval timer.
+pub const PIT_FREQUENCY_HZ: u64 = 1_193_182;
+
+// the divisor PIT channel 0 is programmed with, 65536 (written as 0) is the BIOS default
+// that results in about 18.2 timer interrupts per second
+static PIT_DIVISOR: AtomicU32 = AtomicU32::new(65536);
+
+/// Records that PIT channel 0 has been reprogrammed with `divisor`.
+///
+/// Must be called whenever the timer rate changes so tick conversions stay correct.
+pub fn set_pit_divisor(divisor: u32) {
+    PIT_DIVISOR.store(divisor, Ordering::Relaxed);
+}
+
+/// A span of time measured in timer ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub struct Duration {
+    ticks: u64,
+}
+
+impl Duration {
+    pub const fn from_ticks(ticks: u64) -> Duration {
+        Duration { ticks }
+    }
+
+    /// Converts `ms` to ticks at the current timer rate, rounding up so that
+    /// delays never end early.
+    pub fn from_ms(ms: u64) -> Duration {
+        let divisor = u64::from(PIT_DIVISOR.load(Ordering::Relaxed));
+        Duration { ticks: (ms * PIT_FREQUENCY_HZ).div_ceil(divisor * 1000) }
+    }
+
+    pub const fn as_ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    /// Converts the duration to milliseconds at the current timer rate, rounding down.
+    pub fn as_ms(&self) -> u64 {
+        let divisor = u64::from(PIT_DIVISOR.load(Ordering::Relaxed));
+        self.ticks * divisor * 1000 / PIT_FREQUENCY_HZ
+    }
+}
+
+/// A point in time since boot, measured in timer ticks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Instant {
+    ticks: u64,
+}
+
+impl Instant {
+    pub fn now() -> Instant {
+        Instant { ticks: ticks() }
+    }
+
+    /// Returns the time that has passed since `self`.
+    pub fn elapsed(&self) -> Duration {
+        Instant::now() - *self
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    /// Returns the time between the instants, or zero if `other` is later.
+    fn sub(self, other: Instant) -> Duration {
+        Duration { ticks: self.ticks.saturating_sub(other.ticks) }
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    fn add(self, duration: Duration) -> Instant {
+        Instant { ticks: self.ticks + duration.ticks }
+    }
+}
+
+// the tick count the timestamps of the `timestamped_log` feature are relative to
+static LOG_EPOCH: AtomicU64 = AtomicU64::new(0);
+
+/// Makes the timestamps of the `timestamped_log` feature count from now, e.g. from the start of the tests.
+pub fn set_log_epoch() {
+    LOG_EPOCH.store(ticks(), Ordering::Relaxed);
+}
+
+/// Passes everything written to it on to `write`, starting every line with the ticks
+/// since `set_log_epoch`, right-justified in 10 characters: `[T+        42] `.
+///
+/// `at_line_start` belongs to the output device, so a line continued by the next
+/// print does not get a second timestamp.
+#[cfg(any(test, feature = "timestamped_log"))]
+pub(crate) struct TimestampWriter<'a, F: FnMut(&str)> {
+    write: F,
+    at_line_start: &'a AtomicBool,
+}
+
+#[cfg(any(test, feature = "timestamped_log"))]
+impl<'a, F: FnMut(&str)> TimestampWriter<'a, F> {
+    pub(crate) fn new(at_line_start: &'a AtomicBool, write: F) -> Self {
+        TimestampWriter { write, at_line_start }
+    }
+}
+
+#[cfg(any(test, feature = "timestamped_log"))]
+impl<F: FnMut(&str)> fmt::Write for TimestampWriter<'_, F> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for line in s.split_inclusive('\n') {
+            if self.at_line_start.load(Ordering::Relaxed) {
+                let ticks = ticks().saturating_sub(LOG_EPOCH.load(Ordering::Relaxed));
+                let mut prefix = crate::collections::string::KernelString::<32>::new();
+                fmt::write(&mut prefix, format_args!("[T+{:>10}] ", ticks))?;
+                (self.write)(prefix.as_str());
+            }
+            (self.write)(line);
+            self.at_line_start.store(line.ends_with('\n'), Ordering::Relaxed);
+        }
+        Ok(())
+    }
+}
+
+#[test_case]
+fn test_duration_conversion() {
+    // 18 ticks at the default rate are slightly less than one second
+    assert_eq!(Duration::from_ms(1000).as_ticks(), 19);
+    assert_eq!(Duration::from_ticks(18).as_ms(), 988);
+    assert_eq!(Duration::from_ms(0).as_ticks(), 0);
+}
+
+#[test_case]
+fn test_timestamp_writer() {
+    use crate::collections::string::KernelString;
+    use core::fmt::Write;
+
+    let at_line_start = AtomicBool::new(true);
+    let mut output = KernelString::<64>::new();
+    // no timer ticks while interrupts are disabled
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        set_log_epoch();
+        let mut writer = TimestampWriter::new(&at_line_start, |s| output.write_str(s).unwrap());
+        write!(writer, "a\nb").unwrap();
+        write!(writer, "c\n").unwrap();
+    });
+    assert_eq!(output.as_str(), "[T+         0] a\n[T+         0] bc\n");
+}
@@ -30,10 +30,10 @@ pub enum Color {
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 /// The repr(transparent) attribute tells the compiler to represent ColorCode as a single u8 in memory.
 #[repr(transparent)]
-struct ColorCode(u8);
+pub struct ColorCode(u8);
 
 impl ColorCode {
-    fn new(foreground: Color, background: Color) -> ColorCode {
+    pub fn new(foreground: Color, background: Color) -> ColorCode {
         ColorCode((background as u8) << 4 | (foreground as u8))
     }
 }
@@ -47,17 +47,58 @@ struct ScreenChar {
     color_code: ColorCode,
 }
 
-/// the height and width of the text buffer
-const BUFFER_HEIGHT: usize = 25;
-const BUFFER_WIDTH: usize = 80;
+impl ScreenChar {
+    /// A space in the given colors.
+    pub fn blank(color_code: ColorCode) -> ScreenChar {
+        ScreenChar {
+            ascii_character: b' ',
+            color_code,
+        }
+    }
+}
+
+impl Default for ScreenChar {
+    /// A space, white on black.
+    fn default() -> ScreenChar {
+        ScreenChar::blank(ColorCode::new(Color::White, Color::Black))
+    }
+}
+
+/// the height and width of the text buffer after boot,
+/// `Writer::dimensions` returns the current ones after a `resize`
+pub const BUFFER_HEIGHT: usize = 25;
+pub const BUFFER_WIDTH: usize = 80;
+
+/// the largest text mode the hardware supports, e.g. 80x50 with the 8x8 font
+const MAX_BUFFER_HEIGHT: usize = 50;
+const MAX_BUFFER_WIDTH: usize = 80;
+
+/// The VgaError enum describes why a VGA operation failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VgaError {
+    /// The requested text mode is larger than the hardware supports, or empty.
+    InvalidDimensions { height: usize, width: usize },
+}
 
 // use the volatile crate to prevent the compiler from optimizing away writes to the VGA buffer
 use volatile::Volatile; 
 
 /// The Buffer struct represents the entire VGA text buffer.
+/// The rows are stored back to back, so the offset of a row depends on the current width.
 #[repr(transparent)]
 struct Buffer {
-    chars: [[Volatile<ScreenChar>; BUFFER_WIDTH]; BUFFER_HEIGHT],
+    chars: [Volatile<ScreenChar>; MAX_BUFFER_HEIGHT * MAX_BUFFER_WIDTH],
+}
+
+impl Buffer {
+    /// The clear_with method writes `ch` to the first `cells` characters.
+    /// Only the characters of the current text mode may be written, the bootloader
+    /// does not map the whole buffer.
+    fn clear_with(&mut self, ch: ScreenChar, cells: usize) {
+        for cell in &mut self.chars[..cells] {
+            cell.write(ch);
+        }
+    }
 }
 
 /// The Writer struct represents the state of the VGA text buffer.
@@ -67,11 +108,46 @@ struct Buffer {
 pub struct Writer {
     column_position: usize,
     color_code: ColorCode,
+    /// the dimensions of the current text mode
+    height: usize,
+    width: usize,
     buffer: &'static mut Buffer,
 }
 
 impl Writer {
-    
+    /// The resize method switches the writer to a text mode with the given dimensions,
+    /// e.g. after the VGA mode was changed from 80x25 to 80x50, and clears the screen.
+    ///
+    /// The bootloader only maps the first 4 KiB of the buffer, that is 2048 characters,
+    /// so the caller has to map the rest before switching to a larger mode.
+    pub fn resize(&mut self, height: usize, width: usize) -> Result<(), VgaError> {
+        if height == 0 || width == 0 || height > MAX_BUFFER_HEIGHT || width > MAX_BUFFER_WIDTH {
+            return Err(VgaError::InvalidDimensions { height, width });
+        }
+        self.height = height;
+        self.width = width;
+        for row in 0..height {
+            self.clear_row(row);
+        }
+        self.column_position = 0;
+        Ok(())
+    }
+
+    /// The set_buffer_height_runtime method changes only the height, see `resize`.
+    pub fn set_buffer_height_runtime(&mut self, new_height: usize) -> Result<(), VgaError> {
+        self.resize(new_height, self.width)
+    }
+
+    /// The height and width of the current text mode.
+    pub fn dimensions(&self) -> (usize, usize) {
+        (self.height, self.width)
+    }
+
+    /// The cell method returns the character at the given position of the current mode.
+    fn cell(&mut self, row: usize, col: usize) -> &mut Volatile<ScreenChar> {
+        &mut self.buffer.chars[row * self.width + col]
+    }
+
     /// The write_string method writes a string to the buffer at the current cursor position.
     pub fn write_string(&mut self, s: &str) {
         for byte in s.bytes() {
@@ -95,19 +171,19 @@ impl Writer {
             // if it is any other byte, write it to the buffer
             byte => {
                 // if the current line is full, call the new_line method
-                if self.column_position >= BUFFER_WIDTH {
+                if self.column_position >= self.width {
                     self.new_line();
                 }
 
                 // get the current row and column position
-                let row = BUFFER_HEIGHT - 1;
+                let row = self.height - 1;
                 let col = self.column_position;
 
                 // get the color code
                 let color_code = self.color_code;
                 // write the byte to the buffer at the current position
                 // we have to use write method instead of simply assigning value cause the buffer is volatile
-                self.buffer.chars[row][col].write(ScreenChar {
+                self.cell(row, col).write(ScreenChar {
                     ascii_character: byte,
                     color_code,
                 });
@@ -120,31 +196,120 @@ impl Writer {
     /// The new_line method scrolls the buffer by one line.
     fn new_line(&mut self) {
         // iterate over each row in the buffer
-        for row in 1..BUFFER_HEIGHT {
+        for row in 1..self.height {
             // iterate over each column in the buffer
-            for col in 0..BUFFER_WIDTH {
+            for col in 0..self.width {
                 // get the character at the current position
-                let character = self.buffer.chars[row][col].read();
+                let character = self.cell(row, col).read();
                 // write the character to the row above
-                self.buffer.chars[row - 1][col].write(character);
+                self.cell(row - 1, col).write(character);
             }
         }
         // clear the last row   
-        self.clear_row(BUFFER_HEIGHT - 1);
+        self.clear_row(self.height - 1);
         // reset the column position to 0   
         self.column_position = 0;
     }
 
+    /// The write_hex_dump method prints `data` in an xxd-like format, 16 bytes per line:
+    /// `AAAA:BBBBBBBB  XX XX .. XX  ASCII` where AAAA is the line number and
+    /// BBBBBBBB the address of the first byte, `base_addr` plus the offset into `data`.
+    /// Non-printable bytes are shown as `.` in the ASCII column.
+    pub fn write_hex_dump(&mut self, data: &[u8], base_addr: usize) {
+        use core::fmt::Write;
+
+        for (line, chunk) in data.chunks(16).enumerate() {
+            // writing to the VGA buffer cannot fail
+            let _ = write!(self, "{:04x}:{:08x} ", line, base_addr + line * 16);
+            for i in 0..16 {
+                match chunk.get(i) {
+                    Some(byte) => { let _ = write!(self, " {:02x}", byte); }
+                    // pad the last line so the ASCII column stays aligned
+                    None => self.write_string("   "),
+                }
+            }
+            self.write_string("  ");
+            for &byte in chunk {
+                match byte {
+                    0x20..=0x7e => self.write_byte(byte),
+                    _ => self.write_byte(b'.'),
+                }
+            }
+            self.new_line();
+        }
+    }
+
+    /// The write_at_position method writes a byte at the given position without moving the cursor.
+    /// Positions outside of the buffer are ignored.
+    fn write_at_position(&mut self, row: usize, col: usize, byte: u8, color_code: ColorCode) {
+        if row < self.height && col < self.width {
+            self.cell(row, col).write(ScreenChar {
+                ascii_character: byte,
+                color_code,
+            });
+        }
+    }
+
+    /// The draw_line method draws a line of `ch` between two positions with Bresenham's algorithm.
+    /// Positions outside of the buffer are clamped to its last row and column.
+    pub fn draw_line(&mut self, row1: usize, col1: usize, row2: usize, col2: usize, ch: u8, color: ColorCode) {
+        let clamp = |row: usize, col: usize| (row.min(self.height - 1), col.min(self.width - 1));
+        let (row1, col1) = clamp(row1, col1);
+        let (row2, col2) = clamp(row2, col2);
+
+        // horizontal and vertical lines need no error term
+        if row1 == row2 {
+            for col in col1.min(col2)..=col1.max(col2) {
+                self.write_at_position(row1, col, ch, color);
+            }
+            return;
+        }
+        if col1 == col2 {
+            for row in row1.min(row2)..=row1.max(row2) {
+                self.write_at_position(row, col1, ch, color);
+            }
+            return;
+        }
+
+        let (mut row, mut col) = (row1 as isize, col1 as isize);
+        let (row_end, col_end) = (row2 as isize, col2 as isize);
+        let d_col = (col_end - col).abs();
+        let d_row = -(row_end - row).abs();
+        let step_col = if col < col_end { 1 } else { -1 };
+        let step_row = if row < row_end { 1 } else { -1 };
+        // the error term tracks how far the drawn cells are from the exact line
+        let mut error = d_col + d_row;
+        loop {
+            self.write_at_position(row as usize, col as usize, ch, color);
+            if row == row_end && col == col_end {
+                break;
+            }
+            let error2 = 2 * error;
+            if error2 >= d_row {
+                error += d_row;
+                col += step_col;
+            }
+            if error2 <= d_col {
+                error += d_col;
+                row += step_row;
+            }
+        }
+    }
+
+    /// The clear_screen method blanks the whole screen, white on black,
+    /// and continues writing at the start of the last line.
+    pub fn clear_screen(&mut self) {
+        self.buffer.clear_with(ScreenChar::default(), self.height * self.width);
+        self.column_position = 0;
+    }
+
     /// The clear_row method clears a row in the buffer by writing spaces to each column.
     fn clear_row(&mut self, row: usize) {
-        // create a blank character with a space character and the current color code
-        let blank = ScreenChar {
-            ascii_character: b' ',
-            color_code: self.color_code,
-        };
+        // create a blank character with the current color code
+        let blank = ScreenChar::blank(self.color_code);
         // iterate over each column in the row and write the blank character
-        for col in 0..BUFFER_WIDTH {
-            self.buffer.chars[row][col].write(blank);
+        for col in 0..self.width {
+            self.cell(row, col).write(blank);
         }
     }
 }
@@ -160,24 +325,517 @@ impl fmt::Write for Writer {
     }
 }
 
-// lazy_static is a crate that provides a macro for declaring lazily evaluated statics in Rust
-// this allows us to initialize the WRITER static variable with the Writer struct
+use x86_64::instructions::interrupts;
+use crate::collections::string::KernelString;
+use crate::interrupts::ticks;
+use crate::drivers::speaker;
+use crate::time::Duration;
+use crate::io::ports::{VgaCrtcData, VgaCrtcIndex};
+
 // normally static variables are initialized at compile time,
-// so we need to use lazy_static to ensure that the initialization is done when first accessed at runtime   
-use lazy_static::lazy_static;
+// but the raw pointer to the VGA buffer cannot be dereferenced in a const context,
+// so we use a `Once` cell to create the Writer when WRITER is first accessed at runtime
+use crate::sync::Once;
+use core::ops::Deref;
 // the spin crate provides a Mutex type that can be used to safely share mutable data between threads
 // we use Mutex to ensure that the WRITER static variable can be safely accessed from multiple threads
 // we use the concept of a spinlock to implement the Mutex type,
 // which means that the lock is held by spinning in a loop until it can be acquired
 use spin::Mutex;
 
-lazy_static! {
-    /// The WRITER static variable provides a global interface for writing to the VGA buffer.
-    pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
-        column_position: 0,
-        color_code: ColorCode::new(Color::Yellow, Color::Black),
-        buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
-    });
+/// The WRITER static variable provides a global interface for writing to the VGA buffer.
+pub static WRITER: GlobalWriter = GlobalWriter(Once::new());
+
+/// The type of `WRITER`, which derefs to the `Mutex<Writer>` and creates it on first access.
+pub struct GlobalWriter(Once<Mutex<Writer>>);
+
+impl Deref for GlobalWriter {
+    type Target = Mutex<Writer>;
+
+    fn deref(&self) -> &Mutex<Writer> {
+        self.0.call_once(|| Mutex::new(Writer {
+            column_position: 0,
+            color_code: ColorCode::new(Color::Yellow, Color::Black),
+            height: BUFFER_HEIGHT,
+            width: BUFFER_WIDTH,
+            buffer: unsafe { &mut *(0xb8000 as *mut Buffer) },
+        }))
+    }
+}
+
+impl GlobalWriter {
+    /// Returns whether the writer is locked, e.g. by the code that panicked.
+    ///
+    /// The `spin::Mutex` of this version has no such method, so this briefly takes
+    /// the lock if it is free.
+    pub fn is_locked(&self) -> bool {
+        self.try_lock().is_none()
+    }
+}
+
+/// Writes `msg` to the top left corner of the screen in red on white without taking
+/// the `WRITER` lock, for panic handlers that cannot know whether it is held.
+///
+/// Overwrites what is on the screen and assumes the dimensions after boot. Newlines
+/// continue in the next row, the rest of the message is cut off at the end of the screen.
+pub fn emergency_print(msg: &str) {
+    let buffer = 0xb8000 as *mut ScreenChar;
+    let color_code = ColorCode::new(Color::Red, Color::White);
+    let (mut row, mut col) = (0, 0);
+    for byte in msg.bytes() {
+        if byte == b'\n' || col >= BUFFER_WIDTH {
+            row += 1;
+            col = 0;
+        }
+        if row >= BUFFER_HEIGHT {
+            break;
+        }
+        if byte == b'\n' {
+            continue;
+        }
+        let ascii_character = match byte {
+            0x20..=0x7e => byte,
+            _ => 0xfe,
+        };
+        // the VGA buffer is always mapped, a concurrent writer only garbles the output
+        unsafe {
+            buffer.add(row * BUFFER_WIDTH + col).write_volatile(ScreenChar { ascii_character, color_code });
+        }
+        col += 1;
+    }
+}
+
+// code page 437 characters used to draw the progress bar
+const BAR_FRAME: u8 = 0xb3; // │
+const BAR_FILLED: u8 = 0xdb; // █
+const BAR_EMPTY: u8 = 0xb0; // ░
+
+/// The ProgressBar struct draws a progress bar at a fixed position of the screen,
+/// e.g. to give feedback during long initialization steps.
+/// The width includes the two frame characters at both ends.
+pub struct ProgressBar {
+    row: usize,
+    col: usize,
+    width: usize,
+    total: u64,
+    current: u64,
+    color: ColorCode,
+}
+
+impl ProgressBar {
+    /// Creates an empty progress bar for `total` steps and draws it.
+    pub fn new(row: usize, col: usize, width: usize, total: u64) -> ProgressBar {
+        let bar = ProgressBar {
+            row,
+            col,
+            width: width.max(2),
+            total,
+            current: 0,
+            color: ColorCode::new(Color::LightGreen, Color::Black),
+        };
+        bar.draw();
+        bar
+    }
+
+    /// Advances the bar by `n` steps and redraws it.
+    pub fn advance(&mut self, n: u64) {
+        self.current = self.total.min(self.current + n);
+        self.draw();
+    }
+
+    /// Fills the bar and prints "[done]" behind it.
+    pub fn finish(&mut self) {
+        self.current = self.total;
+        self.draw();
+        interrupts::without_interrupts(|| {
+            let mut writer = WRITER.lock();
+            for (i, byte) in b" [done]".iter().enumerate() {
+                writer.write_at_position(self.row, self.col + self.width + i, *byte, self.color);
+            }
+        });
+    }
+
+    fn draw(&self) {
+        let inner = self.width - 2;
+        let filled = match self.total {
+            0 => inner,
+            total => (inner as u64 * self.current / total) as usize,
+        };
+
+        interrupts::without_interrupts(|| {
+            let mut writer = WRITER.lock();
+            writer.write_at_position(self.row, self.col, BAR_FRAME, self.color);
+            for i in 0..inner {
+                let byte = if i < filled { BAR_FILLED } else { BAR_EMPTY };
+                writer.write_at_position(self.row, self.col + 1 + i, byte, self.color);
+            }
+            writer.write_at_position(self.row, self.col + self.width - 1, BAR_FRAME, self.color);
+        });
+    }
+}
+
+// the characters the spinner cycles through
+const SPINNER_FRAMES: [u8; 4] = *b"|/-\\";
+// the spinner advances at most once per this many timer ticks, about one second
+const SPINNER_INTERVAL_TICKS: u64 = 18;
+
+/// The Spinner struct draws a single animated character at a fixed position of the screen,
+/// e.g. to show that a loop without visible output is still making progress.
+/// It is driven by calling `update` and needs no separate task.
+pub struct Spinner {
+    row: usize,
+    col: usize,
+    frame: u8,
+    last_tick: u64,
+}
+
+impl Spinner {
+    /// Creates a spinner and draws its first frame.
+    pub fn new(row: usize, col: usize) -> Spinner {
+        let spinner = Spinner {
+            row,
+            col,
+            frame: 0,
+            last_tick: ticks(),
+        };
+        spinner.draw();
+        spinner
+    }
+
+    /// Advances to the next frame if enough timer ticks have passed since the last one.
+    pub fn update(&mut self) {
+        let now = ticks();
+        if now.wrapping_sub(self.last_tick) >= SPINNER_INTERVAL_TICKS {
+            self.last_tick = now;
+            self.frame = (self.frame + 1) % SPINNER_FRAMES.len() as u8;
+            self.draw();
+        }
+    }
+
+    /// Draws the current frame in the writer's current color.
+    pub fn draw(&self) {
+        interrupts::without_interrupts(|| {
+            let mut writer = WRITER.lock();
+            let color = writer.color_code;
+            writer.write_at_position(self.row, self.col, SPINNER_FRAMES[self.frame as usize], color);
+        });
+    }
+}
+
+// the CRT controller registers of the hardware cursor
+const CRTC_CURSOR_START: u8 = 0x0A;
+const CRTC_CURSOR_LOCATION_HIGH: u8 = 0x0E;
+const CRTC_CURSOR_LOCATION_LOW: u8 = 0x0F;
+// bit 5 of the cursor start register hides the cursor
+const CRTC_CURSOR_DISABLE: u8 = 1 << 5;
+
+// the bell is a short beep, the timer interrupt ends it
+const BELL_FREQUENCY_HZ: u32 = 880;
+const BELL_DURATION_MS: u64 = 50;
+
+// CSI sequences with more parameters than this only keep the first ones
+const MAX_CSI_PARAMS: usize = 8;
+
+// the VGA colors of the ANSI color numbers 0-7, the bright ones add 8
+const ANSI_COLORS: [Color; 8] = [
+    Color::Black,
+    Color::Red,
+    Color::Green,
+    Color::Brown,
+    Color::Blue,
+    Color::Magenta,
+    Color::Cyan,
+    Color::LightGray,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TerminalState {
+    /// printing characters
+    Ground,
+    /// after an ESC
+    Escape,
+    /// after `ESC[`, collecting parameters until the final byte
+    Csi,
+}
+
+/// The TerminalEmulator struct interprets VT100 control characters and escape sequences
+/// on top of a `Writer`, e.g. for output coming from a serial console or a shell.
+///
+/// Unlike the writer, which always writes to the last line, it keeps a cursor that can be
+/// anywhere on the screen. It supports `\r`, `\b`, the bell `\x07`, cursor movement
+/// (`ESC[nA/B/C/D`, `ESC[r;cH`), `ESC[K` and `ESC[J`, saving and restoring the cursor
+/// (`ESC[s`/`ESC[u` and `ESC7`/`ESC8`), showing and hiding the hardware cursor
+/// (`ESC[?25h`/`ESC[?25l`) and SGR colors with any number of parameters, e.g. `ESC[1;37;44m`.
+/// Unsupported sequences are consumed without output.
+///
+/// SGR colors are set on the writer, so they stay in effect after the emulator is dropped
+/// until `ESC[0m` resets them to the color the writer had when the emulator was created.
+pub struct TerminalEmulator<'a> {
+    writer: &'a mut Writer,
+    state: TerminalState,
+    params: [u16; MAX_CSI_PARAMS],
+    param_count: usize,
+    private: bool,
+    row: usize,
+    col: usize,
+    saved_cursor: (usize, usize),
+    cursor_visible: bool,
+    default_color: ColorCode,
+}
+
+impl<'a> TerminalEmulator<'a> {
+    /// Creates an emulator whose cursor starts where the writer would write next.
+    pub fn new(writer: &'a mut Writer) -> TerminalEmulator<'a> {
+        let row = writer.height - 1;
+        let col = writer.column_position;
+        let default_color = writer.color_code;
+        TerminalEmulator {
+            writer,
+            state: TerminalState::Ground,
+            params: [0; MAX_CSI_PARAMS],
+            param_count: 0,
+            private: false,
+            row,
+            col,
+            saved_cursor: (row, col),
+            cursor_visible: true,
+            default_color,
+        }
+    }
+
+    /// The row and column the next character is written to.
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.row, self.col)
+    }
+
+    /// Whether the hardware cursor was last shown or hidden with `ESC[?25h`/`ESC[?25l`.
+    pub fn cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    /// The write_bytes method feeds `bytes` through the emulator and then moves the hardware
+    /// cursor to the new position. Escape sequences may be split across calls.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_byte(byte);
+        }
+        // a plain `print!` continues in the emulator's column
+        self.writer.column_position = self.col;
+        if self.cursor_visible {
+            self.update_hardware_cursor();
+        }
+    }
+
+    /// The write_byte method feeds a single byte through the escape sequence state machine.
+    pub fn write_byte(&mut self, byte: u8) {
+        match self.state {
+            TerminalState::Ground => match byte {
+                0x1b => self.state = TerminalState::Escape,
+                b'\n' => self.line_feed(),
+                b'\r' => self.col = 0,
+                0x08 => self.col = self.col.min(self.writer.width - 1).saturating_sub(1),
+                0x07 => bell(),
+                0x20..=0x7e => self.put(byte),
+                _ => self.put(0xfe),
+            },
+            TerminalState::Escape => match byte {
+                b'[' => {
+                    self.params = [0; MAX_CSI_PARAMS];
+                    self.param_count = 0;
+                    self.private = false;
+                    self.state = TerminalState::Csi;
+                }
+                b'7' => {
+                    self.saved_cursor = (self.row, self.col);
+                    self.state = TerminalState::Ground;
+                }
+                b'8' => {
+                    (self.row, self.col) = self.saved_cursor;
+                    self.state = TerminalState::Ground;
+                }
+                _ => self.state = TerminalState::Ground,
+            },
+            TerminalState::Csi => match byte {
+                b'0'..=b'9' => {
+                    if self.param_count == 0 {
+                        self.param_count = 1;
+                    }
+                    if let Some(param) = self.params.get_mut(self.param_count - 1) {
+                        *param = param.saturating_mul(10).saturating_add((byte - b'0') as u16);
+                    }
+                }
+                b';' => {
+                    // an empty first parameter still counts, e.g. `ESC[;5H`
+                    self.param_count = self.param_count.max(1).saturating_add(1);
+                }
+                b'?' => self.private = true,
+                0x40..=0x7e => {
+                    self.state = TerminalState::Ground;
+                    self.dispatch_csi(byte);
+                }
+                // intermediate bytes, none of the supported sequences use them
+                _ => {}
+            },
+        }
+    }
+
+    /// The CSI parameters that were stored.
+    fn params(&self) -> &[u16] {
+        &self.params[..self.param_count.min(MAX_CSI_PARAMS)]
+    }
+
+    /// The parameter at `index`, where a missing or zero parameter means `default`.
+    fn param_or(&self, index: usize, default: u16) -> usize {
+        match self.params().get(index) {
+            Some(&value) if value != 0 => value as usize,
+            _ => default as usize,
+        }
+    }
+
+    fn dispatch_csi(&mut self, final_byte: u8) {
+        let (height, width) = self.writer.dimensions();
+        if self.private {
+            if (final_byte == b'h' || final_byte == b'l') && self.params().contains(&25) {
+                self.set_cursor_visible(final_byte == b'h');
+            }
+            return;
+        }
+        match final_byte {
+            b'A' => self.row = self.row.saturating_sub(self.param_or(0, 1)),
+            b'B' => self.row = (self.row + self.param_or(0, 1)).min(height - 1),
+            b'C' => self.col = (self.col + self.param_or(0, 1)).min(width - 1),
+            b'D' => self.col = self.col.min(width - 1).saturating_sub(self.param_or(0, 1)),
+            b'H' | b'f' => {
+                self.row = (self.param_or(0, 1) - 1).min(height - 1);
+                self.col = (self.param_or(1, 1) - 1).min(width - 1);
+            }
+            b'J' => match self.param_or(0, 0) {
+                0 => {
+                    self.erase(self.row, self.col, width);
+                    for row in self.row + 1..height {
+                        self.erase(row, 0, width);
+                    }
+                }
+                1 => {
+                    for row in 0..self.row {
+                        self.erase(row, 0, width);
+                    }
+                    self.erase(self.row, 0, self.col + 1);
+                }
+                2 => {
+                    for row in 0..height {
+                        self.erase(row, 0, width);
+                    }
+                }
+                _ => {}
+            },
+            b'K' => match self.param_or(0, 0) {
+                0 => self.erase(self.row, self.col, width),
+                1 => self.erase(self.row, 0, self.col + 1),
+                2 => self.erase(self.row, 0, width),
+                _ => {}
+            },
+            b'm' => self.select_graphic_rendition(),
+            b's' => self.saved_cursor = (self.row, self.col),
+            b'u' => (self.row, self.col) = self.saved_cursor,
+            _ => {}
+        }
+    }
+
+    /// Applies all SGR parameters in order, no parameters means a reset.
+    fn select_graphic_rendition(&mut self) {
+        let default_foreground = self.default_color.0 & 0x0f;
+        let default_background = self.default_color.0 >> 4;
+        let mut foreground = self.writer.color_code.0 & 0x0f;
+        let mut background = self.writer.color_code.0 >> 4;
+        let count = self.params().len().max(1);
+        for index in 0..count {
+            match self.params().get(index).copied().unwrap_or(0) {
+                0 => {
+                    foreground = default_foreground;
+                    background = default_background;
+                }
+                // bold is shown as the bright variant of the foreground color
+                1 => foreground |= 0x08,
+                22 => foreground &= !0x08,
+                code @ 30..=37 => foreground = ANSI_COLORS[(code - 30) as usize] as u8 | (foreground & 0x08),
+                39 => foreground = default_foreground,
+                code @ 40..=47 => background = ANSI_COLORS[(code - 40) as usize] as u8,
+                49 => background = default_background,
+                code @ 90..=97 => foreground = ANSI_COLORS[(code - 90) as usize] as u8 | 0x08,
+                code @ 100..=107 => background = ANSI_COLORS[(code - 100) as usize] as u8 | 0x08,
+                _ => {}
+            }
+        }
+        self.writer.color_code = ColorCode(background << 4 | foreground);
+    }
+
+    /// Writes a character at the cursor, wrapping to the next line first if the current one is full.
+    fn put(&mut self, byte: u8) {
+        if self.col >= self.writer.width {
+            self.line_feed();
+        }
+        let color_code = self.writer.color_code;
+        self.writer.write_at_position(self.row, self.col, byte, color_code);
+        self.col += 1;
+    }
+
+    /// Moves to the start of the next line, scrolling if the cursor is on the last one.
+    fn line_feed(&mut self) {
+        if self.row + 1 < self.writer.height {
+            self.row += 1;
+        } else {
+            self.writer.new_line();
+        }
+        self.col = 0;
+    }
+
+    /// Blanks the columns `from..to` of `row` in the current color.
+    fn erase(&mut self, row: usize, from: usize, to: usize) {
+        let blank = ScreenChar::blank(self.writer.color_code);
+        for col in from..to.min(self.writer.width) {
+            self.writer.cell(row, col).write(blank);
+        }
+    }
+
+    fn set_cursor_visible(&mut self, visible: bool) {
+        self.cursor_visible = visible;
+        let mut index = VgaCrtcIndex::new();
+        let mut data = VgaCrtcData::new();
+        index.write(CRTC_CURSOR_START);
+        let start = data.read();
+        let start = if visible {
+            start & !CRTC_CURSOR_DISABLE
+        } else {
+            start | CRTC_CURSOR_DISABLE
+        };
+        data.write(start);
+        if visible {
+            self.update_hardware_cursor();
+        }
+    }
+
+    fn update_hardware_cursor(&self) {
+        let position = (self.row * self.writer.width + self.col.min(self.writer.width - 1)) as u16;
+        let mut index = VgaCrtcIndex::new();
+        let mut data = VgaCrtcData::new();
+        index.write(CRTC_CURSOR_LOCATION_LOW);
+        data.write(position as u8);
+        index.write(CRTC_CURSOR_LOCATION_HIGH);
+        data.write((position >> 8) as u8);
+    }
+}
+
+impl fmt::Write for TerminalEmulator<'_> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Rings the bell on the PC speaker without waiting for it to end.
+fn bell() {
+    speaker::start_beep(BELL_FREQUENCY_HZ, Duration::from_ms(BELL_DURATION_MS));
 }
 
 /// Like the `print!` macro in the standard library, but prints to the VGA text buffer.
@@ -197,14 +855,78 @@ macro_rules! println {
 #[doc(hidden)]
 pub fn _print(args: fmt::Arguments) {
     use core::fmt::Write;
-    use x86_64::instructions::interrupts;
 
     // the following can cause a deadlock if an interrupt occurs while holding the lock
     // WRITER.lock().write_fmt(args).unwrap();
     // to overcome the deadlock, 
     // we disable interrupts before acquiring the lock and re-enable them after releasing the lock
     interrupts::without_interrupts(|| {
+        #[cfg(not(feature = "timestamped_log"))]
         WRITER.lock().write_fmt(args).unwrap();
+        #[cfg(feature = "timestamped_log")]
+        {
+            static AT_LINE_START: core::sync::atomic::AtomicBool = core::sync::atomic::AtomicBool::new(true);
+            let mut writer = WRITER.lock();
+            crate::time::TimestampWriter::new(&AT_LINE_START, |s| writer.write_string(s))
+                .write_fmt(args)
+                .unwrap();
+        }
+    });
+}
+
+/// Like `print!`, but never waits for the `WRITER` lock, so it can be used in interrupt handlers.
+/// If the lock is held, the output is kept in `INTERRUPT_PRINT_BUFFER` until
+/// `drain_interrupt_print_buffer` is called.
+#[macro_export]
+macro_rules! print_from_interrupt {
+    ($($arg:tt)*) => ($crate::vga_buffer::_print_from_interrupt(format_args!($($arg)*)));
+}
+
+/// Like `println!`, but never waits for the `WRITER` lock, see `print_from_interrupt!`.
+#[macro_export]
+macro_rules! println_from_interrupt {
+    () => ($crate::print_from_interrupt!("\n"));
+    ($($arg:tt)*) => ($crate::print_from_interrupt!("{}\n", format_args!($($arg)*)));
+}
+
+/// Output of interrupt handlers that could not be written because `WRITER` was locked.
+/// Output that does not fit is dropped.
+pub static INTERRUPT_PRINT_BUFFER: Mutex<KernelString<256>> = Mutex::new(KernelString::new());
+
+/// Prints through `WRITER` if it is not locked, otherwise appends to `INTERRUPT_PRINT_BUFFER`.
+#[doc(hidden)]
+pub fn _print_from_interrupt(args: fmt::Arguments) {
+    use core::fmt::Write;
+
+    interrupts::without_interrupts(|| {
+        // nothing else can hold the buffer lock while interrupts are disabled
+        let mut buffer = match INTERRUPT_PRINT_BUFFER.try_lock() {
+            Some(buffer) => buffer,
+            None => return,
+        };
+        match WRITER.try_lock() {
+            Some(mut writer) => {
+                // keep the order of the output
+                writer.write_string(buffer.as_str());
+                buffer.clear();
+                let _ = writer.write_fmt(args);
+            }
+            None => {
+                let _ = buffer.write_fmt(args);
+            }
+        }
+    });
+}
+
+/// Writes the output buffered by `print_from_interrupt!` to the screen.
+/// Called by the executor when it is idle.
+pub fn drain_interrupt_print_buffer() {
+    interrupts::without_interrupts(|| {
+        let mut buffer = INTERRUPT_PRINT_BUFFER.lock();
+        if !buffer.is_empty() {
+            WRITER.lock().write_string(buffer.as_str());
+            buffer.clear();
+        }
     });
 }
 
@@ -227,7 +949,6 @@ fn test_println_many() {
 #[test_case]
 fn test_println_output() {
     use core::fmt::Write;
-    use x86_64::instructions::interrupts;
 
     let s = "Some test string that fits on a single line";
     // disable interrupts to prevent deadlock
@@ -238,9 +959,193 @@ fn test_println_output() {
         writeln!(writer, "\n{}", s).expect("writeln failed");
         // check that the string was written correctly
         for (i, c) in s.chars().enumerate() {
-            let screen_char = writer.buffer.chars[BUFFER_HEIGHT - 2][i].read();
+            let screen_char = writer.cell(BUFFER_HEIGHT - 2, i).read();
             // check that the character in the buffer matches the character in the string
             assert_eq!(char::from(screen_char.ascii_character), c);
         }
     });
-}
\ No newline at end of file
+}
+// test that a half full progress bar draws the expected characters
+#[test_case]
+fn test_progress_bar_half() {
+    interrupts::without_interrupts(|| {
+        let mut bar = ProgressBar::new(5, 10, 12, 4);
+        bar.advance(2);
+
+        let mut writer = WRITER.lock();
+        let row: [u8; 12] = core::array::from_fn(|i| writer.cell(5, 10 + i).read().ascii_character);
+        assert_eq!(row[0], BAR_FRAME);
+        assert!(row[1..6].iter().all(|&c| c == BAR_FILLED));
+        assert!(row[6..11].iter().all(|&c| c == BAR_EMPTY));
+        assert_eq!(row[11], BAR_FRAME);
+    });
+}
+
+// test the layout of a hex dump with a partial last line
+#[test_case]
+fn test_write_hex_dump() {
+    let data = *b"Hello, hex dump!\x00\x01\xff";
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writer.write_byte(b'\n');
+        writer.write_hex_dump(&data, 0x1000);
+
+        let mut read_row = |row: usize| -> [u8; BUFFER_WIDTH] {
+            core::array::from_fn(|col| writer.cell(row, col).read().ascii_character)
+        };
+        let first = read_row(BUFFER_HEIGHT - 3);
+        let expected = b"0000:00001000  48 65 6c 6c 6f 2c 20 68 65 78 20 64 75 6d 70 21  Hello, hex dump!";
+        assert_eq!(&first[..expected.len()], &expected[..]);
+
+        let second = read_row(BUFFER_HEIGHT - 2);
+        let expected = b"0001:00001010  00 01 ff";
+        assert_eq!(&second[..expected.len()], &expected[..]);
+        // the ASCII column starts after the padded hex column
+        assert_eq!(&second[64..67], b"...");
+    });
+}
+
+#[test_case]
+fn test_spinner_advances_after_interval() {
+    interrupts::without_interrupts(|| {
+        let read = || WRITER.lock().cell(0, BUFFER_WIDTH - 1).read().ascii_character;
+        let mut spinner = Spinner::new(0, BUFFER_WIDTH - 1);
+        assert_eq!(read(), b'|');
+
+        // no tick can happen with interrupts disabled
+        spinner.update();
+        assert_eq!(read(), b'|');
+
+        // pretend the last frame was drawn a full interval ago
+        spinner.last_tick = ticks().wrapping_sub(SPINNER_INTERVAL_TICKS);
+        spinner.update();
+        assert_eq!(read(), b'/');
+    });
+}
+
+#[test_case]
+fn test_resize() {
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        assert_eq!(writer.resize(MAX_BUFFER_HEIGHT + 1, BUFFER_WIDTH),
+            Err(VgaError::InvalidDimensions { height: MAX_BUFFER_HEIGHT + 1, width: BUFFER_WIDTH }));
+        assert_eq!(writer.resize(BUFFER_HEIGHT, 0),
+            Err(VgaError::InvalidDimensions { height: BUFFER_HEIGHT, width: 0 }));
+
+        // a smaller mode stays within the mapped part of the buffer
+        writer.resize(20, 40).unwrap();
+        assert_eq!(writer.dimensions(), (20, 40));
+        writer.write_string("abc\n");
+        assert_eq!(writer.cell(18, 0).read().ascii_character, b'a');
+        // rows are 40 characters apart now
+        assert_eq!(writer.buffer.chars[18 * 40 + 2].read().ascii_character, b'c');
+
+        writer.set_buffer_height_runtime(BUFFER_HEIGHT).unwrap();
+        writer.resize(BUFFER_HEIGHT, BUFFER_WIDTH).unwrap();
+        assert_eq!(writer.dimensions(), (BUFFER_HEIGHT, BUFFER_WIDTH));
+    });
+}
+
+#[test_case]
+fn test_draw_line() {
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        let color = ColorCode::new(Color::White, Color::Black);
+        for row in 2..12 {
+            writer.clear_row(row);
+        }
+        writer.draw_line(2, 0, 5, 3, b'*', color);
+        for i in 0..4 {
+            assert_eq!(writer.cell(2 + i, i).read().ascii_character, b'*');
+        }
+        assert_ne!(writer.cell(2, 1).read().ascii_character, b'*');
+
+        // clamped to the last column
+        writer.draw_line(7, 70, 7, 1000, b'-', color);
+        assert!((70..BUFFER_WIDTH).all(|col| writer.cell(7, col).read().ascii_character == b'-'));
+
+        // a shallow line has exactly one cell per column
+        writer.draw_line(10, 0, 11, 9, b'#', color);
+        for col in 0..10 {
+            let hits = (10..12).filter(|&row| writer.cell(row, col).read().ascii_character == b'#').count();
+            assert_eq!(hits, 1);
+        }
+    });
+}
+
+#[test_case]
+fn test_print_from_interrupt_buffers_while_locked() {
+    interrupts::without_interrupts(|| {
+        drain_interrupt_print_buffer();
+        {
+            let _writer = WRITER.lock();
+            crate::println_from_interrupt!("buffered");
+        }
+        assert_eq!(INTERRUPT_PRINT_BUFFER.lock().as_str(), "buffered\n");
+
+        drain_interrupt_print_buffer();
+        assert!(INTERRUPT_PRINT_BUFFER.lock().is_empty());
+        let mut writer = WRITER.lock();
+        let row: [u8; 8] = core::array::from_fn(|col| writer.cell(BUFFER_HEIGHT - 2, col).read().ascii_character);
+        assert_eq!(&row, b"buffered");
+    });
+}
+
+#[test_case]
+fn test_emergency_print_while_locked() {
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        assert!(WRITER.is_locked());
+        emergency_print("PANIC\n!");
+        let color_code = ColorCode::new(Color::Red, Color::White);
+        for (i, &byte) in b"PANIC".iter().enumerate() {
+            assert_eq!(writer.cell(0, i).read(), ScreenChar { ascii_character: byte, color_code });
+        }
+        assert_eq!(writer.cell(1, 0).read(), ScreenChar { ascii_character: b'!', color_code });
+    });
+    assert!(!WRITER.is_locked());
+}
+
+#[test_case]
+fn test_clear_screen() {
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        writer.write_string("to be cleared\n");
+        writer.clear_screen();
+        let (height, width) = writer.dimensions();
+        for row in [0, height - 2, height - 1] {
+            for col in [0, width - 1] {
+                assert_eq!(writer.cell(row, col).read(), ScreenChar::default());
+            }
+        }
+    });
+}
+
+#[test_case]
+fn test_terminal_emulator() {
+    interrupts::without_interrupts(|| {
+        let mut writer = WRITER.lock();
+        let default_color = writer.color_code;
+        let mut terminal = TerminalEmulator::new(&mut writer);
+        terminal.write_bytes(b"\x1b[2J\x1b[3;5Habc");
+        assert_eq!(terminal.cursor(), (2, 7));
+        // multi-parameter SGR, bold turns red into light red
+        terminal.write_bytes(b"\x1b[1;31;44mx\x1b[0m");
+        assert_eq!(terminal.writer.cell(2, 7).read().color_code, ColorCode::new(Color::LightRed, Color::Blue));
+        assert_eq!(terminal.writer.color_code, default_color);
+        // save, move away, restore, then back up over "cx" and erase to the end of the line
+        terminal.write_bytes(b"\x1b[s\x1b[H\x1b[u\x08\x08\x1b[K");
+        assert_eq!(terminal.cursor(), (2, 6));
+        assert_eq!(terminal.writer.cell(2, 5).read().ascii_character, b'b');
+        assert_eq!(terminal.writer.cell(2, 6).read(), ScreenChar::blank(default_color));
+        terminal.write_bytes(b"\rz");
+        assert_eq!(terminal.writer.cell(2, 0).read().ascii_character, b'z');
+        // an escape sequence split across writes
+        terminal.write_bytes(b"\x1b[?2");
+        terminal.write_bytes(b"5l");
+        assert!(!terminal.cursor_visible());
+        terminal.write_bytes(b"\x1b[?25h");
+        assert!(terminal.cursor_visible());
+        writer.clear_screen();
+    });
+}
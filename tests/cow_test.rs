@@ -0,0 +1,139 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(turiya::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use spin::Mutex;
+use turiya::memory::{self, BootInfoFrameAllocator, ContiguousFrameAllocator, COPY_ON_WRITE};
+use x86_64::structures::paging::{
+    mapper::{MappedFrame, TranslateResult}, FrameAllocator, FrameDeallocator, OffsetPageTable, Page,
+    PageTable, PageTableFlags, PhysFrame, Size4KiB, Translate,
+};
+use x86_64::VirtAddr;
+
+// an unused address for the pages of the tests
+const TEST_PAGE: u64 = 0x_6666_0000_0000;
+
+static MAPPER: Mutex<Option<OffsetPageTable<'static>>> = Mutex::new(None);
+static FRAMES: Mutex<Option<BootInfoFrameAllocator>> = Mutex::new(None);
+static PHYS_MEM_OFFSET: Mutex<VirtAddr> = Mutex::new(VirtAddr::zero());
+
+// the page fault handler and the tests both allocate from `FRAMES`
+struct SharedFrames;
+
+unsafe impl FrameAllocator<Size4KiB> for SharedFrames {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        FRAMES.lock().as_mut()?.allocate_frame()
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for SharedFrames {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        if let Some(frames) = FRAMES.lock().as_mut() {
+            frames.deallocate_frame(frame);
+        }
+    }
+}
+
+impl ContiguousFrameAllocator for SharedFrames {
+    fn allocate_contiguous(&mut self, count: usize) -> Option<PhysFrame> {
+        FRAMES.lock().as_mut()?.allocate_contiguous(count)
+    }
+}
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use turiya::allocator;
+
+    turiya::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BootInfoFrameAllocator::init(&boot_info.memory_map)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    *MAPPER.lock() = Some(mapper);
+    *FRAMES.lock() = Some(frame_allocator);
+    *PHYS_MEM_OFFSET.lock() = phys_mem_offset;
+    memory::cow::init(phys_mem_offset, SharedFrames);
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    turiya::test_panic_handler(info)
+}
+
+fn translate(mapper: &OffsetPageTable, addr: VirtAddr) -> (PhysFrame, PageTableFlags) {
+    match mapper.translate(addr) {
+        TranslateResult::Mapped { frame: MappedFrame::Size4KiB(frame), flags, .. } => (frame, flags),
+        _ => panic!("{:?} is not mapped", addr),
+    }
+}
+
+#[test_case]
+fn write_copies_marked_page() {
+    let addr = VirtAddr::new(TEST_PAGE);
+    let page = Page::<Size4KiB>::containing_address(addr);
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    let ptr = addr.as_mut_ptr::<u64>();
+
+    let original = {
+        let mut mapper = MAPPER.lock();
+        let mapper = mapper.as_mut().unwrap();
+        memory::map_page_range(addr, 1, flags, mapper, &mut SharedFrames)
+            .expect("mapping the test page failed");
+        unsafe { ptr.write_volatile(42) };
+        memory::mark_copy_on_write(page, mapper).unwrap();
+        translate(mapper, addr)
+    };
+    assert!(original.1.contains(COPY_ON_WRITE));
+    assert!(!original.1.contains(PageTableFlags::WRITABLE));
+
+    // raises a page fault that copies the page
+    unsafe { ptr.write_volatile(43) };
+    assert_eq!(unsafe { ptr.read_volatile() }, 43);
+
+    let (frame, flags) = translate(MAPPER.lock().as_ref().unwrap(), addr);
+    assert_ne!(frame, original.0);
+    assert!(flags.contains(PageTableFlags::WRITABLE));
+    assert!(!flags.contains(COPY_ON_WRITE));
+    // the old frame is unchanged
+    let old = *PHYS_MEM_OFFSET.lock() + original.0.start_address().as_u64();
+    assert_eq!(unsafe { old.as_ptr::<u64>().read_volatile() }, 42);
+}
+
+#[test_case]
+fn shared_page_is_read_only() {
+    let offset = *PHYS_MEM_OFFSET.lock();
+    let addr = VirtAddr::new(TEST_PAGE + 0x1000);
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    let mut mapper = MAPPER.lock();
+    let mapper = mapper.as_mut().unwrap();
+    memory::map_page_range(addr, 1, flags, mapper, &mut SharedFrames)
+        .expect("mapping the test page failed");
+
+    // an empty page table standing in for the one of a forked process
+    let table_frame = SharedFrames.allocate_frame().unwrap();
+    let table = (offset + table_frame.start_address().as_u64()).as_mut_ptr::<PageTable>();
+    let mut dst = unsafe {
+        table.write(PageTable::new());
+        OffsetPageTable::new(&mut *table, offset)
+    };
+    memory::share_page(addr, &mut dst, &mut SharedFrames).unwrap();
+
+    let (frame, flags) = translate(&dst, addr);
+    assert_eq!(frame, translate(mapper, addr).0);
+    assert!(flags.contains(COPY_ON_WRITE));
+    assert!(!flags.contains(PageTableFlags::WRITABLE));
+}
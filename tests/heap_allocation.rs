@@ -10,7 +10,11 @@ use bootloader::{entry_point, BootInfo};
 use core::panic::PanicInfo;
 use alloc::boxed::Box;
 use alloc::vec::Vec;
-use turiya::allocator::HEAP_SIZE;
+use spin::Mutex;
+use turiya::allocator::{HEAP_SIZE, HEAP_START};
+use turiya::memory::KernelHeapInfo;
+
+static HEAP_INFO: Mutex<Option<KernelHeapInfo>> = Mutex::new(None);
 
 entry_point!(main);
 
@@ -25,8 +29,9 @@ fn main(boot_info: &'static BootInfo) -> ! {
     let mut frame_allocator = unsafe {
         BootInfoFrameAllocator::init(&boot_info.memory_map)
     };
-    allocator::init_heap(&mut mapper, &mut frame_allocator)
+    let heap = allocator::init_heap(&mut mapper, &mut frame_allocator)
         .expect("heap initialization failed");  
+    *HEAP_INFO.lock() = Some(heap);
 
     test_main();
     loop {}
@@ -37,6 +42,16 @@ fn panic(info: &PanicInfo) -> ! {
     turiya::test_panic_handler(info)
 }
 
+#[test_case]
+fn heap_info() {
+    let heap = HEAP_INFO.lock().expect("heap info missing");
+    assert_eq!(heap.start.as_u64(), HEAP_START as u64);
+    assert_eq!(heap.size, HEAP_SIZE);
+    assert_eq!(heap.page_count, HEAP_SIZE / 4096);
+    // new page tables take frames as well
+    assert!(heap.phys_frames_used >= heap.page_count);
+}
+
 #[test_case]
 fn simple_allocation() {
     let heap_value_1 = Box::new(41);
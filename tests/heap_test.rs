@@ -0,0 +1,96 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(turiya::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+extern crate alloc;
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    use turiya::allocator;
+    use turiya::memory::{self, BootInfoFrameAllocator};
+    use x86_64::VirtAddr;
+
+    turiya::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    let mut mapper = unsafe { memory::init(phys_mem_offset) };
+    let mut frame_allocator = unsafe {
+        BootInfoFrameAllocator::init(&boot_info.memory_map)
+    };
+    allocator::init_heap(&mut mapper, &mut frame_allocator)
+        .expect("heap initialization failed");
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    turiya::test_panic_handler(info)
+}
+
+// the kernel uses the fixed size block allocator, which puts freed blocks
+// on a free list and hands them out again right away
+#[test_case]
+fn freed_blocks_are_reused() {
+    let n = 1000;
+    let boxes: Vec<Box<u64>> = (0..n).map(Box::new).collect();
+    for (i, value) in boxes.iter().enumerate() {
+        assert_eq!(**value, i as u64);
+    }
+    let mut addresses: Vec<usize> = boxes.iter()
+        .map(|value| &**value as *const u64 as usize)
+        .collect();
+    addresses.sort_unstable();
+    drop(boxes);
+
+    let boxes: Vec<Box<u64>> = (0..n).map(|i| Box::new(i * 2)).collect();
+    for (i, value) in boxes.iter().enumerate() {
+        assert_eq!(**value, i as u64 * 2);
+        let addr = &**value as *const u64 as usize;
+        assert!(addresses.binary_search(&addr).is_ok(), "block {:#x} was not reused", addr);
+    }
+}
+
+// one allocation for each size in the allocator's `BLOCK_SIZES` up to 256 bytes
+#[test_case]
+fn block_sizes_do_not_overlap() {
+    let allocations: [(usize, usize); 6] = [
+        (Box::into_raw(Box::new([0x08u8; 8])) as usize, 8),
+        (Box::into_raw(Box::new([0x10u8; 16])) as usize, 16),
+        (Box::into_raw(Box::new([0x20u8; 32])) as usize, 32),
+        (Box::into_raw(Box::new([0x40u8; 64])) as usize, 64),
+        (Box::into_raw(Box::new([0x80u8; 128])) as usize, 128),
+        (Box::into_raw(Box::new([0xffu8; 256])) as usize, 256),
+    ];
+
+    for (i, &(start, size)) in allocations.iter().enumerate() {
+        for &(other_start, other_size) in &allocations[i + 1..] {
+            assert!(start + size <= other_start || other_start + other_size <= start,
+                "allocations at {:#x} and {:#x} overlap", start, other_start);
+        }
+    }
+
+    // writing one block must not have touched the others
+    for &(start, size) in &allocations {
+        let fill = if size == 256 { 0xff } else { size as u8 };
+        let bytes = unsafe { core::slice::from_raw_parts(start as *const u8, size) };
+        assert!(bytes.iter().all(|&byte| byte == fill));
+    }
+
+    unsafe {
+        drop(Box::from_raw(allocations[0].0 as *mut [u8; 8]));
+        drop(Box::from_raw(allocations[1].0 as *mut [u8; 16]));
+        drop(Box::from_raw(allocations[2].0 as *mut [u8; 32]));
+        drop(Box::from_raw(allocations[3].0 as *mut [u8; 64]));
+        drop(Box::from_raw(allocations[4].0 as *mut [u8; 128]));
+        drop(Box::from_raw(allocations[5].0 as *mut [u8; 256]));
+    }
+}
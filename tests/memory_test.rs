@@ -0,0 +1,200 @@
+#![no_std]
+#![no_main]
+#![feature(custom_test_frameworks)]
+#![test_runner(turiya::test_runner)]
+#![reexport_test_harness_main = "test_main"]
+
+use bootloader::{entry_point, BootInfo};
+use core::panic::PanicInfo;
+use spin::Mutex;
+use turiya::memory::{self, BootInfoFrameAllocator, ContiguousFrameAllocator, KernelStack, MmioError};
+use turiya::vga_buffer::{BUFFER_HEIGHT, BUFFER_WIDTH};
+use x86_64::structures::paging::{
+    FrameAllocator, FrameDeallocator, Mapper, OffsetPageTable, Page, PageTableFlags, PhysFrame, Size4KiB,
+    Translate,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+// an unused address for the page of the tests
+const TEST_PAGE: u64 = 0x_6666_1000_0000;
+
+static MAPPER: Mutex<Option<OffsetPageTable<'static>>> = Mutex::new(None);
+static FRAMES: Mutex<Option<BootInfoFrameAllocator>> = Mutex::new(None);
+
+entry_point!(main);
+
+fn main(boot_info: &'static BootInfo) -> ! {
+    turiya::init();
+    let phys_mem_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    *MAPPER.lock() = Some(unsafe { memory::init(phys_mem_offset) });
+    *FRAMES.lock() = Some(unsafe { BootInfoFrameAllocator::init(&boot_info.memory_map) });
+
+    test_main();
+    loop {}
+}
+
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    turiya::test_panic_handler(info)
+}
+
+#[test_case]
+fn example_mapping_is_removed_on_drop() {
+    let mut mapper = MAPPER.lock();
+    let mapper = mapper.as_mut().unwrap();
+    let mut frames = FRAMES.lock();
+    let frames = frames.as_mut().unwrap();
+    let addr = VirtAddr::new(TEST_PAGE);
+
+    let mapped = memory::create_example_mapping(Page::containing_address(addr), mapper, frames);
+    assert!(mapped.is_ok());
+    drop(mapped);
+    assert_eq!(mapper.translate_addr(addr), None);
+}
+
+#[test_case]
+fn example_mapping_maps_vga_buffer() {
+    let mut mapper = MAPPER.lock();
+    let mapper = mapper.as_mut().unwrap();
+    let mut frames = FRAMES.lock();
+    let frames = frames.as_mut().unwrap();
+    // the last cell of the text buffer
+    let cell_offset = 2 * (BUFFER_WIDTH * BUFFER_HEIGHT - 1);
+
+    let mapped = memory::create_example_mapping(Page::containing_address(VirtAddr::new(TEST_PAGE)), mapper, frames)
+        .unwrap();
+    let through_mapping: *mut u16 = (mapped.page().start_address() + cell_offset as u64).as_mut_ptr();
+    // the bootloader identity maps the VGA buffer
+    let vga = (0xb8000 + cell_offset) as *const u16;
+    unsafe {
+        through_mapping.write_volatile(0x0f21);
+        assert_eq!(vga.read_volatile(), 0x0f21);
+    }
+}
+
+#[test_case]
+fn physical_range_maps_vga_buffer() {
+    let mut mapper = MAPPER.lock();
+    let mapper = mapper.as_mut().unwrap();
+    let mut frames = FRAMES.lock();
+    let frames = frames.as_mut().unwrap();
+    // the second to last cell of the text buffer
+    let phys = PhysAddr::new(0xb8000 + 2 * (BUFFER_WIDTH * BUFFER_HEIGHT - 2) as u64);
+
+    let flags = PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+    let virt = memory::map_physical_range(phys, 2, flags, mapper, frames).unwrap();
+    assert_eq!(mapper.translate_addr(virt), Some(phys));
+    unsafe {
+        virt.as_mut_ptr::<u16>().write_volatile(0x0f3f);
+        // the bootloader identity maps the VGA buffer
+        assert_eq!((phys.as_u64() as *const u16).read_volatile(), 0x0f3f);
+    }
+
+    // the next range does not overlap
+    let next = memory::map_physical_range(phys, 2, flags, mapper, frames).unwrap();
+    assert_ne!(next.align_down(4096u64), virt.align_down(4096u64));
+}
+
+#[test_case]
+fn physical_range_stops_before_kernel_stacks() {
+    let mut mapper = MAPPER.lock();
+    let mapper = mapper.as_mut().unwrap();
+    let mut frames = FRAMES.lock();
+    let frames = frames.as_mut().unwrap();
+
+    // larger than the whole MMIO range
+    let size = 2 << 30;
+    let flags = PageTableFlags::WRITABLE | PageTableFlags::NO_CACHE;
+    match memory::map_physical_range(PhysAddr::new(0xb8000), size, flags, mapper, frames) {
+        Err(MmioError::RegionExhausted { pages }) => assert_eq!(pages, size as u64 / 4096),
+        other => panic!("expected RegionExhausted, got {:?}", other),
+    }
+    // nothing was used up by the failed call
+    assert!(memory::map_physical_range(PhysAddr::new(0xb8000), 2, flags, mapper, frames).is_ok());
+}
+
+#[test_case]
+fn remaining_frames_counts_allocations() {
+    let mut frames = FRAMES.lock();
+    let frames = frames.as_mut().unwrap();
+    let remaining = frames.remaining_frames();
+    assert!(remaining > 0);
+    frames.allocate_frame().unwrap();
+    frames.allocate_contiguous(2).unwrap();
+    assert_eq!(frames.remaining_frames(), remaining - 3);
+}
+
+// hands out at most `limit` frames of the wrapped allocator
+struct LimitedFrames<'a> {
+    inner: &'a mut BootInfoFrameAllocator,
+    limit: usize,
+}
+
+unsafe impl FrameAllocator<Size4KiB> for LimitedFrames<'_> {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        self.limit = self.limit.checked_sub(1)?;
+        self.inner.allocate_frame()
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for LimitedFrames<'_> {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        self.inner.deallocate_frame(frame)
+    }
+}
+
+// never has contiguous frames, `map_page_range` maps page by page
+impl ContiguousFrameAllocator for LimitedFrames<'_> {
+    fn allocate_contiguous(&mut self, _count: usize) -> Option<PhysFrame> {
+        None
+    }
+}
+
+#[test_case]
+fn page_range_is_rolled_back_on_failure() {
+    let mut mapper = MAPPER.lock();
+    let mapper = mapper.as_mut().unwrap();
+    let mut frames = FRAMES.lock();
+    let frames = frames.as_mut().unwrap();
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+
+    // creates the page tables of the range
+    let first = VirtAddr::new(TEST_PAGE + 0x10_000);
+    memory::map_page_range(first, 1, flags, mapper, frames).unwrap();
+    let remaining = frames.remaining_frames();
+
+    // the third page of the range cannot be mapped
+    let start = first + 4096u64;
+    let mut limited = LimitedFrames { inner: frames, limit: 2 };
+    assert!(memory::map_page_range(start, 3, flags, mapper, &mut limited).is_err());
+    assert_eq!(frames.remaining_frames(), remaining);
+    for i in 0..3u64 {
+        assert_eq!(mapper.translate_addr(start + i * 4096), None);
+    }
+
+    let (frame, flush) = mapper.unmap(Page::<Size4KiB>::containing_address(first)).unwrap();
+    flush.flush();
+    unsafe { frames.deallocate_frame(frame) };
+}
+
+#[test_case]
+fn kernel_stack_is_rolled_back_on_failure() {
+    let mut mapper = MAPPER.lock();
+    let mapper = mapper.as_mut().unwrap();
+    let mut frames = FRAMES.lock();
+    let frames = frames.as_mut().unwrap();
+
+    // creates the page tables of the stack region
+    let first = KernelStack::<4096>::new(mapper, frames).unwrap();
+    let remaining = frames.remaining_frames();
+
+    // the third page of the stack cannot be mapped
+    let mut limited = LimitedFrames { inner: frames, limit: 2 };
+    assert!(KernelStack::<{ 3 * 4096 }>::new(mapper, &mut limited).is_err());
+    assert_eq!(frames.remaining_frames(), remaining);
+
+    // the failed stack used up neither its range nor its pages
+    let second = KernelStack::<4096>::new(mapper, frames).unwrap();
+    assert_eq!(second.bottom(), first.bottom() + 2 * 4096u64);
+    assert_eq!(mapper.translate_addr(second.bottom() + 4096u64), None);
+}
@@ -0,0 +1,140 @@
+//! Procedural macros for the turiya kernel.
+//!
+//! Only `proc_macro` itself is used so the crate builds without external dependencies.
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Registers a kernel test together with its metadata.
+///
+/// ```ignore
+/// #[kernel_test(timeout_ms = 5000, should_panic = false)]
+/// fn heap_allocations() { ... }
+/// ```
+///
+/// Both arguments are optional. The test is placed in the `kernel_tests` link section
+/// as a `turiya::testing::KernelTest`, where the test runner picks it up.
+/// Like `#[test_case]`, the function is only compiled for tests.
+#[proc_macro_attribute]
+pub fn kernel_test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = match parse_args(attr) {
+        Ok(args) => args,
+        Err((span, message)) => return compile_error(span, &message),
+    };
+    let name = match fn_name(&item) {
+        Some(name) => name,
+        None => return compile_error(Span::call_site(), "#[kernel_test] can only be applied to functions"),
+    };
+
+    let timeout = match args.timeout_ms {
+        Some(ms) => format!("::core::option::Option::Some({}u64)", ms),
+        None => String::from("::core::option::Option::None"),
+    };
+    let registration = format!(
+        "#[cfg(test)]
+        #[used]
+        #[link_section = \"kernel_tests\"]
+        static __KERNEL_TEST_{upper}: ::turiya::testing::KernelTest = ::turiya::testing::KernelTest {{
+            name: ::core::concat!(::core::module_path!(), \"::\", \"{name}\"),
+            test_fn: {name},
+            timeout_ms: {timeout},
+            should_panic: {should_panic},
+        }};",
+        upper = name.to_uppercase(),
+        name = name,
+        timeout = timeout,
+        should_panic = args.should_panic,
+    );
+
+    let mut output: TokenStream = "#[cfg(test)]".parse().unwrap();
+    output.extend(item);
+    output.extend(registration.parse::<TokenStream>().unwrap());
+    output
+}
+
+#[derive(Default)]
+struct Args {
+    timeout_ms: Option<u64>,
+    should_panic: bool,
+}
+
+// parses a comma separated list of `key = value` pairs
+fn parse_args(attr: TokenStream) -> Result<Args, (Span, String)> {
+    let mut args = Args::default();
+    let mut tokens = attr.into_iter().peekable();
+
+    while let Some(token) = tokens.next() {
+        let key = match token {
+            TokenTree::Ident(ident) => ident,
+            other => return Err((other.span(), String::from("expected an argument name"))),
+        };
+        match tokens.next() {
+            Some(TokenTree::Punct(punct)) if punct.as_char() == '=' => {}
+            _ => return Err((key.span(), format!("expected `=` after `{}`", key))),
+        }
+        let value = match tokens.next() {
+            Some(value) => value,
+            None => return Err((key.span(), format!("missing value for `{}`", key))),
+        };
+
+        match key.to_string().as_str() {
+            "timeout_ms" => {
+                let ms = value.to_string().trim_end_matches("u64").replace('_', "").parse();
+                match ms {
+                    Ok(ms) => args.timeout_ms = Some(ms),
+                    Err(_) => return Err((value.span(), String::from("expected an integer"))),
+                }
+            }
+            "should_panic" => match value.to_string().as_str() {
+                "true" => args.should_panic = true,
+                "false" => args.should_panic = false,
+                _ => return Err((value.span(), String::from("expected `true` or `false`"))),
+            },
+            other => return Err((key.span(), format!("unknown argument `{}`", other))),
+        }
+
+        match tokens.next() {
+            None => break,
+            Some(TokenTree::Punct(punct)) if punct.as_char() == ',' => {}
+            Some(other) => return Err((other.span(), String::from("expected `,`"))),
+        }
+    }
+    Ok(args)
+}
+
+// returns the identifier following the `fn` keyword
+fn fn_name(item: &TokenStream) -> Option<String> {
+    let mut tokens = item.clone().into_iter();
+    while let Some(token) = tokens.next() {
+        if let TokenTree::Ident(ident) = &token {
+            if ident.to_string() == "fn" {
+                return match tokens.next() {
+                    Some(TokenTree::Ident(name)) => Some(name.to_string()),
+                    _ => None,
+                };
+            }
+        }
+    }
+    None
+}
+
+// builds `::core::compile_error!("message");` pointing at `span`
+fn compile_error(span: Span, message: &str) -> TokenStream {
+    let tokens = [
+        TokenTree::Punct(Punct::new(':', Spacing::Joint)),
+        TokenTree::Punct(Punct::new(':', Spacing::Alone)),
+        TokenTree::Ident(Ident::new("core", span)),
+        TokenTree::Punct(Punct::new(':', Spacing::Joint)),
+        TokenTree::Punct(Punct::new(':', Spacing::Alone)),
+        TokenTree::Ident(Ident::new("compile_error", span)),
+        TokenTree::Punct(Punct::new('!', Spacing::Alone)),
+        TokenTree::Group(Group::new(
+            Delimiter::Parenthesis,
+            TokenTree::Literal(Literal::string(message)).into(),
+        )),
+        TokenTree::Punct(Punct::new(';', Spacing::Alone)),
+    ];
+    tokens.into_iter().map(|mut token| {
+        token.set_span(span);
+        token
+    }).collect()
+}